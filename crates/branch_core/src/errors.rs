@@ -1,3 +1,5 @@
+use crate::types::{NoteId, TaskState};
+
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum AddError {
     #[error("Note already exists")]
@@ -13,9 +15,27 @@ pub enum AddError {
 
     #[error("Branch does not exist")]
     BranchDoesNotExist,
+
+    #[error("Tag already exists")]
+    TagAlreadyExists,
+
+    #[error("Link would create the cycle {}", format_cycle(.0))]
+    CycleDetected(Vec<NoteId>),
+
+    #[error("Condition is not a valid expression")]
+    InvalidCondition,
+
+    #[error("Reason starting with `=` is not a valid expression")]
+    InvalidReason,
+
+    #[error("Relation needs a name and an inverse name")]
+    InvalidRelation,
+
+    #[error("Failed to write to the journal")]
+    JournalFailed,
 }
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, PartialEq)]
 pub enum ChangeError {
     #[error("Note does not exist")]
     NoteDoesNotExist,
@@ -25,9 +45,48 @@ pub enum ChangeError {
 
     #[error("Branch does not exist")]
     BranchDoesNotExist,
+
+    #[error("Tag does not exist")]
+    TagDoesNotExist,
+
+    #[error("Condition is not a valid expression")]
+    InvalidCondition,
+
+    #[error("Reason starting with `=` is not a valid expression")]
+    InvalidReason,
+
+    #[error("Revision does not exist")]
+    RevisionDoesNotExist,
+
+    #[error("Relation needs a name and an inverse name")]
+    InvalidRelation,
+
+    #[error("Weight is not a finite number")]
+    InvalidWeight,
+
+    #[error("Annotation needs a key")]
+    InvalidAnnotation,
+
+    #[error("Position is out of range")]
+    InvalidPosition,
+
+    #[error("State `{0}` is not configured")]
+    UnknownState(TaskState),
+
+    #[error("Note cannot move from `{from}` to `{to}`")]
+    InvalidTransition { from: TaskState, to: TaskState },
+
+    #[error("Note links to notes that are still open")]
+    OpenForwardlinks,
+
+    #[error("Removing a link would leave note {0} without backlinks")]
+    WouldOrphan(NoteId),
+
+    #[error("Failed to write to the journal")]
+    JournalFailed,
 }
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, PartialEq)]
 pub enum DeleteError {
     #[error("Note does not exist")]
     NoteDoesNotExist,
@@ -38,7 +97,168 @@ pub enum DeleteError {
     BranchDoesNotExist,
     #[error("Branch is not empty")]
     BranchNotEmpty,
+
+    #[error("Deletion would leave note {0} without backlinks")]
+    WouldOrphan(NoteId),
+
+    #[error("Tag does not exist")]
+    TagDoesNotExist,
+
+    #[error("Failed to write to the journal")]
+    JournalFailed,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TrashError {
+    #[error("Trash entry does not exist")]
+    EntryDoesNotExist,
+
+    #[error("Note already exists")]
+    NoteAlreadyExists,
+    #[error("Note does not exist")]
+    NoteDoesNotExist,
+
+    #[error("Branch does not exist")]
+    BranchDoesNotExist,
+
+    #[error("Restoring would create the cycle {}", format_cycle(.0))]
+    CycleDetected(Vec<NoteId>),
+
+    #[error("Failed to write to the journal")]
+    JournalFailed,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ReadError {
+    #[error("Note does not exist")]
+    NoteDoesNotExist,
+
+    #[error("Branch does not exist")]
+    BranchDoesNotExist,
+
+    #[error("Revision does not exist")]
+    RevisionDoesNotExist,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseIdError {
+    #[error("Id is empty")]
+    Empty,
+
+    #[error("Id contains an invalid character `{0}`")]
+    InvalidCharacter(char),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseTagError {
+    #[error("Tag is empty")]
+    Empty,
+
+    #[error("Tag contains an empty segment")]
+    EmptySegment,
+
+    #[error("Tag contains an invalid character `{0}`")]
+    InvalidCharacter(char),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum StorageError {
+    #[error("Failed to read from the storage")]
+    ReadFailed,
+
+    #[error("Failed to write to the storage")]
+    WriteFailed,
+
+    #[error("Stored note is malformed")]
+    MalformedNote,
+
+    #[error("Note already exists in the storage")]
+    DuplicateNote,
+
+    #[error("Stored document has an unsupported format `{0}`")]
+    UnsupportedFormat(String),
+
+    #[error("Stored document has an unsupported version {0}")]
+    UnsupportedVersion(u32),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum OperationError {
+    #[error("Failed to apply the operation")]
+    ApplyFailed,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum JournalError {
+    #[error("Failed to read the journal")]
+    ReadFailed,
+
+    #[error("Failed to write to the journal")]
+    WriteFailed,
+
+    #[error("Journal record {0} is corrupted")]
+    CorruptedRecord(usize),
+
+    #[error("Failed to replay journal record {0}")]
+    ReplayFailed(usize),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum HistoryError {
+    #[error("Nothing to undo")]
+    NothingToUndo,
+
+    #[error("Nothing to redo")]
+    NothingToRedo,
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum ReadError {}
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SearchError {
+    #[error("Query does not contain any words")]
+    EmptyQuery,
+
+    #[error("Query contains a phrase without a closing quote")]
+    UnterminatedPhrase,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SessionError {
+    #[error("Note does not exist")]
+    NoteDoesNotExist,
+
+    #[error("Branch is not open on the current note")]
+    BranchDoesNotExist,
+
+    #[error("Option is not part of the branch")]
+    OptionDoesNotExist,
+
+    #[error("Branch was already decided in this session")]
+    BranchAlreadyDecided,
+
+    #[error("No choice to take back")]
+    NothingToBacktrack,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ExpressionError {
+    #[error("Syntax error at offset {0}")]
+    Syntax(usize),
+
+    #[error("Unknown variable {0}")]
+    UnknownVariable(String),
+
+    #[error("Operands have incompatible types")]
+    TypeMismatch,
+
+    #[error("Note does not exist")]
+    NoteDoesNotExist,
+}
+
+fn format_cycle(cycle: &[NoteId]) -> String {
+    cycle
+        .iter()
+        .chain(cycle.first())
+        .map(NoteId::as_str)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
@@ -0,0 +1,728 @@
+//!
+//! The expression module implements a small language for branch conditions and option guards.
+//! A condition or a reason starting with `=` is an expression, anything else stays free-form text.
+//!
+//! ```text
+//! = budget > 5000 && team_size < 3
+//! = tier == "gold" || !trial
+//! ```
+//!
+//! Expressions support numbers, strings, `true` and `false`, named variables, the arithmetic
+//! operators `+ - * /`, the comparisons `== != < <= > >=`, and `&& || !` with parentheses.
+//!
+//! A branch is resolved against a set of inputs by comparing the value of its condition with the
+//! guards of its options, the first option whose guard has the same value is picked. A free-form
+//! condition counts as `true`, so guards can be plain predicates. Options without a guard are only
+//! picked when no guarded option matches.
+//!
+//! ```rust
+//! use branch_core::expression::{resolve, Inputs};
+//! use branch_core::prelude::*;
+//!
+//! let mut manager = NotesManager::default();
+//! let project = manager
+//!     .add_note(Note::new("project".to_string(), None, String::new()))
+//!     .unwrap();
+//! let hire = manager
+//!     .add_note(Note::new("hire".to_string(), None, String::new()))
+//!     .unwrap();
+//! let wait = manager
+//!     .add_note(Note::new("wait".to_string(), None, String::new()))
+//!     .unwrap();
+//! let branch = manager
+//!     .create_branching(project.clone(), "= budget > 5000 && team_size < 3".to_string())
+//!     .unwrap();
+//! manager.add_branch(project.clone(), branch.clone(), hire.clone(), "= true".to_string()).unwrap();
+//! manager.add_branch(project.clone(), branch.clone(), wait.clone(), "otherwise".to_string()).unwrap();
+//!
+//! let inputs = Inputs::from([
+//!     ("budget".to_string(), 8000.0.into()),
+//!     ("team_size".to_string(), 2.0.into()),
+//! ]);
+//!
+//! let resolutions = resolve(&manager, project, &inputs).unwrap();
+//! assert_eq!(resolutions[0].option, Some(hire));
+//! ```
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use error_stack::{ensure, Report, Result};
+
+use crate::errors::ExpressionError;
+use crate::manager_impl::ReadNote;
+use crate::types::{Branch, BranchId, FLink, NoteId};
+
+///
+/// [`PREFIX`] marks a condition or a reason as an expression.
+///
+pub const PREFIX: char = '=';
+
+///
+/// [`Value`] is the result of an expression, and the type of the inputs.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+///
+/// [`Inputs`] binds the variables of the expressions to values.
+///
+pub type Inputs = HashMap<String, Value>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+///
+/// [`Expression`] is a parsed condition or guard.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Literal(Value),
+    Variable(String),
+    Not(Box<Expression>),
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+///
+/// [`Resolution`] is the option a branch resolves to for a set of inputs, `None` when no option
+/// matches.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolution {
+    pub note: NoteId,
+    pub branch: BranchId,
+    pub option: Option<NoteId>,
+}
+
+const SYMBOLS: [&str; 15] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "(", ")",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(offset, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = offset;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            let number = source[offset..end]
+                .parse()
+                .map_err(|_| Report::new(ExpressionError::Syntax(offset)))?;
+            tokens.push((offset, Token::Number(number)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = offset;
+            while let Some(&(index, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = index + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((offset, Token::Identifier(source[offset..end].to_string())));
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => text.push(c),
+                        None => return Err(Report::new(ExpressionError::Syntax(offset))),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(Report::new(ExpressionError::Syntax(offset))),
+                }
+            }
+            tokens.push((offset, Token::Text(text)));
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| source[offset..].starts_with(*symbol))
+                .ok_or(ExpressionError::Syntax(offset))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push((offset, Token::Symbol(symbol)));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map(|(offset, _)| *offset)
+            .unwrap_or(self.end)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found =
+            matches!(self.tokens.get(self.position), Some((_, Token::Symbol(s))) if *s == symbol);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_any(&mut self, operators: &[(&str, Operator)]) -> Option<Operator> {
+        operators
+            .iter()
+            .find(|(symbol, _)| self.eat(symbol))
+            .map(|(_, operator)| *operator)
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[(&str, Operator)],
+        operand: fn(&mut Self) -> Result<Expression, ExpressionError>,
+    ) -> Result<Expression, ExpressionError> {
+        let mut left = operand(self)?;
+
+        while let Some(operator) = self.eat_any(operators) {
+            let right = operand(self)?;
+            left = Expression::Binary(Box::new(left), operator, Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expression, ExpressionError> {
+        self.binary(&[("||", Operator::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expression, ExpressionError> {
+        self.binary(&[("&&", Operator::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, ExpressionError> {
+        let left = self.sum()?;
+
+        let operator = self.eat_any(&[
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ]);
+
+        match operator {
+            Some(operator) => Ok(Expression::Binary(
+                Box::new(left),
+                operator,
+                Box::new(self.sum()?),
+            )),
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, ExpressionError> {
+        self.binary(
+            &[("+", Operator::Add), ("-", Operator::Subtract)],
+            Self::product,
+        )
+    }
+
+    fn product(&mut self) -> Result<Expression, ExpressionError> {
+        self.binary(
+            &[("*", Operator::Multiply), ("/", Operator::Divide)],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expression, ExpressionError> {
+        if self.eat("!") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expression, ExpressionError> {
+        let offset = self.offset();
+
+        if self.eat("(") {
+            let expression = self.or()?;
+            ensure!(self.eat(")"), ExpressionError::Syntax(self.offset()));
+            return Ok(expression);
+        }
+
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(_, token)| token.clone());
+        self.position += 1;
+
+        match token {
+            Some(Token::Number(number)) => Ok(Expression::Literal(Value::Number(number))),
+            Some(Token::Text(text)) => Ok(Expression::Literal(Value::Text(text))),
+            Some(Token::Identifier(name)) => Ok(match name.as_str() {
+                "true" => Expression::Literal(Value::Bool(true)),
+                "false" => Expression::Literal(Value::Bool(false)),
+                _ => Expression::Variable(name),
+            }),
+            _ => Err(Report::new(ExpressionError::Syntax(offset))),
+        }
+    }
+}
+
+impl Expression {
+    ///
+    /// [`parse`] parses an expression, without the leading `=`.
+    ///
+    pub fn parse(source: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            end: source.len(),
+        };
+
+        let expression = parser.or()?;
+
+        ensure!(
+            parser.position == parser.tokens.len(),
+            ExpressionError::Syntax(parser.offset())
+        );
+
+        Ok(expression)
+    }
+
+    ///
+    /// [`from_text`] parses a condition or a reason if it is an expression, free-form text gives
+    /// `None`.
+    ///
+    pub fn from_text(text: &str) -> Option<Result<Self, ExpressionError>> {
+        text.trim_start().strip_prefix(PREFIX).map(|source| {
+            Self::parse(source).map_err(|report| report.attach_printable(text.to_string()))
+        })
+    }
+
+    pub fn evaluate(&self, inputs: &Inputs) -> Result<Value, ExpressionError> {
+        match self {
+            Expression::Literal(value) => Ok(value.clone()),
+            Expression::Variable(name) => Ok(inputs
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ExpressionError::UnknownVariable(name.clone()))?),
+            Expression::Not(operand) => match operand.evaluate(inputs)? {
+                Value::Bool(value) => Ok(Value::Bool(!value)),
+                _ => Err(Report::new(ExpressionError::TypeMismatch)),
+            },
+            Expression::Negate(operand) => match operand.evaluate(inputs)? {
+                Value::Number(value) => Ok(Value::Number(-value)),
+                _ => Err(Report::new(ExpressionError::TypeMismatch)),
+            },
+            Expression::Binary(left, Operator::And, right) => {
+                Ok(Value::Bool(left.boolean(inputs)? && right.boolean(inputs)?))
+            }
+            Expression::Binary(left, Operator::Or, right) => {
+                Ok(Value::Bool(left.boolean(inputs)? || right.boolean(inputs)?))
+            }
+            Expression::Binary(left, operator, right) => {
+                apply(left.evaluate(inputs)?, *operator, right.evaluate(inputs)?)
+            }
+        }
+    }
+
+    fn boolean(&self, inputs: &Inputs) -> Result<bool, ExpressionError> {
+        match self.evaluate(inputs)? {
+            Value::Bool(value) => Ok(value),
+            _ => Err(Report::new(ExpressionError::TypeMismatch)),
+        }
+    }
+}
+
+fn apply(left: Value, operator: Operator, right: Value) -> Result<Value, ExpressionError> {
+    use std::cmp::Ordering;
+
+    let ordering = match (&left, &right) {
+        (Value::Number(left), Value::Number(right)) => left.partial_cmp(right),
+        (Value::Text(left), Value::Text(right)) => Some(left.cmp(right)),
+        (Value::Bool(left), Value::Bool(right)) => Some(left.cmp(right)),
+        _ => None,
+    };
+
+    let compare = |accept: fn(Ordering) -> bool| match ordering {
+        Some(ordering) => Ok(Value::Bool(accept(ordering))),
+        None => Err(Report::new(ExpressionError::TypeMismatch)),
+    };
+
+    match (operator, left, right) {
+        (Operator::Equal, left, right) => Ok(Value::Bool(left == right)),
+        (Operator::NotEqual, left, right) => Ok(Value::Bool(left != right)),
+        (Operator::Less, _, _) => compare(Ordering::is_lt),
+        (Operator::LessEqual, _, _) => compare(Ordering::is_le),
+        (Operator::Greater, _, _) => compare(Ordering::is_gt),
+        (Operator::GreaterEqual, _, _) => compare(Ordering::is_ge),
+        (Operator::Add, Value::Text(left), Value::Text(right)) => Ok(Value::Text(left + &right)),
+        (operator, Value::Number(left), Value::Number(right)) => {
+            Ok(Value::Number(match operator {
+                Operator::Add => left + right,
+                Operator::Subtract => left - right,
+                Operator::Multiply => left * right,
+                _ => left / right,
+            }))
+        }
+        _ => Err(Report::new(ExpressionError::TypeMismatch)),
+    }
+}
+
+///
+/// [`validate`] checks that a condition or a reason parses, free-form text is always valid.
+///
+pub(crate) fn validate(text: &str) -> Result<(), ExpressionError> {
+    Expression::from_text(text).transpose().map(|_| ())
+}
+
+///
+/// [`resolve_branch`] picks the option of a branch for the given inputs. A nested branch has no
+/// guard, it is picked like an option without one and resolved in turn.
+///
+pub fn resolve_branch(branch: &Branch, inputs: &Inputs) -> Result<Option<NoteId>, ExpressionError> {
+    let subject = match Expression::from_text(&branch.condition) {
+        Some(expression) => expression?.evaluate(inputs)?,
+        None => Value::Bool(true),
+    };
+
+    let mut fallback = None;
+
+    for option in &branch.branches {
+        match option {
+            FLink::Link(link) => match Expression::from_text(&link.reason) {
+                Some(guard) => {
+                    if guard?.evaluate(inputs)? == subject {
+                        return Ok(Some(link.id.clone()));
+                    }
+                }
+                None => fallback = fallback.or(Some(option)),
+            },
+            FLink::Branch(_) => fallback = fallback.or(Some(option)),
+        }
+    }
+
+    match fallback {
+        Some(FLink::Link(link)) => Ok(Some(link.id.clone())),
+        Some(FLink::Branch(sub_branch)) => resolve_branch(sub_branch, inputs),
+        None => Ok(None),
+    }
+}
+
+///
+/// [`resolve`] evaluates the decision tree starting at a note. Every branch that is reached is
+/// resolved, and the walk continues through the options they resolve to. Branches are listed in
+/// the order they are reached.
+///
+pub fn resolve<R: ReadNote + ?Sized>(
+    reader: &R,
+    start: NoteId,
+    inputs: &Inputs,
+) -> Result<Vec<Resolution>, ExpressionError> {
+    let mut resolutions = Vec::new();
+    let mut visited = HashSet::from([start.clone()]);
+    let mut stack = vec![start];
+
+    while let Some(note_id) = stack.pop() {
+        let note = reader
+            .read_note(note_id.clone())
+            .map_err(|report| report.change_context(ExpressionError::NoteDoesNotExist))?;
+
+        let mut next = Vec::new();
+
+        for flink in &note.forwardlinks {
+            if let FLink::Branch(branch) = flink {
+                let option = resolve_branch(branch, inputs)?;
+
+                if let Some(option) = &option {
+                    if visited.insert(option.clone()) {
+                        next.push(option.clone());
+                    }
+                }
+
+                resolutions.push(Resolution {
+                    note: note_id.clone(),
+                    branch: branch.get_id(),
+                    option,
+                });
+            }
+        }
+
+        stack.extend(next.into_iter().rev());
+    }
+
+    Ok(resolutions)
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(value) => write!(f, "{}", value),
+            Value::Text(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{AddError, ChangeError};
+    use crate::manager_impl::{AddBranch, AddNote, ChangeBranch};
+    use crate::prelude::NotesManager;
+    use crate::test_util::{id, note};
+
+    fn evaluate(source: &str, inputs: &Inputs) -> Value {
+        Expression::parse(source).unwrap().evaluate(inputs).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let inputs = Inputs::from([
+            ("budget".to_string(), 8000.0.into()),
+            ("team_size".to_string(), 4.0.into()),
+            ("tier".to_string(), "gold".into()),
+            ("trial".to_string(), false.into()),
+        ]);
+
+        assert_eq!(
+            evaluate("budget > 5000 && team_size < 3", &inputs),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            evaluate("budget > 5000 && (team_size < 3 || !trial)", &inputs),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate("budget / team_size - 1 * 2", &inputs),
+            Value::Number(1998.0)
+        );
+        assert_eq!(evaluate("-(1 + 2) * 3", &inputs), Value::Number(-9.0));
+        assert_eq!(
+            evaluate(r#"tier == "gold" && tier + "!" != "gold""#, &inputs),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            Expression::parse("seats > 2")
+                .unwrap()
+                .evaluate(&inputs)
+                .unwrap_err()
+                .current_context(),
+            &ExpressionError::UnknownVariable("seats".to_string())
+        );
+        assert_eq!(
+            Expression::parse("tier > 2")
+                .unwrap()
+                .evaluate(&inputs)
+                .unwrap_err()
+                .current_context(),
+            &ExpressionError::TypeMismatch
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (source, offset) in [
+            ("budget >", 8),
+            ("(a && b", 7),
+            ("a = b", 2),
+            ("a b", 2),
+            (r#""open"#, 0),
+            ("a $ b", 2),
+            ("a = b", 2),
+        ] {
+            assert_eq!(
+                Expression::parse(source).unwrap_err().current_context(),
+                &ExpressionError::Syntax(offset),
+                "{}",
+                source
+            );
+        }
+
+        assert!(Expression::from_text("free-form (text").is_none());
+        assert!(Expression::from_text(" = a && b").unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_conditions_are_rejected() {
+        let mut manager = NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+
+        assert_eq!(
+            manager
+                .create_branching(id("a"), "= budget >".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::InvalidCondition
+        );
+
+        let branch = manager
+            .create_branching(id("a"), "= budget > 1".to_string())
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .add_branch(id("a"), branch.clone(), id("b"), "=> fallback".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::InvalidReason
+        );
+
+        manager
+            .add_branch(id("a"), branch.clone(), id("b"), "= true".to_string())
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .change_branch_condition(id("a"), branch.clone(), "= &&".to_string())
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidCondition
+        );
+        assert_eq!(
+            manager
+                .change_branch_reason(id("a"), branch, id("b"), "= ==".to_string())
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidReason
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut manager = NotesManager::default();
+        for note_id in ["a", "b", "c", "d", "e"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        let size = manager
+            .create_branching(id("a"), "= team_size".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), size.clone(), id("b"), "= 1".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), size.clone(), id("c"), "larger teams".to_string())
+            .unwrap();
+        let budget = manager
+            .create_branching(id("b"), "is it affordable".to_string())
+            .unwrap();
+        manager
+            .add_branch(
+                id("b"),
+                budget.clone(),
+                id("d"),
+                "= budget > 100".to_string(),
+            )
+            .unwrap();
+        manager
+            .add_branch(
+                id("b"),
+                budget.clone(),
+                id("e"),
+                "= budget <= 100".to_string(),
+            )
+            .unwrap();
+
+        let inputs = Inputs::from([
+            ("team_size".to_string(), 1.0.into()),
+            ("budget".to_string(), 50.0.into()),
+        ]);
+
+        assert_eq!(
+            resolve(&manager, id("a"), &inputs).unwrap(),
+            vec![
+                Resolution {
+                    note: id("a"),
+                    branch: size.clone(),
+                    option: Some(id("b")),
+                },
+                Resolution {
+                    note: id("b"),
+                    branch: budget,
+                    option: Some(id("e")),
+                },
+            ]
+        );
+
+        let inputs = Inputs::from([("team_size".to_string(), 5.0.into())]);
+
+        assert_eq!(
+            resolve(&manager, id("a"), &inputs).unwrap(),
+            vec![Resolution {
+                note: id("a"),
+                branch: size,
+                option: Some(id("c")),
+            }]
+        );
+    }
+}
@@ -0,0 +1,1165 @@
+//!
+//! The journal module records every mutation made on a [`NotesManager`] as an [`Operation`] in
+//! an append-only file, one JSON record per line. Replaying the file rebuilds the manager, which
+//! gives crash recovery and an audit trail of how the graph evolved.
+//!
+//! ```rust
+//! use branch_core::journal::JournaledManager;
+//! use branch_core::prelude::*;
+//!
+//! let directory = tempfile::tempdir().unwrap();
+//! let path = directory.path().join("journal.jsonl");
+//!
+//! let mut manager = JournaledManager::open(&path).unwrap();
+//! let note = manager
+//!     .add_note(Note::new("title".to_string(), None, "body".to_string()))
+//!     .unwrap();
+//! manager.change_note_title(note.clone(), "renamed".to_string()).unwrap();
+//! drop(manager);
+//!
+//! let manager = JournaledManager::open(&path).unwrap();
+//! assert_eq!(manager.read_note(note).unwrap().title, "renamed");
+//! ```
+//!
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use error_stack::{Context, Report, Result, ResultExt};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::{AddError, ChangeError, DeleteError, JournalError, ReadError, TrashError};
+use crate::manager_impl::{
+    AddBranch, AddLink, AddNote, AddTag, ChangeBranch, ChangeLink, ChangeNote, ChangeTag,
+    DeleteBranch, DeleteLink, DeleteNote, DeleteTag, ReadBranch, ReadLink, ReadNote, ReadTag,
+    Trash,
+};
+use crate::operation::Operation;
+use crate::prelude::NotesManager;
+use crate::types::{
+    BranchId, DateQuery, DeletionPolicy, DeletionReport, FLink, FLinkId, Link, Note, NoteId,
+    NoteRevision, ReconsileReport, Relation, RevisionDiff, SortKey, Tag, TaskState, TaskStates,
+    TrashEntry, TrashId,
+};
+
+///
+/// [`JournaledManager`] wraps a [`NotesManager`] and appends every successful mutation to the
+/// journal file. Each record is flushed and synced before the call returns.
+///
+pub struct JournaledManager {
+    manager: NotesManager,
+    path: PathBuf,
+    file: File,
+}
+
+///
+/// [`replay`] rebuilds a manager from the records in the journal file. A missing file is an empty
+/// journal.
+///
+/// A final record that was cut short by a crash is ignored, any other unreadable record is
+/// reported as [`JournalError::CorruptedRecord`].
+///
+pub fn replay(path: impl AsRef<Path>) -> Result<NotesManager, JournalError> {
+    apply_all(read_records(path.as_ref())?.0)
+}
+
+///
+/// [`Record`] is a line of the journal, an operation along with the time it was applied at.
+/// Replaying it at that time stamps the notes the same way.
+///
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Record {
+    at: OffsetDateTime,
+    #[serde(flatten)]
+    operation: Operation,
+}
+
+fn apply_all(records: Vec<Record>) -> Result<NotesManager, JournalError> {
+    let mut manager = NotesManager::default();
+
+    for (index, record) in records.into_iter().enumerate() {
+        manager.pin_clock(Some(record.at));
+
+        record
+            .operation
+            .apply(&mut manager)
+            .change_context(JournalError::ReplayFailed(index + 1))?;
+    }
+
+    manager.pin_clock(None);
+
+    Ok(manager)
+}
+
+///
+/// [`read_operations`] reads every complete record in the journal file.
+///
+pub fn read_operations(path: impl AsRef<Path>) -> Result<Vec<Operation>, JournalError> {
+    read_records(path.as_ref())
+        .map(|(records, _)| records.into_iter().map(|record| record.operation).collect())
+}
+
+///
+/// [`read_records`] reads the records in the journal file, along with the length of the file up
+/// to the end of the last complete record.
+///
+fn read_records(path: &Path) -> Result<(Vec<Record>, u64), JournalError> {
+    if !path.exists() {
+        return Ok((Vec::new(), 0));
+    }
+
+    let file = File::open(path)
+        .change_context(JournalError::ReadFailed)
+        .attach_printable_lazy(|| path.display().to_string())?;
+
+    let mut reader = BufReader::new(file);
+    let mut records = Vec::new();
+    let mut line = String::new();
+    let mut length = 0;
+
+    for index in 1.. {
+        line.clear();
+
+        let read = reader
+            .read_line(&mut line)
+            .change_context(JournalError::ReadFailed)?;
+
+        if read == 0 {
+            break;
+        }
+
+        if !line.trim().is_empty() {
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) => records.push(record),
+                Err(_) if !line.ends_with('\n') => break,
+                Err(error) => {
+                    return Err(
+                        Report::new(error).change_context(JournalError::CorruptedRecord(index))
+                    )
+                }
+            }
+        }
+
+        length += read as u64;
+    }
+
+    Ok((records, length))
+}
+
+impl JournaledManager {
+    ///
+    /// [`open`] replays the journal at the given path and opens it for appending new records.
+    ///
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, JournalError> {
+        let path = path.into();
+
+        let (records, length) = read_records(&path)?;
+        let manager = apply_all(records)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .change_context(JournalError::WriteFailed)
+            .attach_printable_lazy(|| path.display().to_string())?;
+
+        // A record that was cut short by a crash is dropped, so the next record starts on a line
+        // of its own.
+        file.set_len(length)
+            .change_context(JournalError::WriteFailed)?;
+
+        Ok(JournaledManager {
+            manager,
+            path,
+            file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn manager(&self) -> &NotesManager {
+        &self.manager
+    }
+
+    pub fn into_inner(self) -> NotesManager {
+        self.manager
+    }
+
+    ///
+    /// [`set_deletion_policy`] is recorded like any other operation, since the policy decides what
+    /// the deletions that follow remove when they are replayed.
+    ///
+    pub fn set_deletion_policy(&mut self, policy: DeletionPolicy) -> Result<(), JournalError> {
+        self.configure(Operation::SetDeletionPolicy { policy }, |manager| {
+            manager.set_deletion_policy(policy)
+        })
+    }
+
+    pub fn deletion_policy(&self) -> DeletionPolicy {
+        self.manager.deletion_policy()
+    }
+
+    ///
+    /// [`set_task_states`] is recorded as well, the moves between states that follow are replayed
+    /// with the states that were set when they were made.
+    ///
+    pub fn set_task_states(&mut self, states: TaskStates) -> Result<(), JournalError> {
+        self.configure(
+            Operation::SetTaskStates {
+                states: states.clone(),
+            },
+            |manager| manager.set_task_states(states),
+        )
+    }
+
+    pub fn task_states(&self) -> &TaskStates {
+        self.manager.task_states()
+    }
+
+    pub fn set_acyclic(&mut self, acyclic: bool) -> Result<(), JournalError> {
+        self.configure(Operation::SetAcyclic { acyclic }, |manager| {
+            manager.set_acyclic(acyclic)
+        })
+    }
+
+    pub fn is_acyclic(&self) -> bool {
+        self.manager.is_acyclic()
+    }
+
+    pub fn set_revision_retention(&mut self, retention: usize) -> Result<(), JournalError> {
+        self.configure(Operation::SetRevisionRetention { retention }, |manager| {
+            manager.set_revision_retention(retention)
+        })
+    }
+
+    pub fn revision_retention(&self) -> usize {
+        self.manager.revision_retention()
+    }
+
+    pub fn set_trash_retention(&mut self, retention: Option<Duration>) -> Result<(), JournalError> {
+        self.configure(Operation::SetTrashRetention { retention }, |manager| {
+            manager.set_trash_retention(retention)
+        })
+    }
+
+    pub fn trash_retention(&self) -> Option<Duration> {
+        self.manager.trash_retention()
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) -> Result<(), JournalError> {
+        self.configure(Operation::SetHistoryDepth { depth }, |manager| {
+            manager.set_history_depth(depth)
+        })
+    }
+
+    pub fn history_depth(&self) -> usize {
+        self.manager.history_depth()
+    }
+
+    ///
+    /// [`preview_deletion`] runs a deletion on the wrapped manager without applying it, nothing is
+    /// recorded.
+    ///
+    pub fn preview_deletion(
+        &mut self,
+        deletion: impl FnOnce(&mut NotesManager) -> Result<(), DeleteError>,
+    ) -> Result<DeletionReport, DeleteError> {
+        self.manager.preview_deletion(deletion)
+    }
+
+    ///
+    /// [`journaled`] runs a mutation on the wrapped manager and records the operation it returns,
+    /// both in the same transaction. If the record can not be written the mutation is reverted,
+    /// so the manager never gets ahead of its journal.
+    ///
+    fn journaled<T, C: Context>(
+        &mut self,
+        failed: C,
+        mutation: impl FnOnce(&mut NotesManager) -> Result<(T, Operation), C>,
+    ) -> Result<T, C> {
+        let JournaledManager {
+            manager,
+            path,
+            file,
+        } = self;
+
+        manager.transaction(|manager| {
+            let (output, operation) = mutation(manager)?;
+            record(file, path, manager.transaction_time(), operation).change_context(failed)?;
+            Ok(output)
+        })
+    }
+
+    ///
+    /// [`configure`] records a change to the settings of the manager before making it, a setting
+    /// can not be reverted like the notes are.
+    ///
+    fn configure(
+        &mut self,
+        operation: Operation,
+        apply: impl FnOnce(&mut NotesManager),
+    ) -> Result<(), JournalError> {
+        let JournaledManager {
+            manager,
+            path,
+            file,
+        } = self;
+
+        manager.transaction(|manager| {
+            record(file, path, manager.transaction_time(), operation)?;
+            apply(manager);
+            Ok(())
+        })
+    }
+}
+
+///
+/// [`record`] appends an operation to the journal file, at the time of the transaction it runs
+/// in.
+///
+fn record(
+    file: &mut File,
+    path: &Path,
+    at: OffsetDateTime,
+    operation: Operation,
+) -> Result<(), JournalError> {
+    let record = Record { at, operation };
+
+    let mut line = serde_json::to_string(&record).change_context(JournalError::WriteFailed)?;
+    line.push('\n');
+
+    file.write_all(line.as_bytes())
+        .and_then(|_| file.sync_data())
+        .change_context(JournalError::WriteFailed)
+        .attach_printable_lazy(|| path.display().to_string())
+}
+
+impl AddNote for JournaledManager {
+    fn add_note(&mut self, note: Note) -> Result<NoteId, AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            let operation = Operation::AddNote { note: note.clone() };
+            let note_id = manager.add_note(note)?;
+            Ok((note_id, operation))
+        })
+    }
+}
+
+impl AddLink for JournaledManager {
+    fn add_link(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    ) -> Result<(), AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            manager.add_link(from_note.clone(), to_note.clone(), reason.clone())?;
+            Ok((
+                (),
+                Operation::AddLink {
+                    from_note,
+                    to_note,
+                    reason,
+                },
+            ))
+        })
+    }
+
+    fn add_typed_link(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Relation,
+        reason: String,
+    ) -> Result<(), AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            manager.add_typed_link(
+                from_note.clone(),
+                to_note.clone(),
+                relation.clone(),
+                reason.clone(),
+            )?;
+            Ok((
+                (),
+                Operation::AddTypedLink {
+                    from_note,
+                    to_note,
+                    relation,
+                    reason,
+                },
+            ))
+        })
+    }
+
+    fn add_bidirectional_link(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    ) -> Result<(), AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            manager.add_bidirectional_link(from_note.clone(), to_note.clone(), reason.clone())?;
+            Ok((
+                (),
+                Operation::AddBidirectionalLink {
+                    from_note,
+                    to_note,
+                    reason,
+                },
+            ))
+        })
+    }
+}
+
+impl AddBranch for JournaledManager {
+    fn create_branching(&mut self, note: NoteId, condition: String) -> Result<BranchId, AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            let branch = manager.create_branching(note.clone(), condition.clone())?;
+            Ok((
+                branch.clone(),
+                Operation::CreateBranching {
+                    note,
+                    branch,
+                    condition,
+                },
+            ))
+        })
+    }
+
+    fn create_sub_branching(
+        &mut self,
+        note: NoteId,
+        on_branch: BranchId,
+        condition: String,
+    ) -> Result<BranchId, AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            let branch =
+                manager.create_sub_branching(note.clone(), on_branch.clone(), condition.clone())?;
+            Ok((
+                branch.clone(),
+                Operation::CreateSubBranching {
+                    note,
+                    on_branch,
+                    branch,
+                    condition,
+                },
+            ))
+        })
+    }
+
+    fn add_branch(
+        &mut self,
+        note: NoteId,
+        on_branch: BranchId,
+        link_note: NoteId,
+        reason: String,
+    ) -> Result<(), AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            manager.add_branch(
+                note.clone(),
+                on_branch.clone(),
+                link_note.clone(),
+                reason.clone(),
+            )?;
+            Ok((
+                (),
+                Operation::AddBranch {
+                    note,
+                    on_branch,
+                    link_note,
+                    reason,
+                },
+            ))
+        })
+    }
+}
+
+impl AddTag for JournaledManager {
+    fn add_tag(&mut self, note: NoteId, tag: Tag) -> Result<(), AddError> {
+        self.journaled(AddError::JournalFailed, |manager| {
+            manager.add_tag(note.clone(), tag.clone())?;
+            Ok(((), Operation::AddTag { note, tag }))
+        })
+    }
+}
+
+impl ChangeNote for JournaledManager {
+    fn change_note_title(&mut self, note: NoteId, title: String) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_note_title(note.clone(), title.clone())?;
+            Ok(((), Operation::ChangeNoteTitle { note, title }))
+        })
+    }
+
+    fn change_note_subtitle(&mut self, note: NoteId, subtitle: String) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_note_subtitle(note.clone(), subtitle.clone())?;
+            Ok(((), Operation::ChangeNoteSubtitle { note, subtitle }))
+        })
+    }
+
+    fn change_note_body(&mut self, note: NoteId, body: String) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_note_body(note.clone(), body.clone())?;
+            Ok(((), Operation::ChangeNoteBody { note, body }))
+        })
+    }
+
+    fn change_note_state(&mut self, note: NoteId, state: TaskState) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_note_state(note.clone(), state.clone())?;
+            Ok(((), Operation::ChangeNoteState { note, state }))
+        })
+    }
+
+    fn mark_note(&mut self, note: NoteId) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.mark_note(note.clone())?;
+            Ok(((), Operation::MarkNote { note }))
+        })
+    }
+
+    fn unmark_note(&mut self, note: NoteId) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.unmark_note(note.clone())?;
+            Ok(((), Operation::UnmarkNote { note }))
+        })
+    }
+
+    fn reconsile_nodes(&mut self) -> Result<ReconsileReport, ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            let report = manager.reconsile_nodes()?;
+            Ok((report, Operation::ReconsileNodes))
+        })
+    }
+
+    fn restore_revision(&mut self, note: NoteId, revision: usize) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.restore_revision(note.clone(), revision)?;
+            Ok(((), Operation::RestoreRevision { note, revision }))
+        })
+    }
+}
+
+impl ChangeLink for JournaledManager {
+    fn change_link_reason(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_link_reason(from_note.clone(), to_note.clone(), reason.clone())?;
+            Ok((
+                (),
+                Operation::ChangeLinkReason {
+                    from_note,
+                    to_note,
+                    reason,
+                },
+            ))
+        })
+    }
+
+    fn change_link_relation(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Option<Relation>,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_link_relation(from_note.clone(), to_note.clone(), relation.clone())?;
+            Ok((
+                (),
+                Operation::ChangeLinkRelation {
+                    from_note,
+                    to_note,
+                    relation,
+                },
+            ))
+        })
+    }
+
+    fn change_link_weight(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        weight: Option<f64>,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_link_weight(from_note.clone(), to_note.clone(), weight)?;
+            Ok((
+                (),
+                Operation::ChangeLinkWeight {
+                    from_note,
+                    to_note,
+                    weight,
+                },
+            ))
+        })
+    }
+
+    fn change_link_annotation(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        key: String,
+        value: Option<String>,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_link_annotation(
+                from_note.clone(),
+                to_note.clone(),
+                key.clone(),
+                value.clone(),
+            )?;
+            Ok((
+                (),
+                Operation::ChangeLinkAnnotation {
+                    from_note,
+                    to_note,
+                    key,
+                    value,
+                },
+            ))
+        })
+    }
+
+    fn move_forwardlink(
+        &mut self,
+        note: NoteId,
+        item: FLinkId,
+        position: usize,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.move_forwardlink(note.clone(), item.clone(), position)?;
+            Ok((
+                (),
+                Operation::MoveForwardlink {
+                    note,
+                    item,
+                    position,
+                },
+            ))
+        })
+    }
+
+    fn swap_forwardlinks(
+        &mut self,
+        note: NoteId,
+        first: FLinkId,
+        second: FLinkId,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.swap_forwardlinks(note.clone(), first.clone(), second.clone())?;
+            Ok((
+                (),
+                Operation::SwapForwardlinks {
+                    note,
+                    first,
+                    second,
+                },
+            ))
+        })
+    }
+
+    fn sort_forwardlinks(&mut self, note: NoteId, key: SortKey) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.sort_forwardlinks(note.clone(), key)?;
+            Ok(((), Operation::SortForwardlinks { note, key }))
+        })
+    }
+}
+
+impl ChangeBranch for JournaledManager {
+    fn change_branch_condition(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        condition: String,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_branch_condition(note.clone(), branch.clone(), condition.clone())?;
+            Ok((
+                (),
+                Operation::ChangeBranchCondition {
+                    note,
+                    branch,
+                    condition,
+                },
+            ))
+        })
+    }
+
+    fn change_branch_reason(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+        reason: String,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.change_branch_reason(
+                note.clone(),
+                branch.clone(),
+                link_note.clone(),
+                reason.clone(),
+            )?;
+            Ok((
+                (),
+                Operation::ChangeBranchReason {
+                    note,
+                    branch,
+                    link_note,
+                    reason,
+                },
+            ))
+        })
+    }
+
+    fn collapse_branch(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.collapse_branch(note.clone(), branch.clone(), link_note.clone())?;
+            Ok((
+                (),
+                Operation::CollapseBranch {
+                    note,
+                    branch,
+                    link_note,
+                },
+            ))
+        })
+    }
+
+    fn move_branch_option(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        item: FLinkId,
+        position: usize,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.move_branch_option(note.clone(), branch.clone(), item.clone(), position)?;
+            Ok((
+                (),
+                Operation::MoveBranchOption {
+                    note,
+                    branch,
+                    item,
+                    position,
+                },
+            ))
+        })
+    }
+
+    fn swap_branch_options(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        first: FLinkId,
+        second: FLinkId,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.swap_branch_options(
+                note.clone(),
+                branch.clone(),
+                first.clone(),
+                second.clone(),
+            )?;
+            Ok((
+                (),
+                Operation::SwapBranchOptions {
+                    note,
+                    branch,
+                    first,
+                    second,
+                },
+            ))
+        })
+    }
+
+    fn sort_branch_options(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        key: SortKey,
+    ) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.sort_branch_options(note.clone(), branch.clone(), key)?;
+            Ok(((), Operation::SortBranchOptions { note, branch, key }))
+        })
+    }
+}
+
+impl ChangeTag for JournaledManager {
+    fn rename_tag(&mut self, from: Tag, to: Tag) -> Result<(), ChangeError> {
+        self.journaled(ChangeError::JournalFailed, |manager| {
+            manager.rename_tag(from.clone(), to.clone())?;
+            Ok(((), Operation::RenameTag { from, to }))
+        })
+    }
+}
+
+impl DeleteNote for JournaledManager {
+    fn delete_note(&mut self, note: NoteId) -> Result<(), DeleteError> {
+        self.journaled(DeleteError::JournalFailed, |manager| {
+            manager.delete_note(note.clone())?;
+            Ok(((), Operation::DeleteNote { note }))
+        })
+    }
+}
+
+impl DeleteLink for JournaledManager {
+    fn delete_link(&mut self, from_note: NoteId, to_note: NoteId) -> Result<(), DeleteError> {
+        self.journaled(DeleteError::JournalFailed, |manager| {
+            manager.delete_link(from_note.clone(), to_note.clone())?;
+            Ok(((), Operation::DeleteLink { from_note, to_note }))
+        })
+    }
+}
+
+impl DeleteBranch for JournaledManager {
+    fn delete_branch(&mut self, note: NoteId, branch: BranchId) -> Result<(), DeleteError> {
+        self.journaled(DeleteError::JournalFailed, |manager| {
+            manager.delete_branch(note.clone(), branch.clone())?;
+            Ok(((), Operation::DeleteBranch { note, branch }))
+        })
+    }
+
+    fn delete_branch_link(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+    ) -> Result<(), DeleteError> {
+        self.journaled(DeleteError::JournalFailed, |manager| {
+            manager.delete_branch_link(note.clone(), branch.clone(), link_note.clone())?;
+            Ok((
+                (),
+                Operation::DeleteBranchLink {
+                    note,
+                    branch,
+                    link_note,
+                },
+            ))
+        })
+    }
+}
+
+impl DeleteTag for JournaledManager {
+    fn remove_tag(&mut self, note: NoteId, tag: Tag) -> Result<(), DeleteError> {
+        self.journaled(DeleteError::JournalFailed, |manager| {
+            manager.remove_tag(note.clone(), tag.clone())?;
+            Ok(((), Operation::RemoveTag { note, tag }))
+        })
+    }
+}
+
+impl Trash for JournaledManager {
+    fn list_trash(&self) -> Result<Vec<&TrashEntry>, TrashError> {
+        self.manager.list_trash()
+    }
+
+    fn restore_from_trash(&mut self, entry: TrashId) -> Result<(), TrashError> {
+        self.journaled(TrashError::JournalFailed, |manager| {
+            manager.restore_from_trash(entry)?;
+            Ok(((), Operation::RestoreFromTrash { entry }))
+        })
+    }
+
+    fn empty_trash(&mut self) -> Result<(), TrashError> {
+        self.journaled(TrashError::JournalFailed, |manager| {
+            manager.empty_trash()?;
+            Ok(((), Operation::EmptyTrash))
+        })
+    }
+
+    fn purge_trash(&mut self, before: OffsetDateTime) -> Result<usize, TrashError> {
+        self.journaled(TrashError::JournalFailed, |manager| {
+            let purged = manager.purge_trash(before)?;
+            Ok((purged, Operation::PurgeTrash { before }))
+        })
+    }
+}
+
+impl ReadNote for JournaledManager {
+    fn read_note(&self, note: NoteId) -> Result<&Note, ReadError> {
+        self.manager.read_note(note)
+    }
+
+    fn list_root_notes(&self) -> Result<Vec<&NoteId>, ReadError> {
+        self.manager.list_root_notes()
+    }
+
+    fn query_notes_by_date(&self, query: &DateQuery) -> Result<Vec<&Note>, ReadError> {
+        self.manager.query_notes_by_date(query)
+    }
+
+    fn list_revisions(&self, note: NoteId) -> Result<Vec<&NoteRevision>, ReadError> {
+        self.manager.list_revisions(note)
+    }
+
+    fn diff_revisions(
+        &self,
+        note: NoteId,
+        from: usize,
+        to: usize,
+    ) -> Result<RevisionDiff, ReadError> {
+        self.manager.diff_revisions(note, from, to)
+    }
+}
+
+impl ReadLink for JournaledManager {
+    fn list_forwardlinks(&self, note: NoteId) -> Result<Vec<&FLink>, ReadError> {
+        self.manager.list_forwardlinks(note)
+    }
+
+    fn list_unmarked_forwardlinks(&self, note: NoteId) -> Result<Vec<&FLink>, ReadError> {
+        self.manager.list_unmarked_forwardlinks(note)
+    }
+
+    fn list_pure_links(&self, note: NoteId) -> Result<Vec<&Link>, ReadError> {
+        self.manager.list_pure_links(note)
+    }
+
+    fn list_backlinks(&self, note: NoteId) -> Result<Vec<&NoteId>, ReadError> {
+        self.manager.list_backlinks(note)
+    }
+
+    fn list_forwardlinks_by_relation(
+        &self,
+        note: NoteId,
+        relation: &Relation,
+    ) -> Result<Vec<&Link>, ReadError> {
+        self.manager.list_forwardlinks_by_relation(note, relation)
+    }
+
+    fn list_backlinks_by_relation(
+        &self,
+        note: NoteId,
+        relation: &Relation,
+    ) -> Result<Vec<&NoteId>, ReadError> {
+        self.manager.list_backlinks_by_relation(note, relation)
+    }
+}
+
+impl ReadBranch for JournaledManager {
+    fn list_branches(&self, note: NoteId) -> Result<Vec<&BranchId>, ReadError> {
+        self.manager.list_branches(note)
+    }
+
+    fn list_branch_links(&self, branch: BranchId) -> Result<Vec<&Link>, ReadError> {
+        self.manager.list_branch_links(branch)
+    }
+
+    fn find_branch_note(&self, branch: BranchId) -> Result<&NoteId, ReadError> {
+        self.manager.find_branch_note(branch)
+    }
+
+    fn list_sub_branches(&self, branch: BranchId) -> Result<Vec<&BranchId>, ReadError> {
+        self.manager.list_sub_branches(branch)
+    }
+
+    fn find_branch_path(&self, branch: BranchId) -> Result<Vec<BranchId>, ReadError> {
+        self.manager.find_branch_path(branch)
+    }
+}
+
+impl ReadTag for JournaledManager {
+    fn list_tags(&self, note: NoteId) -> Result<Vec<&Tag>, ReadError> {
+        self.manager.list_tags(note)
+    }
+
+    fn list_tagged_notes(&self, tag: Tag) -> Result<Vec<&NoteId>, ReadError> {
+        self.manager.list_tagged_notes(tag)
+    }
+
+    fn find_notes_by_tags(
+        &self,
+        include: &[Tag],
+        exclude: &[Tag],
+    ) -> Result<Vec<&NoteId>, ReadError> {
+        self.manager.find_notes_by_tags(include, exclude)
+    }
+
+    fn tag_counts(&self) -> Result<Vec<(&Tag, usize)>, ReadError> {
+        self.manager.tag_counts()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_rebuilds_manager() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.jsonl");
+
+        let mut journaled = JournaledManager::open(&path).unwrap();
+        let a = journaled
+            .add_note(Note::new("a".to_string(), None, "body".to_string()))
+            .unwrap();
+        let b = journaled
+            .add_note(Note::new("b".to_string(), None, "body".to_string()))
+            .unwrap();
+        let branch = journaled
+            .create_branching(a.clone(), "condition".to_string())
+            .unwrap();
+        journaled
+            .add_branch(a.clone(), branch.clone(), b.clone(), "option".to_string())
+            .unwrap();
+        journaled
+            .collapse_branch(a.clone(), branch, b.clone())
+            .unwrap();
+
+        assert!(journaled
+            .add_link(a.clone(), NoteId::new(), "reason".to_string())
+            .is_err());
+
+        let c = journaled
+            .add_note(Note::new("c".to_string(), None, "body".to_string()))
+            .unwrap();
+        journaled
+            .add_link(a.clone(), c.clone(), "reason".to_string())
+            .unwrap();
+        journaled.delete_link(a.clone(), c.clone()).unwrap();
+        let entry = journaled.list_trash().unwrap()[0].id;
+        journaled.restore_from_trash(entry).unwrap();
+        // Replaying the link deletion with the default policy would delete `c` before
+        // `delete_note` is replayed.
+        journaled
+            .set_deletion_policy(DeletionPolicy::KeepOrphans)
+            .unwrap();
+        journaled.delete_link(a.clone(), c.clone()).unwrap();
+        journaled.delete_note(c).unwrap();
+        // Replaying the move with the default states would fail, `review` is unknown to them.
+        journaled
+            .set_task_states(
+                TaskStates::default().with_transition(TaskState::todo(), TaskState::new("review")),
+            )
+            .unwrap();
+        journaled
+            .change_note_state(b.clone(), TaskState::new("review"))
+            .unwrap();
+
+        let operations = read_operations(&path).unwrap();
+        assert_eq!(operations.len(), 14);
+
+        let replayed = replay(&path).unwrap();
+        assert_eq!(
+            replayed.read_note(a.clone()).unwrap(),
+            journaled.read_note(a).unwrap()
+        );
+        assert_eq!(
+            replayed.read_note(b.clone()).unwrap(),
+            journaled.read_note(b).unwrap()
+        );
+        assert_eq!(
+            replayed.list_trash().unwrap(),
+            journaled.list_trash().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_settings_are_replayed() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.jsonl");
+
+        let mut journaled = JournaledManager::open(&path).unwrap();
+        journaled.set_acyclic(true).unwrap();
+        journaled.set_revision_retention(1).unwrap();
+        journaled
+            .set_trash_retention(Some(Duration::days(7)))
+            .unwrap();
+        journaled.set_history_depth(3).unwrap();
+
+        let a = journaled
+            .add_note(Note::new("a".to_string(), None, "body".to_string()))
+            .unwrap();
+        journaled
+            .change_note_body(a.clone(), "first".to_string())
+            .unwrap();
+        journaled
+            .change_note_body(a.clone(), "second".to_string())
+            .unwrap();
+        drop(journaled);
+
+        let journaled = JournaledManager::open(&path).unwrap();
+        assert!(journaled.is_acyclic());
+        assert_eq!(journaled.revision_retention(), 1);
+        assert_eq!(journaled.trash_retention(), Some(Duration::days(7)));
+        assert_eq!(journaled.history_depth(), 3);
+        assert_eq!(journaled.list_revisions(a).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_write_rolls_back() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.jsonl");
+
+        let mut journaled = JournaledManager::open(&path).unwrap();
+        let a = journaled
+            .add_note(Note::new("a".to_string(), None, "body".to_string()))
+            .unwrap();
+
+        // A file opened for reading only refuses every record.
+        journaled.file = File::open(&path).unwrap();
+
+        assert_eq!(
+            journaled
+                .change_note_title(a.clone(), "renamed".to_string())
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::JournalFailed
+        );
+        assert!(journaled.set_acyclic(true).is_err());
+
+        assert_eq!(journaled.read_note(a).unwrap().title, "a");
+        assert!(!journaled.is_acyclic());
+        assert_eq!(read_operations(&path).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_torn_record_is_ignored() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.jsonl");
+
+        let mut journaled = JournaledManager::open(&path).unwrap();
+        let note = journaled
+            .add_note(Note::new("a".to_string(), None, "body".to_string()))
+            .unwrap();
+        drop(journaled);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"operation":"mark_note","no"#).unwrap();
+        drop(file);
+
+        let mut journaled = JournaledManager::open(&path).unwrap();
+        journaled.mark_note(note.clone()).unwrap();
+        drop(journaled);
+
+        let journaled = JournaledManager::open(&path).unwrap();
+        assert_eq!(journaled.read_note(note).unwrap().state, TaskState::done());
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("journal.jsonl");
+
+        std::fs::write(&path, "not json\n").unwrap();
+
+        assert_eq!(
+            replay(&path).err().unwrap().current_context(),
+            &JournalError::CorruptedRecord(1)
+        );
+    }
+}
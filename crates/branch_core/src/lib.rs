@@ -1,4 +1,15 @@
 pub mod errors;
+pub mod expression;
+#[cfg(feature = "journal")]
+pub mod journal;
 mod manager;
 mod manager_impl;
+pub mod operation;
+pub mod prelude;
+pub mod session;
+pub mod storage;
+#[cfg(test)]
+pub(crate) mod test_util;
+pub mod traversal;
 pub mod types;
+pub mod wiki;
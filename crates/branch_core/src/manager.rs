@@ -1,12 +1,38 @@
 use std::collections::HashMap;
 
-use crate::types::{Note, NoteId};
+use crate::types::{DeletionPolicy, Note, NoteId, TaskStates};
 
 #[derive(Default)]
 pub struct NotesManager {
     notes: HashMap<NoteId, Note>,
+    history: history::History,
+    savepoints: Vec<history::Revision>,
+    search: search::SearchIndex,
+    tags: tags::TagIndex,
+    acyclic: bool,
+    deletion: DeletionPolicy,
+    retention: revisions::Retention,
+    states: TaskStates,
+    clock: transaction::Clock,
+    trash: trash::TrashBin,
 }
 
 mod add;
-mod delete;
 mod change;
+mod cycles;
+mod delete;
+mod history;
+mod order;
+mod persist;
+mod read;
+mod reconsile;
+mod revisions;
+mod search;
+mod states;
+mod tags;
+mod transaction;
+mod trash;
+mod wiki;
+
+pub use history::DEFAULT_HISTORY_DEPTH;
+pub use revisions::DEFAULT_REVISION_RETENTION;
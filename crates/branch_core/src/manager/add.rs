@@ -1,7 +1,8 @@
-use error_stack::ensure;
+use error_stack::{ensure, ResultExt};
 
 use crate::errors::AddError;
-use crate::manager_impl::{AddBranch, AddLink, AddNote};
+use crate::expression;
+use crate::manager_impl::{AddBranch, AddLink, AddNote, AddTag};
 use crate::types;
 
 impl AddNote for super::NotesManager {
@@ -9,16 +10,18 @@ impl AddNote for super::NotesManager {
         &mut self,
         note: crate::types::Note,
     ) -> error_stack::Result<crate::types::NoteId, AddError> {
-        let note_id = note.get_id();
+        self.transaction(|manager| {
+            let note_id = note.get_id();
 
-        ensure!(
-            !self.notes.contains_key(&note_id),
-            AddError::NoteAlreadyExists
-        );
+            ensure!(
+                !manager.notes.contains_key(&note_id),
+                AddError::NoteAlreadyExists
+            );
 
-        self.notes.insert(note_id.clone(), note);
+            manager.insert_note(note);
 
-        Ok(note_id)
+            Ok(note_id)
+        })
     }
 }
 
@@ -29,59 +32,167 @@ impl AddLink for super::NotesManager {
         to_note: crate::types::NoteId,
         reason: String,
     ) -> error_stack::Result<(), AddError> {
-        ensure!(
-            self.notes.contains_key(&to_note),
-            AddError::NoteDoesNotExist
-        );
+        self.insert_link(from_note, types::Link::new(to_note, reason))
+    }
 
-        let from_note_ = self
-            .notes
-            .get_mut(&from_note)
-            .ok_or(AddError::NoteDoesNotExist)?;
+    fn add_typed_link(
+        &mut self,
+        from_note: types::NoteId,
+        to_note: types::NoteId,
+        relation: types::Relation,
+        reason: String,
+    ) -> error_stack::Result<(), AddError> {
+        ensure!(relation.is_valid(), AddError::InvalidRelation);
 
-        let duplicate_clause = from_note_.forwardlinks.iter().any(|flink| match flink {
-            crate::types::FLink::Link(link) => link.id == to_note,
-            crate::types::FLink::Branch(_) => false,
-        });
+        self.insert_link(
+            from_note,
+            types::Link::new(to_note, reason).with_relation(relation),
+        )
+    }
 
-        ensure!(!duplicate_clause, AddError::LinkAlreadyExists);
+    ///
+    /// [`add_bidirectional_link`] adds the link and its other half in one go. In strict mode it
+    /// is refused, the two halves form a cycle.
+    ///
+    fn add_bidirectional_link(
+        &mut self,
+        from_note: types::NoteId,
+        to_note: types::NoteId,
+        reason: String,
+    ) -> error_stack::Result<(), AddError> {
+        self.transaction(|manager| {
+            let link = types::Link {
+                bidirectional: true,
+                ..types::Link::new(to_note.clone(), reason)
+            };
+
+            manager.insert_link(to_note, link.mirrored(from_note.clone()))?;
+            manager.insert_link(from_note, link)
+        })
+    }
+}
 
-        from_note_
-            .forwardlinks
-            .push(types::FLink::Link(types::Link {
-                id: to_note.clone(),
-                reason,
-            }));
+impl super::NotesManager {
+    ///
+    /// [`insert_link`] adds a link at the end of the forward links of a note.
+    ///
+    fn insert_link(
+        &mut self,
+        from_note: types::NoteId,
+        link: types::Link,
+    ) -> error_stack::Result<(), AddError> {
+        self.transaction(|manager| {
+            let to_note = link.id.clone();
+
+            ensure!(
+                manager.notes.contains_key(&to_note),
+                AddError::NoteDoesNotExist
+            );
+
+            manager.ensure_acyclic(&from_note, &to_note)?;
+
+            let from_note_ = manager
+                .note_mut(&from_note)
+                .ok_or(AddError::NoteDoesNotExist)?;
 
-        let to_note = self
-            .notes
-            .get_mut(&to_note)
-            .ok_or(AddError::NoteDoesNotExist)?;
+            ensure!(
+                from_note_.link(&to_note).is_none(),
+                AddError::LinkAlreadyExists
+            );
 
-        to_note.add_backlink(from_note);
+            from_note_.forwardlinks.push(types::FLink::Link(link));
 
-        Ok(())
+            manager
+                .note_mut(&to_note)
+                .ok_or(AddError::NoteDoesNotExist)?
+                .add_backlink(from_note);
+
+            Ok(())
+        })
     }
-}
 
-impl AddBranch for super::NotesManager {
-    fn create_branching(
+    ///
+    /// [`create_branching_with_id`] creates a branching with a known id, this is used to replay
+    /// recorded operations.
+    ///
+    pub(crate) fn create_branching_with_id(
         &mut self,
         note: types::NoteId,
+        branch: types::BranchId,
         condition: String,
     ) -> error_stack::Result<types::BranchId, AddError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(AddError::NoteDoesNotExist)?;
+        expression::validate(&condition).change_context(AddError::InvalidCondition)?;
+
+        self.transaction(|manager| {
+            let note = manager.note_mut(&note).ok_or(AddError::NoteDoesNotExist)?;
 
-        let branch = types::Branch::new(condition);
+            ensure!(
+                note.branch(&branch).is_none(),
+                AddError::BranchAlreadyExists
+            );
 
-        let branch_id = branch.get_id();
+            let branch = types::Branch::new(condition).with_id(branch);
 
-        note.forwardlinks.push(types::FLink::Branch(branch));
+            let branch_id = branch.get_id();
+
+            note.forwardlinks.push(types::FLink::Branch(branch));
+
+            Ok(branch_id)
+        })
+    }
+
+    ///
+    /// [`create_sub_branching_with_id`] creates a nested branching with a known id, this is used
+    /// to replay recorded operations.
+    ///
+    pub(crate) fn create_sub_branching_with_id(
+        &mut self,
+        note: types::NoteId,
+        on_branch: types::BranchId,
+        branch: types::BranchId,
+        condition: String,
+    ) -> error_stack::Result<types::BranchId, AddError> {
+        expression::validate(&condition).change_context(AddError::InvalidCondition)?;
+
+        self.transaction(|manager| {
+            let note = manager.note_mut(&note).ok_or(AddError::NoteDoesNotExist)?;
+
+            ensure!(
+                note.branch(&branch).is_none(),
+                AddError::BranchAlreadyExists
+            );
+
+            let parent = note
+                .branch_mut(&on_branch)
+                .ok_or(AddError::BranchDoesNotExist)?;
+
+            let branch = types::Branch::new(condition).with_id(branch);
+
+            let branch_id = branch.get_id();
+
+            parent.branches.push(types::FLink::Branch(branch));
+
+            Ok(branch_id)
+        })
+    }
+}
+
+impl AddBranch for super::NotesManager {
+    fn create_branching(
+        &mut self,
+        note: types::NoteId,
+        condition: String,
+    ) -> error_stack::Result<types::BranchId, AddError> {
+        self.create_branching_with_id(note, types::BranchId::new(), condition)
+    }
 
-        Ok(branch_id)
+    fn create_sub_branching(
+        &mut self,
+        note: types::NoteId,
+        on_branch: types::BranchId,
+        condition: String,
+    ) -> error_stack::Result<types::BranchId, AddError> {
+        self.create_sub_branching_with_id(note, on_branch, types::BranchId::new(), condition)
     }
 
     fn add_branch(
@@ -91,46 +202,61 @@ impl AddBranch for super::NotesManager {
         link_note: types::NoteId,
         reason: String,
     ) -> error_stack::Result<(), AddError> {
-        ensure!(
-            self.notes.contains_key(&link_note),
-            AddError::NoteDoesNotExist
-        );
+        expression::validate(&reason).change_context(AddError::InvalidReason)?;
+
+        self.transaction(|manager| {
+            ensure!(
+                manager.notes.contains_key(&link_note),
+                AddError::NoteDoesNotExist
+            );
 
-        let note_ = self
-            .notes
-            .get_mut(&note)
-            .ok_or(AddError::NoteDoesNotExist)?;
+            manager.ensure_acyclic(&note, &link_note)?;
 
-        let branch = note_
-            .forwardlinks
-            .iter_mut()
-            .find_map(|flink| match flink {
-                types::FLink::Branch(branch) if branch.get_id() == on_branch => Some(branch),
-                _ => None,
-            })
-            .ok_or(AddError::BranchDoesNotExist)?;
+            let note_ = manager.note_mut(&note).ok_or(AddError::NoteDoesNotExist)?;
 
-        let duplicate_clause = branch.branches.iter().any(|link| link.id == link_note);
+            let branch = note_
+                .branch_mut(&on_branch)
+                .ok_or(AddError::BranchDoesNotExist)?;
 
-        ensure!(!duplicate_clause, AddError::BranchAlreadyExists);
+            let duplicate_clause = branch.links().any(|link| link.id == link_note);
 
-        branch.branches.push(types::Link {
-            id: link_note.clone(),
-            reason,
-        });
+            ensure!(!duplicate_clause, AddError::BranchAlreadyExists);
 
-        self.notes
-            .get_mut(&link_note)
-            .ok_or(AddError::NoteDoesNotExist)?
-            .add_backlink(note);
+            branch.branches.push(types::FLink::Link(types::Link::new(
+                link_note.clone(),
+                reason,
+            )));
 
-        Ok(())
+            manager
+                .note_mut(&link_note)
+                .ok_or(AddError::NoteDoesNotExist)?
+                .add_backlink(note);
+
+            Ok(())
+        })
+    }
+}
+
+impl AddTag for super::NotesManager {
+    fn add_tag(
+        &mut self,
+        note: types::NoteId,
+        tag: types::Tag,
+    ) -> error_stack::Result<(), AddError> {
+        self.transaction(|manager| {
+            let note = manager.note_mut(&note).ok_or(AddError::NoteDoesNotExist)?;
+
+            ensure!(note.tags.insert(tag), AddError::TagAlreadyExists);
+
+            Ok(())
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::{id, note};
 
     #[test]
     fn test_add_verify() {
@@ -173,4 +299,107 @@ mod tests {
             &AddError::NoteAlreadyExists
         );
     }
+
+    #[test]
+    fn test_nested_branches() {
+        use crate::manager_impl::{
+            ChangeBranch, ChangeNote, DeleteBranch, ReadBranch, ReadLink, ReadNote,
+        };
+
+        let mut manager = super::super::NotesManager::default();
+        for note_id in ["a", "b", "c", "d"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+
+        // a: top? b | (nested? c | d | (deeper? b))
+        let top = manager
+            .create_branching(id("a"), "top".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), top.clone(), id("b"), "b".to_string())
+            .unwrap();
+        let nested = manager
+            .create_sub_branching(id("a"), top.clone(), "nested".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), nested.clone(), id("c"), "c".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), nested.clone(), id("d"), "d".to_string())
+            .unwrap();
+        let deeper = manager
+            .create_sub_branching(id("a"), nested.clone(), "deeper".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), deeper.clone(), id("b"), "b again".to_string())
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .create_sub_branching(id("a"), types::BranchId::new(), "missing".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::BranchDoesNotExist
+        );
+        assert_eq!(manager.list_branches(id("a")).unwrap(), vec![&top]);
+        assert_eq!(
+            manager.list_sub_branches(top.clone()).unwrap(),
+            vec![&nested]
+        );
+        assert_eq!(
+            manager.find_branch_path(deeper.clone()).unwrap(),
+            vec![top.clone(), nested.clone(), deeper.clone()]
+        );
+        assert_eq!(manager.find_branch_note(deeper.clone()).unwrap(), &id("a"));
+        assert_eq!(
+            manager.list_backlinks(id("b")).unwrap(),
+            vec![&id("a"), &id("a")]
+        );
+
+        manager
+            .change_branch_reason(id("a"), nested.clone(), id("c"), "changed".to_string())
+            .unwrap();
+        assert_eq!(
+            manager.list_branch_links(nested.clone()).unwrap()[0].reason,
+            "changed"
+        );
+
+        // Deleting a nested branch removes it from its parent along with its options. The backlink
+        // of the top level option to the same note is left alone.
+        manager.delete_branch(id("a"), deeper.clone()).unwrap();
+        assert_eq!(manager.list_sub_branches(nested.clone()).unwrap().len(), 0);
+        assert!(manager.find_branch_path(deeper).is_err());
+        assert_eq!(manager.list_backlinks(id("b")).unwrap(), vec![&id("a")]);
+
+        manager
+            .collapse_branch(id("a"), nested.clone(), id("d"))
+            .unwrap();
+        assert!(manager.find_branch_path(nested).is_err());
+        assert_eq!(
+            manager
+                .list_branch_links(top.clone())
+                .unwrap()
+                .iter()
+                .map(|link| &link.id)
+                .collect::<Vec<_>>(),
+            vec![&id("b"), &id("d")]
+        );
+        assert!(manager.read_note(id("c")).unwrap().backlinks.is_empty());
+        assert_eq!(manager.list_backlinks(id("d")).unwrap(), vec![&id("a")]);
+
+        let before = manager.read_note(id("a")).unwrap().clone();
+        manager.reconsile_nodes().unwrap();
+        assert_eq!(
+            manager.read_note(id("a")).unwrap().forwardlinks,
+            before.forwardlinks
+        );
+
+        // A top level branch goes the same way, and the notes left without backlinks follow the
+        // deletion policy.
+        manager.set_deletion_policy(types::DeletionPolicy::KeepOrphans);
+        manager.delete_branch(id("a"), top.clone()).unwrap();
+        assert!(manager.list_branches(id("a")).unwrap().is_empty());
+        assert!(manager.find_branch_path(top).is_err());
+        assert!(manager.read_note(id("d")).unwrap().backlinks.is_empty());
+    }
 }
@@ -1,7 +1,7 @@
 use error_stack::ResultExt;
 
 use crate::errors;
-use crate::manager_impl::{ChangeBranch, ChangeLink, ChangeNote, DeleteBranch};
+use crate::manager_impl::{ChangeBranch, ChangeLink, ChangeNote, ChangeTag};
 
 impl ChangeNote for super::NotesManager {
     fn change_note_title(
@@ -9,14 +9,7 @@ impl ChangeNote for super::NotesManager {
         note: crate::types::NoteId,
         title: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        note.title = title;
-
-        Ok(())
+        self.transaction(|manager| manager.edit_note(&note, |note| note.title = title))
     }
 
     fn change_note_subtitle(
@@ -24,14 +17,7 @@ impl ChangeNote for super::NotesManager {
         note: crate::types::NoteId,
         subtitle: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        note.subtitle = Some(subtitle);
-
-        Ok(())
+        self.transaction(|manager| manager.edit_note(&note, |note| note.subtitle = Some(subtitle)))
     }
 
     fn change_note_body(
@@ -39,46 +25,112 @@ impl ChangeNote for super::NotesManager {
         note: crate::types::NoteId,
         body: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        note.body = body;
+        self.transaction(|manager| {
+            manager.edit_note(&note, |note| note.body = body)?;
+            manager.sync_references(&note)
+        })
+    }
 
-        Ok(())
+    fn change_note_state(
+        &mut self,
+        note: crate::types::NoteId,
+        state: crate::types::TaskState,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.transaction(|manager| manager.change_state(&note, state))
     }
 
     fn mark_note(
         &mut self,
         note: crate::types::NoteId,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
+        let done = self.states.done().clone();
 
-        note.marked = true;
-
-        Ok(())
+        self.transaction(|manager| manager.change_state(&note, done))
     }
 
     fn unmark_note(
         &mut self,
         note: crate::types::NoteId,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
+        let initial = self.states.initial().clone();
 
-        note.marked = false;
+        self.transaction(|manager| manager.change_state(&note, initial))
+    }
 
-        Ok(())
+    fn reconsile_nodes(
+        &mut self,
+    ) -> error_stack::Result<crate::types::ReconsileReport, crate::errors::ChangeError> {
+        self.transaction(|manager| {
+            let mut report = crate::types::ReconsileReport::default();
+
+            loop {
+                manager.drop_dangling_links(&mut report);
+                manager.remove_empty_branches(&mut report);
+                manager.pair_bidirectional_links(&mut report);
+
+                let orphans = manager.rebuild_backlinks(&mut report);
+
+                if orphans.is_empty() {
+                    break;
+                }
+
+                match manager.deletion {
+                    crate::types::DeletionPolicy::KeepOrphans => break,
+                    crate::types::DeletionPolicy::CascadeOrphans => {}
+                    crate::types::DeletionPolicy::RefuseOrphans => {
+                        return Err(error_stack::Report::new(
+                            crate::errors::ChangeError::WouldOrphan(orphans[0].clone()),
+                        ));
+                    }
+                }
+
+                // The orphans go to the trash like any deleted note, their backlinks are rebuilt
+                // by the next round.
+                manager
+                    .trashing(|manager| {
+                        for orphan in &orphans {
+                            manager.remove_note(orphan);
+                        }
+
+                        Ok(())
+                    })
+                    .change_context(crate::errors::ChangeError::NoteDoesNotExist)?;
+
+                for orphan in orphans {
+                    report
+                        .fixes
+                        .push(crate::types::ReconsileFix::RemovedOrphan { note: orphan });
+                }
+            }
+
+            manager.propagate_done(&mut report);
+
+            Ok(report)
+        })
     }
 
-    fn reconsile_nodes(&mut self) -> error_stack::Result<(), crate::errors::ChangeError> {
-        todo!()
+    fn restore_revision(
+        &mut self,
+        note: crate::types::NoteId,
+        revision: usize,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.transaction(|manager| {
+            let revision = manager
+                .notes
+                .get(&note)
+                .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?
+                .revisions
+                .get(revision)
+                .cloned()
+                .ok_or(crate::errors::ChangeError::RevisionDoesNotExist)?;
+
+            manager.edit_note(&note, |note| {
+                note.title = revision.title;
+                note.subtitle = revision.subtitle;
+                note.body = revision.body;
+            })?;
+            manager.sync_references(&note)
+        })
     }
 }
 
@@ -89,22 +141,138 @@ impl ChangeLink for super::NotesManager {
         to_note: crate::types::NoteId,
         reason: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let from_note_ = self
-            .notes
-            .get_mut(&from_note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        let link = from_note_
-            .forwardlinks
-            .iter_mut()
-            .find(|flink| match flink {
-                crate::types::FLink::Link(link) => link.id == to_note,
-                crate::types::FLink::Branch(_) => false,
+        self.transaction(|manager| {
+            manager.edit_link(&from_note, &to_note, |link| link.reason = reason)
+        })
+    }
+
+    fn change_link_relation(
+        &mut self,
+        from_note: crate::types::NoteId,
+        to_note: crate::types::NoteId,
+        relation: Option<crate::types::Relation>,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        error_stack::ensure!(
+            relation
+                .as_ref()
+                .is_none_or(crate::types::Relation::is_valid),
+            crate::errors::ChangeError::InvalidRelation
+        );
+
+        self.transaction(|manager| {
+            manager.edit_link(&from_note, &to_note, |link| link.relation = relation)
+        })
+    }
+
+    fn change_link_weight(
+        &mut self,
+        from_note: crate::types::NoteId,
+        to_note: crate::types::NoteId,
+        weight: Option<f64>,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        error_stack::ensure!(
+            crate::types::Link::is_valid_weight(weight),
+            crate::errors::ChangeError::InvalidWeight
+        );
+
+        self.transaction(|manager| {
+            manager.edit_link(&from_note, &to_note, |link| link.weight = weight)
+        })
+    }
+
+    fn change_link_annotation(
+        &mut self,
+        from_note: crate::types::NoteId,
+        to_note: crate::types::NoteId,
+        key: String,
+        value: Option<String>,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        error_stack::ensure!(
+            crate::types::Link::is_valid_annotation(&key),
+            crate::errors::ChangeError::InvalidAnnotation
+        );
+
+        self.transaction(|manager| {
+            manager.edit_link(&from_note, &to_note, |link| match value {
+                Some(value) => {
+                    link.annotations.insert(key, value);
+                }
+                None => {
+                    link.annotations.remove(&key);
+                }
             })
+        })
+    }
+
+    fn move_forwardlink(
+        &mut self,
+        note: crate::types::NoteId,
+        item: crate::types::FLinkId,
+        position: usize,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_forwardlinks(&note, |_, options| {
+            crate::types::move_option(options, &item, position)
+        })
+    }
+
+    fn swap_forwardlinks(
+        &mut self,
+        note: crate::types::NoteId,
+        first: crate::types::FLinkId,
+        second: crate::types::FLinkId,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_forwardlinks(&note, |_, options| {
+            crate::types::swap_options(options, &first, &second)
+        })
+    }
+
+    fn sort_forwardlinks(
+        &mut self,
+        note: crate::types::NoteId,
+        key: crate::types::SortKey,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_forwardlinks(&note, |manager, options| {
+            crate::types::sort_options(options, key, |id| manager.sort_fields(id));
+
+            Ok(())
+        })
+    }
+}
+
+impl super::NotesManager {
+    ///
+    /// [`edit_link`] applies an edit to a forward link of a note. The other half of a
+    /// bidirectional link is made the same again afterwards.
+    ///
+    pub(super) fn edit_link(
+        &mut self,
+        from_note: &crate::types::NoteId,
+        to_note: &crate::types::NoteId,
+        edit: impl FnOnce(&mut crate::types::Link),
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        let link = self
+            .note_mut(from_note)
+            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?
+            .link_mut(to_note)
             .ok_or(crate::errors::ChangeError::LinkDoesNotExist)?;
 
-        if let crate::types::FLink::Link(link) = link {
-            link.reason = reason;
+        edit(link);
+
+        if !link.bidirectional {
+            return Ok(());
+        }
+
+        let mirrored = link.mirrored(from_note.clone());
+
+        if let Some(other) = self
+            .note_mut(to_note)
+            .and_then(|note| note.link_mut(from_note))
+        {
+            *other = crate::types::Link {
+                created: other.created,
+                modified: other.modified,
+                ..mirrored
+            };
         }
 
         Ok(())
@@ -118,25 +286,22 @@ impl ChangeBranch for super::NotesManager {
         branch: crate::types::BranchId,
         condition: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        let branch = note
-            .forwardlinks
-            .iter_mut()
-            .find(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => branch_.get_id() == branch,
-                crate::types::FLink::Link(_) => false,
-            })
-            .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+        crate::expression::validate(&condition)
+            .change_context(crate::errors::ChangeError::InvalidCondition)?;
 
-        if let crate::types::FLink::Branch(branch_) = branch {
-            branch_.condition = condition;
-        }
+        self.transaction(|manager| {
+            let note = manager
+                .note_mut(&note)
+                .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
 
-        Ok(())
+            let branch = note
+                .branch_mut(&branch)
+                .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+
+            branch.condition = condition;
+
+            Ok(())
+        })
     }
 
     fn change_branch_reason(
@@ -146,31 +311,31 @@ impl ChangeBranch for super::NotesManager {
         link_note: crate::types::NoteId,
         reason: String,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        let branch = note
-            .forwardlinks
-            .iter_mut()
-            .find(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => branch_.get_id() == branch,
-                crate::types::FLink::Link(_) => false,
-            })
-            .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+        crate::expression::validate(&reason)
+            .change_context(crate::errors::ChangeError::InvalidReason)?;
+
+        self.transaction(|manager| {
+            let note = manager
+                .note_mut(&note)
+                .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
 
-        if let crate::types::FLink::Branch(branch_) = branch {
-            let link = branch_
+            let branch = note
+                .branch_mut(&branch)
+                .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+
+            let link = branch
                 .branches
                 .iter_mut()
-                .find(|link| link.id == link_note)
+                .find_map(|option| match option {
+                    crate::types::FLink::Link(link) if link.id == link_note => Some(link),
+                    _ => None,
+                })
                 .ok_or(crate::errors::ChangeError::LinkDoesNotExist)?;
 
             link.reason = reason;
-        }
 
-        Ok(())
+            Ok(())
+        })
     }
 
     fn collapse_branch(
@@ -179,42 +344,211 @@ impl ChangeBranch for super::NotesManager {
         branch: crate::types::BranchId,
         link_note: crate::types::NoteId,
     ) -> error_stack::Result<(), crate::errors::ChangeError> {
-        let note_ = self
-            .notes
-            .get_mut(&note)
-            .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?;
-
-        let branch_ = note_
-            .forwardlinks
-            .iter()
-            .find(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => branch_.get_id() == branch,
-                crate::types::FLink::Link(_) => false,
-            })
-            .cloned()
-            .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
-
-        note_.forwardlinks.retain(|flink| match flink {
-            crate::types::FLink::Branch(branch_) => branch_.get_id() != branch,
-            crate::types::FLink::Link(_) => true,
-        });
-
-        if let crate::types::FLink::Branch(branch_) = branch_ {
-            for b in branch_.branches.clone() {
-                if b.id == link_note {
-                    note_.forwardlinks.push(crate::types::FLink::Link(b));
-                    break;
+        self.transaction(|manager| {
+            let branch_ = manager
+                .notes
+                .get(&note)
+                .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?
+                .branch(&branch)
+                .cloned()
+                .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+
+            // The discarded options are removed while the branch still exists, so that their
+            // backlinks are cleaned up as well. Options of nested branches are discarded too, and
+            // only the first option leading to the chosen note is kept.
+            let mut chosen = None;
+
+            for branch__ in branch_.walk() {
+                for b in branch__.links() {
+                    if b.id == link_note && chosen.is_none() {
+                        chosen = Some(b.clone());
+                    } else {
+                        manager
+                            .remove_branch_link(
+                                note.clone(),
+                                branch__.get_id(),
+                                b.id.clone(),
+                                crate::types::DeletionPolicy::KeepOrphans,
+                            )
+                            .change_context(errors::ChangeError::BranchDoesNotExist)?;
+                    }
                 }
             }
 
-            for b in branch_.branches.clone() {
-                if b.id != link_note {
-                    self.delete_branch_link(note.clone(), branch_.get_id(), b.id.clone())
-                        .change_context(errors::ChangeError::BranchDoesNotExist)?;
+            let siblings = manager
+                .note_mut(&note)
+                .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?
+                .branch_siblings_mut(&branch)
+                .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+
+            let position = siblings
+                .iter()
+                .position(|flink| flink.is_branch(&branch))
+                .ok_or(crate::errors::ChangeError::BranchDoesNotExist)?;
+
+            // When the note already links to the chosen note next to the branch, the option is
+            // merged into that link and only one backlink is kept. Otherwise it takes the place of
+            // the branch.
+            let merged = chosen.as_ref().is_some_and(|b| {
+                siblings.iter().any(|flink| match flink {
+                    crate::types::FLink::Link(link) => link.id == b.id,
+                    crate::types::FLink::Branch(_) => false,
+                })
+            });
+
+            match chosen {
+                Some(b) if merged => {
+                    siblings.remove(position);
+                    manager
+                        .note_mut(&b.id)
+                        .ok_or(crate::errors::ChangeError::NoteDoesNotExist)?
+                        .delete_backlink_once(&note);
+                }
+                Some(b) => siblings[position] = crate::types::FLink::Link(b),
+                None => {
+                    siblings.remove(position);
                 }
             }
+
+            Ok(())
+        })
+    }
+
+    fn move_branch_option(
+        &mut self,
+        note: crate::types::NoteId,
+        branch: crate::types::BranchId,
+        item: crate::types::FLinkId,
+        position: usize,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_branch_options(&note, &branch, |_, options| {
+            crate::types::move_option(options, &item, position)
+        })
+    }
+
+    fn swap_branch_options(
+        &mut self,
+        note: crate::types::NoteId,
+        branch: crate::types::BranchId,
+        first: crate::types::FLinkId,
+        second: crate::types::FLinkId,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_branch_options(&note, &branch, |_, options| {
+            crate::types::swap_options(options, &first, &second)
+        })
+    }
+
+    fn sort_branch_options(
+        &mut self,
+        note: crate::types::NoteId,
+        branch: crate::types::BranchId,
+        key: crate::types::SortKey,
+    ) -> error_stack::Result<(), crate::errors::ChangeError> {
+        self.reorder_branch_options(&note, &branch, |manager, options| {
+            crate::types::sort_options(options, key, |id| manager.sort_fields(id));
+
+            Ok(())
+        })
+    }
+}
+
+impl ChangeTag for super::NotesManager {
+    fn rename_tag(
+        &mut self,
+        from: crate::types::Tag,
+        to: crate::types::Tag,
+    ) -> error_stack::Result<(), errors::ChangeError> {
+        self.transaction(|manager| {
+            let notes = manager
+                .tags
+                .tagged(&from)
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+
+            error_stack::ensure!(!notes.is_empty(), errors::ChangeError::TagDoesNotExist);
+
+            for note in notes {
+                let note = manager
+                    .note_mut(&note)
+                    .ok_or(errors::ChangeError::NoteDoesNotExist)?;
+
+                note.tags = std::mem::take(&mut note.tags)
+                    .into_iter()
+                    .map(|tag| tag.rebase(&from, &to).unwrap_or(tag))
+                    .collect();
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeBranch};
+    use crate::test_util::{id, note};
+    use crate::types::FLink;
+
+    #[test]
+    fn test_collapse_into_existing_link() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "direct".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("b"), "option".to_string())
+            .unwrap();
+
+        manager.collapse_branch(id("a"), branch, id("b")).unwrap();
+
+        let a = &manager.notes[&id("a")];
+        match &a.forwardlinks[..] {
+            [FLink::Link(link)] => {
+                assert_eq!(link.id, id("b"));
+                assert_eq!(link.reason, "direct");
+            }
+            _ => panic!("expected the existing link only"),
         }
+        assert_eq!(manager.notes[&id("b")].backlinks, vec![id("a")]);
+    }
 
-        Ok(())
+    #[test]
+    fn test_collapse_keeps_the_position() {
+        let mut manager = super::super::NotesManager::default();
+        for note_id in ["a", "b", "c", "d"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "before".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("c"), "option".to_string())
+            .unwrap();
+        manager
+            .add_link(id("a"), id("d"), "after".to_string())
+            .unwrap();
+
+        manager.collapse_branch(id("a"), branch, id("c")).unwrap();
+
+        assert_eq!(
+            manager.notes[&id("a")]
+                .forwardlinks
+                .iter()
+                .map(|flink| match flink {
+                    FLink::Link(link) => link.id.clone(),
+                    FLink::Branch(_) => panic!("expected links only"),
+                })
+                .collect::<Vec<_>>(),
+            vec![id("b"), id("c"), id("d")]
+        );
     }
 }
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+
+use error_stack::{Report, Result};
+
+use crate::errors::AddError;
+use crate::traversal::{Direction, Traversal};
+use crate::types::NoteId;
+
+impl super::NotesManager {
+    ///
+    /// [`set_acyclic`] turns the strict mode on or off. In strict mode [`add_link`] and
+    /// [`add_branch`] reject an edge that would close a cycle with [`AddError::CycleDetected`].
+    /// Cycles that already exist are left alone, use [`find_cycles`] to list them.
+    ///
+    /// [`add_link`]: crate::prelude::AddLink::add_link
+    /// [`add_branch`]: crate::prelude::AddBranch::add_branch
+    ///
+    pub fn set_acyclic(&mut self, acyclic: bool) {
+        self.acyclic = acyclic;
+    }
+
+    pub fn is_acyclic(&self) -> bool {
+        self.acyclic
+    }
+
+    ///
+    /// [`find_cycles`] lists one cycle for every group of notes that can reach each other, branch
+    /// options included. A cycle starts at its smallest note id and its last note links back to the
+    /// first, so a note linking to itself is a cycle of one note.
+    ///
+    pub fn find_cycles(&self) -> Vec<Vec<NoteId>> {
+        let forward = Traversal::new(self);
+        let backward = forward.direction(Direction::Backward);
+
+        // The components are found with Kosaraju's algorithm: notes are ordered by the time their
+        // forward walk finishes, then the backward walks in reverse order each collect one
+        // component.
+        let mut visited = HashSet::new();
+        let mut order = Vec::with_capacity(self.notes.len());
+
+        for root in self.sorted_note_ids() {
+            if !visited.insert(root.clone()) {
+                continue;
+            }
+
+            let neighbors = forward.neighbors(&root).unwrap_or_default();
+            let mut stack = vec![(root, neighbors.into_iter())];
+
+            while let Some((_, neighbors)) = stack.last_mut() {
+                match neighbors.next() {
+                    Some(next) => {
+                        if visited.insert(next.clone()) {
+                            let neighbors = forward.neighbors(&next).unwrap_or_default();
+                            stack.push((next, neighbors.into_iter()));
+                        }
+                    }
+                    None => order.extend(stack.pop().map(|(note, _)| note)),
+                }
+            }
+        }
+
+        let mut assigned = HashSet::new();
+        let mut cycles = Vec::new();
+
+        for root in order.into_iter().rev() {
+            if !assigned.insert(root.clone()) {
+                continue;
+            }
+
+            let mut component = vec![root.clone()];
+            let mut stack = vec![root];
+
+            while let Some(note) = stack.pop() {
+                for previous in backward.neighbors(&note).unwrap_or_default() {
+                    if assigned.insert(previous.clone()) {
+                        component.push(previous.clone());
+                        stack.push(previous);
+                    }
+                }
+            }
+
+            if let Some(start) = component.into_iter().min() {
+                cycles.extend(self.cycle_through(&start));
+            }
+        }
+
+        cycles.sort();
+        cycles
+    }
+
+    ///
+    /// [`cycle_through`] finds the shortest cycle going through the note, if there is one.
+    ///
+    fn cycle_through(&self, start: &NoteId) -> Option<Vec<NoteId>> {
+        let traversal = Traversal::new(self);
+
+        traversal
+            .neighbors(start)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|next| traversal.shortest_path(&next, start).ok().flatten())
+            .min_by_key(|path| path.len())
+            .map(|mut path| {
+                path.pop();
+                path.insert(0, start.clone());
+                path
+            })
+    }
+
+    ///
+    /// [`ensure_acyclic`] checks, in strict mode, that an edge from `from` to `to` does not close
+    /// a cycle. The error names the cycle the edge would create, starting at `from`.
+    ///
+    pub(super) fn ensure_acyclic(&self, from: &NoteId, to: &NoteId) -> Result<(), AddError> {
+        if !self.acyclic {
+            return Ok(());
+        }
+
+        match Traversal::new(self).shortest_path(to, from).ok().flatten() {
+            Some(mut path) => {
+                path.pop();
+                path.insert(0, from.clone());
+                Err(Report::new(AddError::CycleDetected(path)))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::AddError;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote};
+    use crate::test_util::{id, note};
+
+    #[test]
+    fn test_find_cycles() {
+        let mut manager = super::super::NotesManager::default();
+        for note_id in ["a", "b", "c", "d", "e"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+
+        assert!(manager.find_cycles().is_empty());
+
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("c"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("c"), branch, id("a"), "option".to_string())
+            .unwrap();
+        manager
+            .add_link(id("c"), id("d"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("e"), id("e"), "reason".to_string())
+            .unwrap();
+
+        assert_eq!(
+            manager.find_cycles(),
+            vec![vec![id("a"), id("b"), id("c")], vec![id("e")]]
+        );
+    }
+
+    #[test]
+    fn test_acyclic_mode() {
+        let mut manager = super::super::NotesManager::default();
+        manager.set_acyclic(true);
+        for note_id in ["a", "b", "c"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("a"), id("c"), "reason".to_string())
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .add_link(id("c"), id("a"), "reason".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::CycleDetected(vec![id("c"), id("a")])
+        );
+        assert_eq!(
+            manager
+                .add_link(id("b"), id("b"), "reason".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::CycleDetected(vec![id("b")])
+        );
+
+        // The two halves of a bidirectional link always form a cycle.
+        manager.add_note(note("d")).unwrap();
+        assert_eq!(
+            manager
+                .add_bidirectional_link(id("c"), id("d"), "reason".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::CycleDetected(vec![id("c"), id("d")])
+        );
+
+        let branch = manager
+            .create_branching(id("c"), "condition".to_string())
+            .unwrap();
+        assert_eq!(
+            manager
+                .add_branch(id("c"), branch, id("b"), "option".to_string())
+                .unwrap_err()
+                .current_context(),
+            &AddError::CycleDetected(vec![id("c"), id("b")])
+        );
+        assert!(manager.find_cycles().is_empty());
+    }
+}
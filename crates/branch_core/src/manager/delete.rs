@@ -1,35 +1,157 @@
 use crate::errors;
-use crate::manager_impl::{DeleteBranch, DeleteLink, DeleteNote};
+use crate::manager_impl::{DeleteBranch, DeleteLink, DeleteNote, DeleteTag};
+use crate::types::{DeletionPolicy, DeletionReport, FLink, NoteId};
 
-impl DeleteNote for super::NotesManager {
-    fn delete_note(
+impl super::NotesManager {
+    ///
+    /// [`set_deletion_policy`] sets what happens to a note when a deletion removes the last link
+    /// pointing to it. [`DeleteNote`], [`DeleteLink`] and [`DeleteBranch`] all follow it.
+    ///
+    pub fn set_deletion_policy(&mut self, policy: DeletionPolicy) {
+        self.deletion = policy;
+    }
+
+    pub fn deletion_policy(&self) -> DeletionPolicy {
+        self.deletion
+    }
+
+    ///
+    /// [`preview_deletion`] runs a deletion without applying it, and reports what it would remove.
+    /// The deletion fails the same way it would if it was applied.
+    ///
+    /// ```rust
+    /// use branch_core::prelude::*;
+    ///
+    /// let mut manager = NotesManager::default();
+    /// let from = manager
+    ///     .add_note(Note::new("from".to_string(), None, String::new()))
+    ///     .unwrap();
+    /// let to = manager
+    ///     .add_note(Note::new("to".to_string(), None, String::new()))
+    ///     .unwrap();
+    /// manager.add_link(from.clone(), to.clone(), "reason".to_string()).unwrap();
+    ///
+    /// let report = manager
+    ///     .preview_deletion(|manager| manager.delete_link(from.clone(), to.clone()))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(report.notes, vec![to.clone()]);
+    /// assert_eq!(report.links[0].note, from);
+    /// assert!(manager.read_note(to).is_ok());
+    /// ```
+    ///
+    pub fn preview_deletion(
         &mut self,
-        note: crate::types::NoteId,
+        deletion: impl FnOnce(&mut Self) -> error_stack::Result<(), errors::DeleteError>,
+    ) -> error_stack::Result<DeletionReport, errors::DeleteError> {
+        self.rolled_back(|manager| {
+            let (notes, links) = manager.untrashed(deletion)?;
+
+            Ok(DeletionReport {
+                notes: notes.iter().map(|note| note.get_id()).collect(),
+                links,
+            })
+        })
+    }
+
+    ///
+    /// [`release`] removes a backlink after a link or branch option from `from_note` was removed,
+    /// and applies the policy if it was the last one. A target that is already gone, or that is
+    /// the note being deleted, is left alone.
+    ///
+    fn release(
+        &mut self,
+        from_note: &NoteId,
+        to_note: &NoteId,
+        policy: DeletionPolicy,
     ) -> error_stack::Result<(), errors::DeleteError> {
-        let note_ = self
-            .notes
-            .get(&note)
-            .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+        if from_note == to_note {
+            return Ok(());
+        }
 
-        if note_.backlinks.is_empty() {
-            for flink in note_.forwardlinks.clone() {
-                match flink {
-                    crate::types::FLink::Link(link) => {
-                        self.delete_link(note.clone(), link.id.clone())?;
-                    }
-                    crate::types::FLink::Branch(branch) => {
-                        self.delete_branch(note.clone(), branch.get_id())?;
-                    }
+        let Some(to_note_) = self.note_mut(to_note) else {
+            return Ok(());
+        };
+
+        if to_note_.delete_backlink_once(from_note) > 0 {
+            return Ok(());
+        }
+
+        match policy {
+            DeletionPolicy::KeepOrphans => Ok(()),
+            DeletionPolicy::CascadeOrphans => self.delete_note(to_note.clone()),
+            DeletionPolicy::RefuseOrphans => Err(error_stack::Report::new(
+                errors::DeleteError::WouldOrphan(to_note.clone()),
+            )),
+        }
+    }
+
+    ///
+    /// [`unlink`] removes the forward link from `from_note` to `to_note`, and its other half if
+    /// it is bidirectional. The removed links are returned as `(from, to)` pairs, the requested
+    /// one first, their backlinks are left to the caller.
+    ///
+    pub(super) fn unlink(&mut self, from_note: &NoteId, to_note: &NoteId) -> Vec<(NoteId, NoteId)> {
+        let Some(link) = self
+            .note_mut(from_note)
+            .and_then(|note| note.remove_link(to_note))
+        else {
+            return Vec::new();
+        };
+
+        let mut removed = vec![(from_note.clone(), to_note.clone())];
+
+        if link.bidirectional
+            && from_note != to_note
+            && self
+                .note_mut(to_note)
+                .and_then(|note| note.remove_link(from_note))
+                .is_some()
+        {
+            removed.push((to_note.clone(), from_note.clone()));
+        }
+
+        removed
+    }
+}
+
+impl DeleteNote for super::NotesManager {
+    ///
+    /// [`delete_note`] removes the note along with the links and branch options pointing to it.
+    /// The notes it linked to are subject to the deletion policy.
+    ///
+    fn delete_note(&mut self, note: NoteId) -> error_stack::Result<(), errors::DeleteError> {
+        self.trashing(|manager| {
+            let note_ = manager
+                .notes
+                .get(&note)
+                .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+
+            let mut backlinks = note_.backlinks.clone();
+            backlinks.sort();
+            backlinks.dedup();
+
+            let targets = note_
+                .forwardlinks
+                .iter()
+                .flat_map(|flink| flink.targets())
+                .map(|link| link.id.clone())
+                .collect::<Vec<_>>();
+
+            for backlink in backlinks {
+                if let Some(backlink) = manager.note_mut(&backlink) {
+                    remove_links_to(&mut backlink.forwardlinks, &note);
                 }
             }
-            self.notes.remove(&note);
-        } else {
-            for backlink in note_.backlinks.clone() {
-                self.delete_link(backlink.clone(), note.clone())?;
+
+            manager.remove_note(&note);
+
+            for target in targets {
+                manager.release(&note, &target, manager.deletion)?;
             }
-        }
 
-        Ok(())
+            Ok(())
+        })
     }
 }
 
@@ -39,27 +161,31 @@ impl DeleteLink for super::NotesManager {
         from_note: crate::types::NoteId,
         to_note: crate::types::NoteId,
     ) -> error_stack::Result<(), errors::DeleteError> {
-        let from_note_ = self
-            .notes
-            .get_mut(&from_note)
-            .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+        self.trashing(|manager| {
+            error_stack::ensure!(
+                manager.notes.contains_key(&to_note),
+                errors::DeleteError::NoteDoesNotExist
+            );
 
-        from_note_.forwardlinks.retain(|flink| match flink {
-            crate::types::FLink::Link(link) => link.id != to_note,
-            crate::types::FLink::Branch(_) => true,
-        });
+            error_stack::ensure!(
+                manager.notes.contains_key(&from_note),
+                errors::DeleteError::NoteDoesNotExist
+            );
 
-        let links = self
-            .notes
-            .get_mut(&to_note)
-            .ok_or(errors::DeleteError::NoteDoesNotExist)?
-            .delete_backlink(&from_note);
+            let removed = manager.unlink(&from_note, &to_note);
 
-        if links == 0 {
-            self.delete_note(to_note)?;
-        }
+            // The note the link is deleted from is not subject to the policy through the other
+            // half of a bidirectional link.
+            for (from, to) in removed.iter().skip(1) {
+                manager.release(from, to, DeletionPolicy::KeepOrphans)?;
+            }
 
-        Ok(())
+            if !removed.is_empty() {
+                manager.release(&from_note, &to_note, manager.deletion)?;
+            }
+
+            Ok(())
+        })
     }
 }
 
@@ -69,38 +195,37 @@ impl DeleteBranch for super::NotesManager {
         note: crate::types::NoteId,
         branch: crate::types::BranchId,
     ) -> error_stack::Result<(), errors::DeleteError> {
-        let note_ = self
-            .notes
-            .get_mut(&note)
-            .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+        self.trashing(|manager| {
+            let branch_ = manager
+                .notes
+                .get(&note)
+                .ok_or(errors::DeleteError::NoteDoesNotExist)?
+                .branch(&branch)
+                .cloned()
+                .ok_or(errors::DeleteError::BranchDoesNotExist)?;
 
-        let branch_ = note_
-            .forwardlinks
-            .iter()
-            .find_map(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => {
-                    if branch_.get_id() == branch {
-                        Some(branch_)
-                    } else {
-                        None
+            // The options are deleted one by one first, so that the policy applies to the notes
+            // they lead to. Nested branches are deleted the same way.
+            for option in branch_.branches {
+                match option {
+                    crate::types::FLink::Link(link) => {
+                        manager.delete_branch_link(note.clone(), branch.clone(), link.id)?;
+                    }
+                    crate::types::FLink::Branch(sub_branch) => {
+                        manager.delete_branch(note.clone(), sub_branch.get_id())?;
                     }
                 }
-                crate::types::FLink::Link(_) => None,
-            })
-            .ok_or(errors::DeleteError::BranchDoesNotExist)?;
-
-        if branch_.branches.is_empty() {
-            note_.forwardlinks.retain(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => branch_.get_id() != branch,
-                crate::types::FLink::Link(_) => true,
-            });
-        } else {
-            for link in branch_.branches.clone() {
-                self.delete_branch_link(note.clone(), branch.clone(), link.id.clone())?;
             }
-        }
 
-        Ok(())
+            manager
+                .note_mut(&note)
+                .ok_or(errors::DeleteError::NoteDoesNotExist)?
+                .branch_siblings_mut(&branch)
+                .ok_or(errors::DeleteError::BranchDoesNotExist)?
+                .retain(|flink| !flink.is_branch(&branch));
+
+            Ok(())
+        })
     }
 
     fn delete_branch_link(
@@ -108,36 +233,278 @@ impl DeleteBranch for super::NotesManager {
         note: crate::types::NoteId,
         branch: crate::types::BranchId,
         link_note: crate::types::NoteId,
+    ) -> error_stack::Result<(), errors::DeleteError> {
+        self.trashing(|manager| {
+            manager.remove_branch_link(note, branch, link_note, manager.deletion)
+        })
+    }
+}
+
+impl super::NotesManager {
+    ///
+    /// [`remove_branch_link`] removes an option of a branch, applying `policy` to the note it led
+    /// to. Collapsing a branch keeps the discarded notes whatever the deletion policy is.
+    ///
+    pub(super) fn remove_branch_link(
+        &mut self,
+        note: NoteId,
+        branch: crate::types::BranchId,
+        link_note: NoteId,
+        policy: DeletionPolicy,
     ) -> error_stack::Result<(), errors::DeleteError> {
         let note_ = self
-            .notes
-            .get_mut(&note)
+            .note_mut(&note)
             .ok_or(errors::DeleteError::NoteDoesNotExist)?;
 
         let branch_ = note_
-            .forwardlinks
-            .iter_mut()
-            .find_map(|flink| match flink {
-                crate::types::FLink::Branch(branch_) => {
-                    if branch_.get_id() == branch {
-                        Some(branch_)
-                    } else {
-                        None
-                    }
-                }
-                crate::types::FLink::Link(_) => None,
-            })
+            .branch_mut(&branch)
             .ok_or(errors::DeleteError::BranchDoesNotExist)?;
 
-        branch_.branches.retain(|link| link.id != link_note);
+        let options = branch_.branches.len();
 
-        let link_note_ = self
-            .notes
-            .get_mut(&link_note)
-            .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+        branch_.branches.retain(|option| match option {
+            crate::types::FLink::Link(link) => link.id != link_note,
+            crate::types::FLink::Branch(_) => true,
+        });
+
+        let removed = branch_.branches.len() < options;
 
-        link_note_.delete_backlink(&note);
+        error_stack::ensure!(
+            self.notes.contains_key(&link_note),
+            errors::DeleteError::NoteDoesNotExist
+        );
+
+        // The note can still be linked from elsewhere in the note, through a direct link or an
+        // option of another branch, so only the backlink of this option is removed.
+        if removed {
+            self.release(&note, &link_note, policy)?;
+        }
 
         Ok(())
     }
 }
+
+impl DeleteTag for super::NotesManager {
+    fn remove_tag(
+        &mut self,
+        note: crate::types::NoteId,
+        tag: crate::types::Tag,
+    ) -> error_stack::Result<(), errors::DeleteError> {
+        self.transaction(|manager| {
+            let note = manager
+                .note_mut(&note)
+                .ok_or(errors::DeleteError::NoteDoesNotExist)?;
+
+            error_stack::ensure!(note.tags.remove(&tag), errors::DeleteError::TagDoesNotExist);
+
+            Ok(())
+        })
+    }
+}
+
+///
+/// [`remove_links_to`] removes the links leading to a note, the options of nested branches
+/// included. The branches themselves are kept.
+///
+fn remove_links_to(options: &mut Vec<FLink>, note: &NoteId) {
+    options.retain(|option| !matches!(option, FLink::Link(link) if &link.id == note));
+
+    for option in options {
+        if let FLink::Branch(branch) = option {
+            remove_links_to(&mut branch.branches, note);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeLink, ReadLink, ReadNote, Trash};
+    use crate::test_util::{id, note};
+    use crate::types;
+
+    ///
+    /// a -> b -> c
+    /// a -> d
+    /// a: condition? d | e
+    ///
+    fn graph() -> (super::super::NotesManager, types::BranchId) {
+        let mut manager = super::super::NotesManager::default();
+        for note_id in ["a", "b", "c", "d", "e"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("a"), id("d"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("d"), "d".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("e"), "e".to_string())
+            .unwrap();
+
+        (manager, branch)
+    }
+
+    fn note_ids(manager: &super::super::NotesManager) -> Vec<NoteId> {
+        manager
+            .sorted_notes()
+            .into_iter()
+            .map(|note| note.get_id())
+            .collect()
+    }
+
+    #[test]
+    fn test_cascade_orphans() {
+        let (mut manager, branch) = graph();
+
+        // `d` is still linked directly, `e` loses its only backlink.
+        manager
+            .delete_branch_link(id("a"), branch.clone(), id("d"))
+            .unwrap();
+        manager
+            .delete_branch_link(id("a"), branch, id("e"))
+            .unwrap();
+        assert_eq!(manager.list_backlinks(id("d")).unwrap(), vec![&id("a")]);
+
+        // Deleting a note removes the links to it and cascades through the notes it linked to.
+        manager.delete_note(id("b")).unwrap();
+        assert_eq!(note_ids(&manager), vec![id("a"), id("d")]);
+        assert_eq!(manager.list_pure_links(id("a")).unwrap()[0].id, id("d"));
+
+        // Deleting a link that is not there changes nothing.
+        manager.delete_link(id("a"), id("a")).unwrap();
+        assert_eq!(note_ids(&manager), vec![id("a"), id("d")]);
+    }
+
+    #[test]
+    fn test_keep_orphans() {
+        let (mut manager, branch) = graph();
+        manager.set_deletion_policy(DeletionPolicy::KeepOrphans);
+
+        manager
+            .delete_branch_link(id("a"), branch, id("e"))
+            .unwrap();
+        manager.delete_note(id("b")).unwrap();
+
+        assert_eq!(note_ids(&manager), vec![id("a"), id("c"), id("d"), id("e")]);
+        assert!(manager.read_note(id("c")).unwrap().backlinks.is_empty());
+        assert!(manager.read_note(id("e")).unwrap().backlinks.is_empty());
+    }
+
+    #[test]
+    fn test_refuse_orphans() {
+        let (mut manager, branch) = graph();
+        manager.set_deletion_policy(DeletionPolicy::RefuseOrphans);
+        let before = manager.notes.clone();
+
+        assert_eq!(
+            manager
+                .delete_link(id("a"), id("b"))
+                .unwrap_err()
+                .current_context(),
+            &errors::DeleteError::WouldOrphan(id("b"))
+        );
+        assert_eq!(
+            manager.delete_note(id("b")).unwrap_err().current_context(),
+            &errors::DeleteError::WouldOrphan(id("c"))
+        );
+        assert_eq!(
+            manager
+                .delete_branch(id("a"), branch.clone())
+                .unwrap_err()
+                .current_context(),
+            &errors::DeleteError::WouldOrphan(id("e"))
+        );
+        assert_eq!(manager.notes, before);
+        assert!(manager.list_trash().unwrap().is_empty());
+
+        // `d` keeps the direct link, so its option can go.
+        manager
+            .delete_branch_link(id("a"), branch, id("d"))
+            .unwrap();
+        manager.delete_note(id("c")).unwrap();
+        assert_eq!(note_ids(&manager), vec![id("a"), id("b"), id("d"), id("e")]);
+    }
+
+    #[test]
+    fn test_bidirectional_link() {
+        let (mut manager, _) = graph();
+        manager.add_note(note("f")).unwrap();
+
+        manager
+            .add_bidirectional_link(id("c"), id("f"), "related".to_string())
+            .unwrap();
+        manager
+            .change_link_relation(id("f"), id("c"), Some(types::Relation::depends_on()))
+            .unwrap();
+        manager
+            .change_link_weight(id("c"), id("f"), Some(0.5))
+            .unwrap();
+
+        let link = manager.read_note(id("f")).unwrap().link(&id("c")).unwrap();
+        let other = manager.read_note(id("c")).unwrap().link(&id("f")).unwrap();
+        assert_eq!(link.weight, Some(0.5));
+        assert_eq!(
+            other.relation,
+            Some(types::Relation::new("blocks", "depends-on"))
+        );
+        assert_eq!(&other.mirrored(id("c")), link);
+
+        // Deleting either half removes both, `f` is orphaned and the policy applies to it, not to
+        // the note the link was deleted from.
+        manager.delete_link(id("c"), id("f")).unwrap();
+
+        assert_eq!(
+            note_ids(&manager),
+            vec![id("a"), id("b"), id("c"), id("d"), id("e")]
+        );
+        assert!(manager.list_pure_links(id("c")).unwrap().is_empty());
+        assert_eq!(manager.list_backlinks(id("c")).unwrap(), vec![&id("b")]);
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_preview_deletion() {
+        let (mut manager, branch) = graph();
+        let before = manager.notes.clone();
+
+        let report = manager
+            .preview_deletion(|manager| manager.delete_link(id("a"), id("b")))
+            .unwrap();
+
+        assert_eq!(report.notes, vec![id("b"), id("c")]);
+        assert_eq!(report.links.len(), 1);
+        assert_eq!(report.links[0].note, id("a"));
+        assert_eq!(report.links[0].position, 0);
+
+        let report = manager
+            .preview_deletion(|manager| manager.delete_branch(id("a"), branch.clone()))
+            .unwrap();
+
+        assert_eq!(report.notes, vec![id("e")]);
+        assert_eq!(report.links.len(), 1);
+        assert!(report.links[0].link.is_branch(&branch));
+
+        manager.set_deletion_policy(DeletionPolicy::RefuseOrphans);
+        assert_eq!(
+            manager
+                .preview_deletion(|manager| manager.delete_note(id("b")))
+                .unwrap_err()
+                .current_context(),
+            &errors::DeleteError::WouldOrphan(id("c"))
+        );
+
+        assert_eq!(manager.notes, before);
+        assert!(manager.list_trash().unwrap().is_empty());
+    }
+}
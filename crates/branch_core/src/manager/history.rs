@@ -0,0 +1,226 @@
+use std::collections::{HashMap, VecDeque};
+
+use error_stack::{ensure, Result};
+
+use crate::errors::HistoryError;
+use crate::types::{Note, NoteId, TrashEntry, TrashId};
+
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+///
+/// [`Revision`] holds the state of every note and trash entry touched by a single mutation, as it
+/// was before the mutation. `None` means it did not exist. Restoring a revision is the inverse of
+/// the mutation, no matter how far it cascaded.
+///
+#[derive(Default)]
+pub(super) struct Revision {
+    pub(super) notes: HashMap<NoteId, Option<Note>>,
+    pub(super) trash: HashMap<TrashId, Option<TrashEntry>>,
+}
+
+impl Revision {
+    pub(super) fn is_empty(&self) -> bool {
+        self.notes.is_empty() && self.trash.is_empty()
+    }
+}
+
+pub(super) struct History {
+    undo: VecDeque<Revision>,
+    redo: Vec<Revision>,
+    depth: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth: DEFAULT_HISTORY_DEPTH,
+        }
+    }
+}
+
+impl History {
+    pub(super) fn push(&mut self, revision: Revision) {
+        self.redo.clear();
+
+        if self.depth == 0 {
+            return;
+        }
+
+        self.undo.push_back(revision);
+
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl super::NotesManager {
+    pub(super) fn restore(&mut self, revision: Revision) -> Revision {
+        let notes = revision
+            .notes
+            .into_iter()
+            .map(|(note_id, before)| {
+                let after = match before {
+                    Some(note) => self.notes.insert(note_id.clone(), note),
+                    None => self.notes.remove(&note_id),
+                };
+                (note_id, after)
+            })
+            .collect();
+
+        let trash = revision
+            .trash
+            .into_iter()
+            .map(|(entry_id, before)| (entry_id, self.trash.replace(entry_id, before)))
+            .collect();
+
+        Revision { notes, trash }
+    }
+
+    ///
+    /// [`undo`] reverts the last mutation, including everything it cascaded into.
+    ///
+    pub fn undo(&mut self) -> Result<(), HistoryError> {
+        let revision = self.history.undo.pop_back();
+
+        ensure!(revision.is_some(), HistoryError::NothingToUndo);
+
+        let inverse = self.restore(revision.unwrap_or_default());
+        self.reindex(inverse.notes.keys());
+        self.history.redo.push(inverse);
+
+        Ok(())
+    }
+
+    ///
+    /// [`redo`] applies the last undone mutation again. Any new mutation clears the redo stack.
+    ///
+    pub fn redo(&mut self) -> Result<(), HistoryError> {
+        let revision = self.history.redo.pop();
+
+        ensure!(revision.is_some(), HistoryError::NothingToRedo);
+
+        let inverse = self.restore(revision.unwrap_or_default());
+        self.reindex(inverse.notes.keys());
+        self.history.undo.push_back(inverse);
+
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    ///
+    /// [`set_history_depth`] sets how many mutations can be undone, dropping the oldest ones if
+    /// the history is already deeper. A depth of zero disables the history.
+    ///
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history.depth = depth;
+
+        while self.history.undo.len() > depth {
+            self.history.undo.pop_front();
+        }
+    }
+
+    pub fn history_depth(&self) -> usize {
+        self.history.depth
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.undo.clear();
+        self.history.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::HistoryError;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeBranch, ChangeNote, DeleteLink};
+    use crate::test_util::{id, note};
+
+    #[test]
+    fn test_undo_cascaded_delete() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+
+        let before = manager.notes.clone();
+
+        manager.delete_link(id("a"), id("b")).unwrap();
+
+        assert_eq!(manager.notes.len(), 1);
+
+        manager.undo().unwrap();
+        assert_eq!(manager.notes, before);
+
+        manager.redo().unwrap();
+        assert_eq!(manager.notes.len(), 1);
+        assert_eq!(
+            manager.redo().unwrap_err().current_context(),
+            &HistoryError::NothingToRedo
+        );
+    }
+
+    #[test]
+    fn test_undo_collapse_branch() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("b"), "b".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("c"), "c".to_string())
+            .unwrap();
+
+        let before = manager.notes.clone();
+
+        manager.collapse_branch(id("a"), branch, id("b")).unwrap();
+        manager.undo().unwrap();
+
+        assert_eq!(manager.notes, before);
+    }
+
+    #[test]
+    fn test_history_depth() {
+        let mut manager = super::super::NotesManager::default();
+        manager.set_history_depth(2);
+        manager.add_note(note("a")).unwrap();
+
+        for title in ["b", "c", "d"] {
+            manager
+                .change_note_title(id("a"), title.to_string())
+                .unwrap();
+        }
+
+        manager.undo().unwrap();
+        manager.undo().unwrap();
+
+        assert_eq!(manager.notes[&id("a")].title, "b");
+        assert_eq!(
+            manager.undo().unwrap_err().current_context(),
+            &HistoryError::NothingToUndo
+        );
+
+        assert!(manager.change_note_title(id("z"), "z".to_string()).is_err());
+        assert!(manager.can_redo());
+    }
+}
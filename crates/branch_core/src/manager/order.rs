@@ -0,0 +1,254 @@
+use error_stack::{Report, Result};
+
+use crate::errors::ChangeError;
+use crate::types::{BranchId, FLink, NoteId};
+
+impl super::NotesManager {
+    ///
+    /// [`reorder_forwardlinks`] changes the order of the forward links of a note. The links are
+    /// taken out of the note while `reorder` runs, so it can look at the other notes.
+    ///
+    pub(super) fn reorder_forwardlinks(
+        &mut self,
+        note: &NoteId,
+        reorder: impl FnOnce(&Self, &mut Vec<FLink>) -> std::result::Result<(), ChangeError>,
+    ) -> Result<(), ChangeError> {
+        self.transaction(|manager| {
+            let note_ = manager
+                .note_mut(note)
+                .ok_or(ChangeError::NoteDoesNotExist)?;
+            let mut options = std::mem::take(&mut note_.forwardlinks);
+
+            let output = reorder(manager, &mut options);
+
+            if let Some(note_) = manager.note_mut(note) {
+                note_.forwardlinks = options;
+            }
+
+            output.map_err(Report::new)
+        })
+    }
+
+    ///
+    /// [`reorder_branch_options`] changes the order of the options of a branch, the same way
+    /// [`reorder_forwardlinks`] does for a note.
+    ///
+    pub(super) fn reorder_branch_options(
+        &mut self,
+        note: &NoteId,
+        branch: &BranchId,
+        reorder: impl FnOnce(&Self, &mut Vec<FLink>) -> std::result::Result<(), ChangeError>,
+    ) -> Result<(), ChangeError> {
+        self.transaction(|manager| {
+            let branch_ = manager
+                .note_mut(note)
+                .ok_or(ChangeError::NoteDoesNotExist)?
+                .branch_mut(branch)
+                .ok_or(ChangeError::BranchDoesNotExist)?;
+            let mut options = std::mem::take(&mut branch_.branches);
+
+            let output = reorder(manager, &mut options);
+
+            if let Some(branch_) = manager
+                .note_mut(note)
+                .and_then(|note_| note_.branch_mut(branch))
+            {
+                branch_.branches = options;
+            }
+
+            output.map_err(Report::new)
+        })
+    }
+
+    ///
+    /// [`sort_fields`] gives what links are sorted by from the note they point to, its title and
+    /// whether it is in a terminal state.
+    ///
+    pub(super) fn sort_fields(&self, note: &NoteId) -> Option<(String, bool)> {
+        self.notes
+            .get(note)
+            .map(|note| (note.title.clone(), self.states.is_terminal(&note.state)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ChangeError;
+    use crate::manager_impl::{
+        AddBranch, AddLink, AddNote, ChangeBranch, ChangeLink, ChangeNote, ReadLink, ReadNote,
+    };
+    use crate::test_util::{id, titled_note};
+    use crate::types::{self, FLinkId, SortKey};
+
+    fn order(manager: &super::super::NotesManager, note: &str) -> Vec<FLinkId> {
+        manager
+            .list_forwardlinks(id(note))
+            .unwrap()
+            .into_iter()
+            .map(types::FLink::flink_id)
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder_forwardlinks() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(titled_note("a", "Plan", "body")).unwrap();
+        manager.add_note(titled_note("b", "write", "body")).unwrap();
+        manager
+            .add_note(titled_note("c", "Design", "body"))
+            .unwrap();
+        manager
+            .add_note(titled_note("d", "Review", "body"))
+            .unwrap();
+
+        manager.add_link(id("a"), id("b"), "2".to_string()).unwrap();
+        let branch = manager
+            .create_branching(id("a"), "ready".to_string())
+            .unwrap();
+        manager.add_link(id("a"), id("c"), "1".to_string()).unwrap();
+        manager.add_link(id("a"), id("d"), "3".to_string()).unwrap();
+
+        let link = |note: &str| FLinkId::Link(id(note));
+        let branching = FLinkId::Branch(branch.clone());
+
+        manager.move_forwardlink(id("a"), link("d"), 0).unwrap();
+        assert_eq!(
+            order(&manager, "a"),
+            vec![link("d"), link("b"), branching.clone(), link("c")]
+        );
+
+        manager
+            .swap_forwardlinks(id("a"), link("b"), link("c"))
+            .unwrap();
+        assert_eq!(
+            order(&manager, "a"),
+            vec![link("d"), link("c"), branching.clone(), link("b")]
+        );
+
+        manager.sort_forwardlinks(id("a"), SortKey::Title).unwrap();
+        assert_eq!(
+            order(&manager, "a"),
+            vec![link("c"), branching.clone(), link("d"), link("b")]
+        );
+
+        manager.sort_forwardlinks(id("a"), SortKey::Reason).unwrap();
+        assert_eq!(
+            order(&manager, "a"),
+            vec![link("c"), link("b"), link("d"), branching.clone()]
+        );
+
+        // The empty branch does not lead to any closed note, so it stays with the open ones.
+        manager.mark_note(id("c")).unwrap();
+        manager.sort_forwardlinks(id("a"), SortKey::State).unwrap();
+        assert_eq!(
+            order(&manager, "a"),
+            vec![link("b"), link("d"), branching, link("c")]
+        );
+
+        assert_eq!(
+            manager
+                .move_forwardlink(id("a"), link("b"), 4)
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidPosition
+        );
+        assert_eq!(
+            manager
+                .swap_forwardlinks(id("a"), link("b"), link("a"))
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::LinkDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_reorder_branch_options() {
+        let mut manager = super::super::NotesManager::default();
+        for (note_id, title) in [("a", "a"), ("b", "b"), ("c", "c"), ("d", "d")] {
+            manager
+                .add_note(titled_note(note_id, title, "body"))
+                .unwrap();
+        }
+
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("c"), "c".to_string())
+            .unwrap();
+        let nested = manager
+            .create_sub_branching(id("a"), branch.clone(), "nested".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("b"), "b".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), nested.clone(), id("d"), "d".to_string())
+            .unwrap();
+
+        let options = |manager: &super::super::NotesManager| {
+            manager
+                .read_note(id("a"))
+                .unwrap()
+                .branch(&branch)
+                .unwrap()
+                .branches
+                .iter()
+                .map(types::FLink::flink_id)
+                .collect::<Vec<_>>()
+        };
+
+        manager
+            .move_branch_option(id("a"), branch.clone(), FLinkId::Branch(nested.clone()), 2)
+            .unwrap();
+        assert_eq!(
+            options(&manager),
+            vec![
+                FLinkId::Link(id("c")),
+                FLinkId::Link(id("b")),
+                FLinkId::Branch(nested.clone())
+            ]
+        );
+
+        manager
+            .sort_branch_options(id("a"), branch.clone(), SortKey::Title)
+            .unwrap();
+        assert_eq!(
+            options(&manager),
+            vec![
+                FLinkId::Link(id("b")),
+                FLinkId::Link(id("c")),
+                FLinkId::Branch(nested.clone())
+            ]
+        );
+
+        manager
+            .swap_branch_options(
+                id("a"),
+                branch.clone(),
+                FLinkId::Link(id("b")),
+                FLinkId::Branch(nested.clone()),
+            )
+            .unwrap();
+        assert_eq!(
+            options(&manager),
+            vec![
+                FLinkId::Branch(nested.clone()),
+                FLinkId::Link(id("c")),
+                FLinkId::Link(id("b"))
+            ]
+        );
+
+        // Undoing puts the previous order back.
+        manager.undo().unwrap();
+        assert_eq!(options(&manager)[0], FLinkId::Link(id("b")));
+
+        assert_eq!(
+            manager
+                .move_branch_option(id("a"), nested, FLinkId::Link(id("c")), 0)
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::LinkDoesNotExist
+        );
+    }
+}
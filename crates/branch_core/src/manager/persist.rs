@@ -0,0 +1,81 @@
+use error_stack::ensure;
+
+use crate::errors::StorageError;
+use crate::storage::Storage;
+use crate::types::{Note, ReconsileReport, Settings};
+
+impl super::NotesManager {
+    ///
+    /// [`load`] creates a manager from the notes, the trash and the settings in the storage,
+    /// rebuilding the backlinks from the forward links of every note.
+    ///
+    pub fn load(storage: &impl Storage) -> error_stack::Result<Self, StorageError> {
+        let mut manager = Self::from_notes(storage.load_notes()?)?;
+        manager.apply_settings(storage.load_settings()?);
+        manager.load_trash(storage.load_trash()?);
+
+        Ok(manager)
+    }
+
+    ///
+    /// [`save`] writes every note of the manager, the trash and the settings to the storage.
+    ///
+    pub fn save(&self, storage: &mut impl Storage) -> error_stack::Result<(), StorageError> {
+        storage.save_notes(&self.sorted_notes())?;
+        storage.save_trash(&self.trash.entries())?;
+        storage.save_settings(&self.settings())
+    }
+
+    pub fn settings(&self) -> Settings {
+        Settings {
+            acyclic: self.acyclic,
+            deletion_policy: self.deletion,
+            revision_retention: self.revision_retention(),
+            trash_retention: self.trash_retention(),
+            history_depth: self.history_depth(),
+            task_states: self.states.clone(),
+        }
+    }
+
+    ///
+    /// [`apply_settings`] changes every setting of the manager at once, the same way their own
+    /// setters do.
+    ///
+    pub fn apply_settings(&mut self, settings: Settings) {
+        self.set_acyclic(settings.acyclic);
+        self.set_deletion_policy(settings.deletion_policy);
+        self.set_revision_retention(settings.revision_retention);
+        self.set_trash_retention(settings.trash_retention);
+        self.set_history_depth(settings.history_depth);
+        self.set_task_states(settings.task_states);
+    }
+
+    pub(crate) fn from_notes(notes: Vec<Note>) -> error_stack::Result<Self, StorageError> {
+        let mut manager = Self::default();
+
+        for note in notes {
+            let note_id = note.get_id();
+
+            ensure!(
+                !manager.notes.contains_key(&note_id),
+                StorageError::DuplicateNote
+            );
+
+            manager.notes.insert(note_id, note);
+        }
+
+        manager.rebuild_backlinks(&mut ReconsileReport::default());
+
+        let note_ids = manager.notes.keys().cloned().collect::<Vec<_>>();
+        manager.reindex(&note_ids);
+
+        Ok(manager)
+    }
+
+    pub(crate) fn sorted_notes(&self) -> Vec<&Note> {
+        self.sorted_note_ids()
+            .iter()
+            .map(|note_id| &self.notes[note_id])
+            .collect()
+    }
+}
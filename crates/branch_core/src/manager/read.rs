@@ -0,0 +1,508 @@
+use crate::errors::ReadError;
+use std::collections::BTreeSet;
+
+use crate::manager_impl::{ReadBranch, ReadLink, ReadNote, ReadTag};
+use crate::types;
+
+impl super::NotesManager {
+    ///
+    /// [`find_branch`] looks up a branch across all the notes, nested branches included, returning
+    /// the owning note id along with the branch itself.
+    ///
+    pub(crate) fn find_branch(
+        &self,
+        branch: &types::BranchId,
+    ) -> Option<(&types::NoteId, &types::Branch)> {
+        self.notes
+            .iter()
+            .find_map(|(note_id, note)| Some((note_id, note.branch(branch)?)))
+    }
+}
+
+impl ReadNote for super::NotesManager {
+    fn read_note(&self, note: types::NoteId) -> error_stack::Result<&types::Note, ReadError> {
+        Ok(self.notes.get(&note).ok_or(ReadError::NoteDoesNotExist)?)
+    }
+
+    fn list_root_notes(&self) -> error_stack::Result<Vec<&types::NoteId>, ReadError> {
+        Ok(self
+            .notes
+            .iter()
+            .filter(|(_, note)| note.backlinks.is_empty())
+            .map(|(note_id, _)| note_id)
+            .collect())
+    }
+
+    fn query_notes_by_date(
+        &self,
+        query: &types::DateQuery,
+    ) -> error_stack::Result<Vec<&types::Note>, ReadError> {
+        let mut notes = self
+            .notes
+            .values()
+            .filter(|note| query.matches(note))
+            .collect::<Vec<_>>();
+
+        notes.sort_by_key(|note| (note.date(query.sort), note.get_id()));
+
+        Ok(notes)
+    }
+
+    fn list_revisions(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::NoteRevision>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note.revisions.iter().collect())
+    }
+
+    fn diff_revisions(
+        &self,
+        note: types::NoteId,
+        from: usize,
+        to: usize,
+    ) -> error_stack::Result<types::RevisionDiff, ReadError> {
+        let note = self.read_note(note)?;
+
+        let from = note
+            .revision_content(from)
+            .ok_or(ReadError::RevisionDoesNotExist)?;
+        let to = note
+            .revision_content(to)
+            .ok_or(ReadError::RevisionDoesNotExist)?;
+
+        Ok(super::revisions::diff(from, to))
+    }
+}
+
+impl ReadLink for super::NotesManager {
+    fn list_forwardlinks(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::FLink>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note.forwardlinks.iter().collect())
+    }
+
+    fn list_unmarked_forwardlinks(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::FLink>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note.open_forwardlinks(|note_id| self.is_open(note_id)))
+    }
+
+    fn list_pure_links(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::Link>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note
+            .forwardlinks
+            .iter()
+            .filter_map(|flink| match flink {
+                types::FLink::Link(link) => Some(link),
+                types::FLink::Branch(_) => None,
+            })
+            .collect())
+    }
+
+    fn list_backlinks(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::NoteId>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note.backlinks.iter().collect())
+    }
+
+    fn list_forwardlinks_by_relation(
+        &self,
+        note: types::NoteId,
+        relation: &types::Relation,
+    ) -> error_stack::Result<Vec<&types::Link>, ReadError> {
+        Ok(self
+            .list_pure_links(note)?
+            .into_iter()
+            .filter(|link| link.has_relation(relation))
+            .collect())
+    }
+
+    fn list_backlinks_by_relation(
+        &self,
+        note: types::NoteId,
+        relation: &types::Relation,
+    ) -> error_stack::Result<Vec<&types::NoteId>, ReadError> {
+        let note_ = self.read_note(note.clone())?;
+
+        // A note links directly to another note at most once, its other backlinks come from
+        // branch options.
+        let mut seen = BTreeSet::new();
+
+        Ok(note_
+            .backlinks
+            .iter()
+            .filter(|backlink| seen.insert(*backlink))
+            .filter(|backlink| {
+                self.notes
+                    .get(*backlink)
+                    .and_then(|backlink| backlink.link(&note))
+                    .is_some_and(|link| link.has_relation(relation))
+            })
+            .collect())
+    }
+}
+
+impl ReadBranch for super::NotesManager {
+    fn list_branches(
+        &self,
+        note: types::NoteId,
+    ) -> error_stack::Result<Vec<&types::BranchId>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note
+            .forwardlinks
+            .iter()
+            .filter_map(|flink| match flink {
+                types::FLink::Branch(branch) => Some(branch.id()),
+                types::FLink::Link(_) => None,
+            })
+            .collect())
+    }
+
+    fn list_branch_links(
+        &self,
+        branch: types::BranchId,
+    ) -> error_stack::Result<Vec<&types::Link>, ReadError> {
+        let (_, branch) = self
+            .find_branch(&branch)
+            .ok_or(ReadError::BranchDoesNotExist)?;
+
+        Ok(branch.links().collect())
+    }
+
+    fn find_branch_note(
+        &self,
+        branch: types::BranchId,
+    ) -> error_stack::Result<&types::NoteId, ReadError> {
+        let (note_id, _) = self
+            .find_branch(&branch)
+            .ok_or(ReadError::BranchDoesNotExist)?;
+
+        Ok(note_id)
+    }
+
+    fn list_sub_branches(
+        &self,
+        branch: types::BranchId,
+    ) -> error_stack::Result<Vec<&types::BranchId>, ReadError> {
+        let (_, branch) = self
+            .find_branch(&branch)
+            .ok_or(ReadError::BranchDoesNotExist)?;
+
+        Ok(branch.sub_branches().map(|branch| branch.id()).collect())
+    }
+
+    fn find_branch_path(
+        &self,
+        branch: types::BranchId,
+    ) -> error_stack::Result<Vec<types::BranchId>, ReadError> {
+        let (note_id, _) = self
+            .find_branch(&branch)
+            .ok_or(ReadError::BranchDoesNotExist)?;
+
+        Ok(self.notes[note_id]
+            .branch_path(&branch)
+            .ok_or(ReadError::BranchDoesNotExist)?)
+    }
+}
+
+impl ReadTag for super::NotesManager {
+    fn list_tags(&self, note: types::NoteId) -> error_stack::Result<Vec<&types::Tag>, ReadError> {
+        let note = self.read_note(note)?;
+
+        Ok(note.tags.iter().collect())
+    }
+
+    fn list_tagged_notes(
+        &self,
+        tag: types::Tag,
+    ) -> error_stack::Result<Vec<&types::NoteId>, ReadError> {
+        Ok(self.tags.tagged(&tag).into_iter().collect())
+    }
+
+    fn find_notes_by_tags(
+        &self,
+        include: &[types::Tag],
+        exclude: &[types::Tag],
+    ) -> error_stack::Result<Vec<&types::NoteId>, ReadError> {
+        let mut notes = match include.split_first() {
+            Some((first, rest)) => rest.iter().fold(self.tags.tagged(first), |notes, tag| {
+                notes
+                    .intersection(&self.tags.tagged(tag))
+                    .copied()
+                    .collect()
+            }),
+            None => self.notes.keys().collect::<BTreeSet<_>>(),
+        };
+
+        for tag in exclude {
+            let excluded = self.tags.tagged(tag);
+            notes.retain(|note_id| !excluded.contains(note_id));
+        }
+
+        Ok(notes.into_iter().collect())
+    }
+
+    fn tag_counts(&self) -> error_stack::Result<Vec<(&types::Tag, usize)>, ReadError> {
+        Ok(self.tags.counts())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{AddError, ChangeError};
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeLink, ChangeNote};
+    use crate::test_util::{id, note};
+
+    #[test]
+    fn test_read_missing_note() {
+        let manager = super::super::NotesManager::default();
+
+        let result = manager.read_note(id("00000"));
+
+        assert_eq!(
+            result.err().unwrap().current_context(),
+            &ReadError::NoteDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_list_links_and_roots() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("c"), "option".to_string())
+            .unwrap();
+
+        assert_eq!(manager.list_root_notes().unwrap(), vec![&id("a")]);
+        assert_eq!(manager.list_forwardlinks(id("a")).unwrap().len(), 2);
+        assert_eq!(manager.list_pure_links(id("a")).unwrap().len(), 1);
+        assert_eq!(manager.list_backlinks(id("c")).unwrap(), vec![&id("a")]);
+        assert_eq!(manager.list_branches(id("a")).unwrap(), vec![&branch]);
+        assert_eq!(manager.find_branch_note(branch.clone()).unwrap(), &id("a"));
+        assert_eq!(manager.list_branch_links(branch).unwrap()[0].id, id("c"));
+
+        manager.mark_note(id("b")).unwrap();
+
+        let unmarked = manager.list_unmarked_forwardlinks(id("a")).unwrap();
+        assert_eq!(unmarked.len(), 1);
+        assert!(matches!(unmarked[0], types::FLink::Branch(_)));
+    }
+
+    #[test]
+    fn test_links_by_relation() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+
+        manager
+            .add_typed_link(
+                id("a"),
+                id("b"),
+                types::Relation::depends_on(),
+                "needs the schema".to_string(),
+            )
+            .unwrap();
+        manager
+            .add_typed_link(id("c"), id("b"), types::Relation::supports(), String::new())
+            .unwrap();
+        manager
+            .add_link(id("a"), id("c"), "reason".to_string())
+            .unwrap();
+
+        let depends_on = manager
+            .list_forwardlinks_by_relation(id("a"), &types::Relation::depends_on())
+            .unwrap();
+        assert_eq!(depends_on.len(), 1);
+        assert_eq!(depends_on[0].reason, "needs the schema");
+        assert_eq!(
+            manager
+                .list_backlinks_by_relation(id("b"), &types::Relation::supports())
+                .unwrap(),
+            vec![&id("c")]
+        );
+
+        let result = manager.add_typed_link(
+            id("b"),
+            id("c"),
+            types::Relation::new("blocks", " "),
+            String::new(),
+        );
+        assert_eq!(
+            result.err().unwrap().current_context(),
+            &AddError::InvalidRelation
+        );
+
+        manager
+            .change_link_relation(id("a"), id("b"), None)
+            .unwrap();
+
+        assert!(manager
+            .list_backlinks_by_relation(id("b"), &types::Relation::depends_on())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_link_metadata() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+
+        manager
+            .change_link_weight(id("a"), id("b"), Some(2.5))
+            .unwrap();
+        manager
+            .change_link_annotation(id("a"), id("b"), "source".into(), Some("review".into()))
+            .unwrap();
+        manager
+            .change_link_annotation(id("a"), id("b"), "owner".into(), Some("me".into()))
+            .unwrap();
+        manager
+            .change_link_annotation(id("a"), id("b"), "owner".into(), None)
+            .unwrap();
+
+        let link = manager.list_pure_links(id("a")).unwrap()[0];
+        assert_eq!(link.weight, Some(2.5));
+        assert_eq!(
+            link.annotations.iter().collect::<Vec<_>>(),
+            vec![(&"source".to_string(), &"review".to_string())]
+        );
+        assert!(!link.bidirectional);
+
+        assert_eq!(
+            manager
+                .change_link_weight(id("a"), id("b"), Some(f64::NAN))
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidWeight
+        );
+        assert_eq!(
+            manager
+                .change_link_annotation(id("a"), id("b"), " ".into(), None)
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidAnnotation
+        );
+        assert_eq!(
+            manager
+                .change_link_weight(id("b"), id("a"), None)
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::LinkDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_missing_branch() {
+        let manager = super::super::NotesManager::default();
+
+        let result = manager.list_branch_links(types::BranchId::new_test("0".to_string()));
+
+        assert_eq!(
+            result.err().unwrap().current_context(),
+            &ReadError::BranchDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_query_notes_by_date() {
+        use crate::manager_impl::ChangeLink;
+        use time::{Duration, OffsetDateTime};
+
+        let day = |day: i64| OffsetDateTime::UNIX_EPOCH + Duration::days(day);
+
+        let mut manager = super::super::NotesManager::default();
+        for (note_id, created) in [("a", 1), ("b", 2), ("c", 3)] {
+            let mut note = note(note_id);
+            note.created = day(created);
+            note.modified = day(created);
+            manager.add_note(note).unwrap();
+        }
+
+        manager.pin_clock(Some(day(10)));
+        manager
+            .add_link(id("a"), id("c"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("b"), "condition".to_string())
+            .unwrap();
+
+        manager.pin_clock(Some(day(20)));
+        manager
+            .add_branch(id("b"), branch.clone(), id("a"), "option".to_string())
+            .unwrap();
+        // Setting the reason it already has is not a change.
+        manager
+            .change_link_reason(id("a"), id("c"), "reason".to_string())
+            .unwrap();
+
+        let a = manager.read_note(id("a")).unwrap();
+        assert_eq!((a.created, a.modified), (day(1), day(10)));
+        let c = manager.read_note(id("c")).unwrap();
+        assert_eq!(c.modified, day(3));
+
+        let b = manager.read_note(id("b")).unwrap();
+        let branch = b.branch(&branch).unwrap();
+        assert_eq!(b.modified, day(20));
+        assert_eq!((branch.created, branch.modified), (day(10), day(20)));
+        assert_eq!(branch.links().next().unwrap().created, day(20));
+
+        let ids = |notes: Vec<&types::Note>| {
+            notes
+                .into_iter()
+                .map(|note| note.get_id())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            ids(manager
+                .query_notes_by_date(&types::DateQuery::modified().from(day(10)))
+                .unwrap()),
+            vec![id("a"), id("b")]
+        );
+        assert_eq!(
+            ids(manager
+                .query_notes_by_date(&types::DateQuery::created().from(day(2)).to(day(3)))
+                .unwrap()),
+            vec![id("b")]
+        );
+        assert_eq!(
+            ids(manager
+                .query_notes_by_date(
+                    &types::DateQuery::created().sorted_by(types::DateField::Modified)
+                )
+                .unwrap()),
+            vec![id("c"), id("a"), id("b")]
+        );
+    }
+}
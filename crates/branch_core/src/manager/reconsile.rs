@@ -0,0 +1,486 @@
+use std::collections::HashMap;
+
+use crate::types::{Branch, FLink, Link, NoteId, ReconsileFix, ReconsileReport};
+
+impl super::NotesManager {
+    ///
+    /// [`sorted_note_ids`] returns the note ids in a stable order, so that passes over the graph
+    /// produce the same report every time.
+    ///
+    pub(crate) fn sorted_note_ids(&self) -> Vec<NoteId> {
+        let mut ids = self.notes.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    ///
+    /// [`drop_dangling_links`] removes forward links and branch options whose target note does
+    /// not exist.
+    ///
+    pub(super) fn drop_dangling_links(&mut self, report: &mut ReconsileReport) {
+        for note_id in self.sorted_note_ids() {
+            let Some(mut forwardlinks) = self
+                .note_mut(&note_id)
+                .map(|note| std::mem::take(&mut note.forwardlinks))
+            else {
+                continue;
+            };
+
+            forwardlinks.retain_mut(|flink| match flink {
+                FLink::Link(link) => {
+                    let exists = self.notes.contains_key(&link.id);
+                    if !exists {
+                        report.fixes.push(ReconsileFix::DroppedLink {
+                            note: note_id.clone(),
+                            target: link.id.clone(),
+                        });
+                    }
+                    exists
+                }
+                FLink::Branch(branch) => {
+                    self.drop_dangling_options(&note_id, branch, report);
+                    true
+                }
+            });
+
+            if let Some(note) = self.note_mut(&note_id) {
+                note.forwardlinks = forwardlinks;
+            }
+        }
+    }
+
+    fn drop_dangling_options(
+        &self,
+        note_id: &NoteId,
+        branch: &mut Branch,
+        report: &mut ReconsileReport,
+    ) {
+        let branch_id = branch.get_id();
+
+        branch.branches.retain_mut(|option| match option {
+            FLink::Link(link) => {
+                let exists = self.notes.contains_key(&link.id);
+                if !exists {
+                    report.fixes.push(ReconsileFix::DroppedBranchOption {
+                        note: note_id.clone(),
+                        branch: branch_id.clone(),
+                        target: link.id.clone(),
+                    });
+                }
+                exists
+            }
+            FLink::Branch(sub_branch) => {
+                self.drop_dangling_options(note_id, sub_branch, report);
+                true
+            }
+        });
+    }
+
+    ///
+    /// [`remove_empty_branches`] removes branches that do not have any options left. Nested
+    /// branches are handled first, so a branch whose only options were empty branches is removed
+    /// as well.
+    ///
+    pub(super) fn remove_empty_branches(&mut self, report: &mut ReconsileReport) {
+        for note_id in self.sorted_note_ids() {
+            let Some(note) = self.note_mut(&note_id) else {
+                continue;
+            };
+
+            remove_empty_branches_in(&note_id, &mut note.forwardlinks, report);
+        }
+    }
+
+    ///
+    /// [`pair_bidirectional_links`] adds the other half of the bidirectional links that lost it.
+    /// A plain link going back is turned into the other half.
+    ///
+    pub(super) fn pair_bidirectional_links(&mut self, report: &mut ReconsileReport) {
+        for note_id in self.sorted_note_ids() {
+            let links = self.notes[&note_id]
+                .forwardlinks
+                .iter()
+                .filter_map(|flink| match flink {
+                    FLink::Link(link) if link.bidirectional => Some(link.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            for link in links {
+                let Some(target) = self.notes.get(&link.id) else {
+                    continue;
+                };
+
+                if target
+                    .link(&note_id)
+                    .is_some_and(|other| other.bidirectional)
+                {
+                    continue;
+                }
+
+                let mirrored = link.mirrored(note_id.clone());
+                let Some(target) = self.note_mut(&link.id) else {
+                    continue;
+                };
+
+                match target.link_mut(&note_id) {
+                    Some(other) => {
+                        *other = Link {
+                            created: other.created,
+                            modified: other.modified,
+                            ..mirrored
+                        }
+                    }
+                    None => target.forwardlinks.push(FLink::Link(mirrored)),
+                }
+
+                report.fixes.push(ReconsileFix::PairedLink {
+                    note: link.id,
+                    target: note_id.clone(),
+                });
+            }
+        }
+    }
+
+    ///
+    /// [`rebuild_backlinks`] recomputes the backlinks of every note from the forward links that
+    /// point to it. Notes that had backlinks before but have none left are returned as orphans,
+    /// the caller applies the deletion policy to them the way `delete_link` does.
+    ///
+    pub(super) fn rebuild_backlinks(&mut self, report: &mut ReconsileReport) -> Vec<NoteId> {
+        let mut expected: HashMap<NoteId, Vec<NoteId>> = HashMap::new();
+
+        for note_id in self.sorted_note_ids() {
+            let note = &self.notes[&note_id];
+            for flink in &note.forwardlinks {
+                match flink {
+                    FLink::Link(link) => expected
+                        .entry(link.id.clone())
+                        .or_default()
+                        .push(note_id.clone()),
+                    FLink::Branch(branch) => {
+                        for link in branch.targets() {
+                            expected
+                                .entry(link.id.clone())
+                                .or_default()
+                                .push(note_id.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut orphans = Vec::new();
+
+        for note_id in self.sorted_note_ids() {
+            let expected = expected.remove(&note_id).unwrap_or_default();
+            let Some(note) = self.note_mut(&note_id) else {
+                continue;
+            };
+
+            let mut current = note.backlinks.clone();
+            current.sort();
+            let mut wanted = expected.clone();
+            wanted.sort();
+
+            if current == wanted {
+                continue;
+            }
+
+            let added = difference(&wanted, &current);
+            let removed = difference(&current, &wanted);
+
+            if !note.backlinks.is_empty() && expected.is_empty() {
+                orphans.push(note_id.clone());
+            }
+
+            note.backlinks = expected;
+
+            report.fixes.push(ReconsileFix::RebuiltBacklinks {
+                note: note_id,
+                added,
+                removed,
+            });
+        }
+
+        orphans
+    }
+
+    ///
+    /// [`propagate_done`] moves every open note whose forward links all lead to closed notes to
+    /// the done state, when the move is allowed. This is repeated until nothing changes, so the
+    /// notes are completed upwards through the graph.
+    ///
+    pub(super) fn propagate_done(&mut self, report: &mut ReconsileReport) {
+        let done = self.states.done().clone();
+
+        loop {
+            let to_complete = self
+                .sorted_note_ids()
+                .into_iter()
+                .filter(|note_id| {
+                    let note = &self.notes[note_id];
+                    !self.states.is_terminal(&note.state)
+                        && self.states.allows(&note.state, &done)
+                        && !note.forwardlinks.is_empty()
+                        && note.forwardlinks.iter().all(|flink| {
+                            flink.targets().iter().all(|link| self.is_closed(&link.id))
+                        })
+                })
+                .collect::<Vec<_>>();
+
+            if to_complete.is_empty() {
+                break;
+            }
+
+            let at = self.transaction_time();
+
+            for note_id in to_complete {
+                if let Some(note) = self.note_mut(&note_id) {
+                    note.change_state(done.clone(), at);
+                }
+                report
+                    .fixes
+                    .push(ReconsileFix::CompletedNote { note: note_id });
+            }
+        }
+    }
+
+    fn is_closed(&self, note: &NoteId) -> bool {
+        self.notes
+            .get(note)
+            .is_some_and(|note| self.states.is_terminal(&note.state))
+    }
+}
+
+fn remove_empty_branches_in(
+    note_id: &NoteId,
+    flinks: &mut Vec<FLink>,
+    report: &mut ReconsileReport,
+) {
+    flinks.retain_mut(|flink| match flink {
+        FLink::Branch(branch) => {
+            remove_empty_branches_in(note_id, &mut branch.branches, report);
+
+            let empty = branch.branches.is_empty();
+            if empty {
+                report.fixes.push(ReconsileFix::RemovedEmptyBranch {
+                    note: note_id.clone(),
+                    branch: branch.get_id(),
+                });
+            }
+            !empty
+        }
+        FLink::Link(_) => true,
+    });
+}
+
+///
+/// [`difference`] computes the multiset difference `left - right` of two sorted lists.
+///
+fn difference(left: &[NoteId], right: &[NoteId]) -> Vec<NoteId> {
+    let mut right = right.to_vec();
+    left.iter()
+        .filter(|id| match right.iter().position(|other| other == *id) {
+            Some(index) => {
+                right.remove(index);
+                false
+            }
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ChangeError;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeNote, ReadLink, Trash};
+    use crate::test_util::{id, note};
+    use crate::types::{self, DeletionPolicy, FLink, ReconsileFix};
+
+    #[test]
+    fn test_reconsile_clean_graph() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+
+        let report = manager.reconsile_nodes().unwrap();
+
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_reconsile_pairs_bidirectional_links() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager
+            .add_bidirectional_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+
+        manager
+            .notes
+            .get_mut(&id("b"))
+            .unwrap()
+            .remove_link(&id("a"));
+
+        let report = manager.reconsile_nodes().unwrap();
+
+        assert_eq!(
+            report.fixes,
+            vec![ReconsileFix::PairedLink {
+                note: id("b"),
+                target: id("a"),
+            }]
+        );
+        assert_eq!(
+            manager.notes[&id("b")]
+                .link(&id("a"))
+                .map(|link| link.bidirectional),
+            Some(true)
+        );
+        assert_eq!(manager.list_backlinks(id("a")).unwrap(), vec![&id("b")]);
+    }
+
+    #[test]
+    fn test_reconsile_dangling_and_orphans() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+
+        manager.notes.remove(&id("a"));
+
+        let report = manager.reconsile_nodes().unwrap();
+
+        assert!(manager.notes.is_empty());
+        let entries = manager
+            .list_trash()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            report.fixes,
+            vec![
+                ReconsileFix::RebuiltBacklinks {
+                    note: id("b"),
+                    added: vec![],
+                    removed: vec![id("a")],
+                },
+                ReconsileFix::RemovedOrphan { note: id("b") },
+                ReconsileFix::RebuiltBacklinks {
+                    note: id("c"),
+                    added: vec![],
+                    removed: vec![id("b")],
+                },
+                ReconsileFix::RemovedOrphan { note: id("c") },
+            ]
+        );
+
+        // `b` links to `c`, so the later entry is restored first.
+        for entry in entries.into_iter().rev() {
+            manager.restore_from_trash(entry).unwrap();
+        }
+        assert_eq!(manager.list_backlinks(id("c")).unwrap(), vec![&id("b")]);
+        assert!(manager.list_backlinks(id("b")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reconsile_follows_the_deletion_policy() {
+        let graph = |policy| {
+            let mut manager = super::super::NotesManager::default();
+            manager.set_deletion_policy(policy);
+            for note_id in ["a", "b", "c"] {
+                manager.add_note(note(note_id)).unwrap();
+            }
+            manager
+                .add_link(id("a"), id("b"), "reason".to_string())
+                .unwrap();
+            manager
+                .add_link(id("b"), id("c"), "reason".to_string())
+                .unwrap();
+            manager.notes.remove(&id("a"));
+            manager
+        };
+
+        let mut manager = graph(DeletionPolicy::KeepOrphans);
+        let report = manager.reconsile_nodes().unwrap();
+        assert_eq!(
+            report.fixes,
+            vec![ReconsileFix::RebuiltBacklinks {
+                note: id("b"),
+                added: vec![],
+                removed: vec![id("a")],
+            }]
+        );
+        assert_eq!(manager.list_backlinks(id("c")).unwrap(), vec![&id("b")]);
+        assert!(manager.list_trash().unwrap().is_empty());
+
+        let mut manager = graph(DeletionPolicy::RefuseOrphans);
+        assert_eq!(
+            manager.reconsile_nodes().unwrap_err().current_context(),
+            &ChangeError::WouldOrphan(id("b"))
+        );
+        assert_eq!(manager.list_backlinks(id("b")).unwrap(), vec![&id("a")]);
+    }
+
+    #[test]
+    fn test_reconsile_marks() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager.add_note(note("c")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("b"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("b"), branch, id("c"), "option".to_string())
+            .unwrap();
+        manager
+            .notes
+            .get_mut(&id("b"))
+            .unwrap()
+            .forwardlinks
+            .push(FLink::Link(types::Link::new(
+                id("missing"),
+                "reason".to_string(),
+            )));
+        manager.mark_note(id("c")).unwrap();
+
+        let report = manager.reconsile_nodes().unwrap();
+
+        assert_eq!(
+            report.fixes,
+            vec![
+                ReconsileFix::DroppedLink {
+                    note: id("b"),
+                    target: id("missing"),
+                },
+                ReconsileFix::CompletedNote { note: id("b") },
+                ReconsileFix::CompletedNote { note: id("a") },
+            ]
+        );
+        assert!(manager
+            .list_unmarked_forwardlinks(id("a"))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use error_stack::Result;
+
+use crate::errors::ChangeError;
+use crate::types::{DiffLine, Note, NoteId, RevisionDiff};
+
+///
+/// [`DEFAULT_REVISION_RETENTION`] is the number of revisions kept for every note unless it is
+/// changed with `set_revision_retention`.
+///
+pub const DEFAULT_REVISION_RETENTION: usize = 50;
+
+///
+/// [`Retention`] is the number of revisions kept for every note.
+///
+pub(super) struct Retention(usize);
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention(DEFAULT_REVISION_RETENTION)
+    }
+}
+
+impl super::NotesManager {
+    ///
+    /// [`set_revision_retention`] sets how many revisions are kept for every note, dropping the
+    /// oldest ones of notes that already have more. A retention of zero disables revisions.
+    ///
+    pub fn set_revision_retention(&mut self, retention: usize) {
+        self.retention = Retention(retention);
+
+        for note in self.notes.values_mut() {
+            note.trim_revisions(retention);
+        }
+    }
+
+    pub fn revision_retention(&self) -> usize {
+        self.retention.0
+    }
+
+    ///
+    /// [`edit_note`] applies an edit to the title, subtitle or body of a note. When the content
+    /// changes, the previous content is kept as a revision.
+    ///
+    pub(super) fn edit_note(
+        &mut self,
+        note: &NoteId,
+        edit: impl FnOnce(&mut Note),
+    ) -> Result<(), ChangeError> {
+        let retention = self.retention.0;
+        let at = self.transaction_time();
+        let note = self.note_mut(note).ok_or(ChangeError::NoteDoesNotExist)?;
+
+        let before = note.snapshot(at);
+
+        edit(note);
+
+        if before.title != note.title
+            || before.subtitle != note.subtitle
+            || before.body != note.body
+        {
+            note.revisions.push(before);
+            note.trim_revisions(retention);
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// [`diff`] compares the fields of two revisions, given as title, subtitle and body.
+///
+pub(super) fn diff(
+    from: (&str, Option<&str>, &str),
+    to: (&str, Option<&str>, &str),
+) -> RevisionDiff {
+    RevisionDiff {
+        title: diff_lines(from.0, to.0),
+        subtitle: diff_lines(from.1.unwrap_or_default(), to.1.unwrap_or_default()),
+        body: diff_lines(from.2, to.2),
+    }
+}
+
+///
+/// [`diff_lines`] computes a line-level diff from the longest common subsequence of the lines.
+/// Within a change, removed lines come before added ones.
+///
+fn diff_lines(from: &str, to: &str) -> Vec<DiffLine> {
+    let from = from.lines().collect::<Vec<_>>();
+    let to = to.lines().collect::<Vec<_>>();
+
+    // common[i][j] is the length of the longest common subsequence of from[i..] and to[j..].
+    let mut common = vec![vec![0; to.len() + 1]; from.len() + 1];
+
+    for i in (0..from.len()).rev() {
+        for j in (0..to.len()).rev() {
+            common[i][j] = if from[i] == to[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(from.len().max(to.len()));
+    let (mut i, mut j) = (0, 0);
+
+    while i < from.len() || j < to.len() {
+        if i < from.len() && j < to.len() && from[i] == to[j] {
+            lines.push(DiffLine::Unchanged(from[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < from.len() && (j == to.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(from[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(to[j].to_string()));
+            j += 1;
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::{ChangeError, ReadError};
+    use crate::manager_impl::{AddNote, ChangeNote, ReadNote};
+    use crate::test_util::{id, note};
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(
+            diff_lines("a\nb\nc\nd", "a\nc\nx\nd\ne"),
+            vec![
+                DiffLine::Unchanged("a".to_string()),
+                DiffLine::Removed("b".to_string()),
+                DiffLine::Unchanged("c".to_string()),
+                DiffLine::Added("x".to_string()),
+                DiffLine::Unchanged("d".to_string()),
+                DiffLine::Added("e".to_string()),
+            ]
+        );
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn test_revisions() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+
+        manager
+            .change_note_body(id("a"), "first\nsecond".to_string())
+            .unwrap();
+        manager
+            .change_note_body(id("a"), "first\nsecond".to_string())
+            .unwrap();
+        manager
+            .change_note_title(id("a"), "title".to_string())
+            .unwrap();
+
+        let revisions = manager.list_revisions(id("a")).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].body, "body");
+        assert_eq!(revisions[1].title, "a");
+
+        let diff = manager.diff_revisions(id("a"), 0, 2).unwrap();
+        assert_eq!(
+            diff.title,
+            vec![
+                DiffLine::Removed("a".to_string()),
+                DiffLine::Added("title".to_string()),
+            ]
+        );
+        assert!(diff.subtitle.is_empty());
+        assert_eq!(diff.body.len(), 3);
+        assert_eq!(
+            manager
+                .diff_revisions(id("a"), 0, 3)
+                .unwrap_err()
+                .current_context(),
+            &ReadError::RevisionDoesNotExist
+        );
+
+        manager.restore_revision(id("a"), 0).unwrap();
+
+        let note = manager.read_note(id("a")).unwrap();
+        assert_eq!((note.title.as_str(), note.body.as_str()), ("a", "body"));
+        assert_eq!(note.revisions.len(), 3);
+        assert_eq!(note.revisions[2].title, "title");
+        assert_eq!(
+            manager
+                .restore_revision(id("a"), 4)
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::RevisionDoesNotExist
+        );
+
+        manager.set_revision_retention(1);
+        assert_eq!(manager.list_revisions(id("a")).unwrap()[0].title, "title");
+
+        manager
+            .change_note_subtitle(id("a"), "subtitle".to_string())
+            .unwrap();
+        assert_eq!(manager.list_revisions(id("a")).unwrap().len(), 1);
+        assert_eq!(manager.list_revisions(id("a")).unwrap()[0].subtitle, None);
+    }
+}
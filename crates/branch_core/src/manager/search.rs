@@ -0,0 +1,415 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use error_stack::{bail, ensure, Result};
+
+use crate::errors::SearchError;
+use crate::manager_impl::Search;
+use crate::types::{FLink, Note, NoteId, SearchHit};
+
+///
+/// Saturation of the term frequency, a note that repeats a word many times should not outrank
+/// every other note.
+///
+const SATURATION: f64 = 1.2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Title,
+    Subtitle,
+    Body,
+    Reason,
+    Condition,
+}
+
+impl Field {
+    fn weight(self) -> f64 {
+        match self {
+            Field::Title => 3.0,
+            Field::Subtitle => 2.0,
+            Field::Body => 1.0,
+            Field::Reason | Field::Condition => 0.5,
+        }
+    }
+}
+
+///
+/// [`SearchIndex`] is an inverted index over the text of every note. It maps each term to the
+/// notes containing it, and keeps the tokens of every note so that phrases can be matched without
+/// going back to the notes.
+///
+/// The index is updated note by note, see [`update`].
+///
+#[derive(Default)]
+pub(super) struct SearchIndex {
+    terms: BTreeMap<String, HashSet<NoteId>>,
+    documents: HashMap<NoteId, Vec<(Field, Vec<String>)>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Pattern {
+    Exact(String),
+    Prefix(String),
+}
+
+///
+/// A [`Clause`] is a sequence of patterns that must appear next to each other in one field. A
+/// single word is a clause of one pattern.
+///
+type Clause = Vec<Pattern>;
+
+///
+/// [`tokenize`] splits text on everything that is not a letter or a digit, in any script, and
+/// lowercases the words.
+///
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn fields(note: &Note) -> Vec<(Field, Vec<String>)> {
+    let mut fields = vec![(Field::Title, tokenize(&note.title))];
+
+    if let Some(subtitle) = &note.subtitle {
+        fields.push((Field::Subtitle, tokenize(subtitle)));
+    }
+
+    fields.push((Field::Body, tokenize(&note.body)));
+
+    for flink in &note.forwardlinks {
+        match flink {
+            FLink::Link(link) => fields.push((Field::Reason, tokenize(&link.reason))),
+            FLink::Branch(branch) => {
+                for branch in branch.walk() {
+                    fields.push((Field::Condition, tokenize(&branch.condition)));
+                    for link in branch.links() {
+                        fields.push((Field::Reason, tokenize(&link.reason)));
+                    }
+                }
+            }
+        }
+    }
+
+    fields.retain(|(_, tokens)| !tokens.is_empty());
+    fields
+}
+
+///
+/// [`parse_query`] reads a query made of words, `"quoted phrases"` and `prefix*` words. A word
+/// that tokenizes into several terms, like `e-mail`, is matched as a phrase.
+///
+fn parse_query(query: &str) -> Result<Vec<Clause>, SearchError> {
+    let mut clauses = Vec::new();
+    let mut rest = query;
+
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+
+        let (text, remaining) = match rest.strip_prefix('"') {
+            Some(phrase) => {
+                let Some(end) = phrase.find('"') else {
+                    bail!(SearchError::UnterminatedPhrase);
+                };
+                (&phrase[..end], &phrase[end + 1..])
+            }
+            None => {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        rest = remaining;
+
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let mut clause = Clause::new();
+
+        for (position, word) in words.iter().enumerate() {
+            let prefix = word.ends_with('*');
+            let mut tokens = tokenize(word);
+
+            let last = match tokens.pop() {
+                Some(last) => last,
+                None => continue,
+            };
+
+            clause.extend(tokens.into_iter().map(Pattern::Exact));
+            clause.push(if prefix && position + 1 == words.len() {
+                Pattern::Prefix(last)
+            } else {
+                Pattern::Exact(last)
+            });
+        }
+
+        if !clause.is_empty() {
+            clauses.push(clause);
+        }
+    }
+
+    ensure!(!clauses.is_empty(), SearchError::EmptyQuery);
+
+    Ok(clauses)
+}
+
+impl Pattern {
+    fn matches(&self, token: &str) -> bool {
+        match self {
+            Pattern::Exact(term) => token == term,
+            Pattern::Prefix(prefix) => token.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+///
+/// [`occurrences`] counts how many times the clause appears in a run of tokens.
+///
+fn occurrences(clause: &Clause, tokens: &[String]) -> usize {
+    tokens
+        .windows(clause.len())
+        .filter(|window| {
+            window
+                .iter()
+                .zip(clause)
+                .all(|(token, pattern)| pattern.matches(token))
+        })
+        .count()
+}
+
+impl SearchIndex {
+    ///
+    /// [`update`] replaces the indexed content of a single note, `None` removes the note from the
+    /// index.
+    ///
+    pub(super) fn update(&mut self, note_id: &NoteId, note: Option<&Note>) {
+        if let Some(fields) = self.documents.remove(note_id) {
+            for (_, tokens) in fields {
+                for token in tokens {
+                    if let Some(notes) = self.terms.get_mut(&token) {
+                        notes.remove(note_id);
+                        if notes.is_empty() {
+                            self.terms.remove(&token);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(note) = note {
+            let fields = fields(note);
+
+            for (_, tokens) in &fields {
+                for token in tokens {
+                    self.terms
+                        .entry(token.clone())
+                        .or_default()
+                        .insert(note_id.clone());
+                }
+            }
+
+            self.documents.insert(note_id.clone(), fields);
+        }
+    }
+
+    fn candidates(&self, pattern: &Pattern) -> HashSet<&NoteId> {
+        match pattern {
+            Pattern::Exact(term) => self
+                .terms
+                .get(term)
+                .map(|notes| notes.iter().collect())
+                .unwrap_or_default(),
+            Pattern::Prefix(prefix) => self
+                .terms
+                .range::<String, _>(prefix.clone()..)
+                .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                .flat_map(|(_, notes)| notes)
+                .collect(),
+        }
+    }
+
+    fn frequency(&self, note_id: &NoteId, clause: &Clause) -> f64 {
+        self.documents
+            .get(note_id)
+            .into_iter()
+            .flatten()
+            .map(|(field, tokens)| field.weight() * occurrences(clause, tokens) as f64)
+            .sum()
+    }
+
+    fn search(&self, clauses: &[Clause]) -> Vec<SearchHit> {
+        let mut candidates: Option<HashSet<&NoteId>> = None;
+
+        for pattern in clauses.iter().flatten() {
+            let matching = self.candidates(pattern);
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+
+        let mut scores = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .map(|note_id| (note_id, 0.0))
+            .collect::<HashMap<_, _>>();
+
+        let total = self.documents.len() as f64;
+
+        for clause in clauses {
+            let frequencies = scores
+                .keys()
+                .map(|note_id| (*note_id, self.frequency(note_id, clause)))
+                .collect::<Vec<_>>();
+
+            let found = frequencies.iter().filter(|(_, tf)| *tf > 0.0).count() as f64;
+            let idf = (1.0 + total / found.max(1.0)).ln();
+
+            for (note_id, tf) in frequencies {
+                if tf > 0.0 {
+                    if let Some(score) = scores.get_mut(note_id) {
+                        *score += idf * tf * (SATURATION + 1.0) / (tf + SATURATION);
+                    }
+                } else {
+                    scores.remove(note_id);
+                }
+            }
+        }
+
+        let mut hits = scores
+            .into_iter()
+            .map(|(note_id, score)| SearchHit {
+                note: note_id.clone(),
+                score,
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.note.cmp(&b.note))
+        });
+
+        hits
+    }
+}
+
+impl Search for super::NotesManager {
+    fn search(&self, query: &str) -> Result<Vec<SearchHit>, SearchError> {
+        let clauses = parse_query(query)?;
+
+        Ok(self.search.search(&clauses))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeNote, DeleteNote};
+    use crate::test_util::{id, titled_note};
+
+    fn ids(hits: Vec<SearchHit>) -> Vec<NoteId> {
+        hits.into_iter().map(|hit| hit.note).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Crème brûlée, ÜBER-cool 東京 v2!"),
+            vec!["crème", "brûlée", "über", "cool", "東京", "v2"]
+        );
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query(r#"rust "borrow checker" pre* e-mail"#).unwrap(),
+            vec![
+                vec![Pattern::Exact("rust".to_string())],
+                vec![
+                    Pattern::Exact("borrow".to_string()),
+                    Pattern::Exact("checker".to_string())
+                ],
+                vec![Pattern::Prefix("pre".to_string())],
+                vec![
+                    Pattern::Exact("e".to_string()),
+                    Pattern::Exact("mail".to_string())
+                ],
+            ]
+        );
+        assert_eq!(
+            parse_query("  ,, ").unwrap_err().current_context(),
+            &SearchError::EmptyQuery
+        );
+        assert_eq!(
+            parse_query(r#"rust "borrow"#)
+                .unwrap_err()
+                .current_context(),
+            &SearchError::UnterminatedPhrase
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut manager = super::super::NotesManager::default();
+        manager
+            .add_note(titled_note("a", "Rust ownership", "memory safety"))
+            .unwrap();
+        manager
+            .add_note(titled_note(
+                "b",
+                "Notes",
+                "ownership in rust, the borrow checker",
+            ))
+            .unwrap();
+        manager
+            .add_note(titled_note("c", "Checker", "a checker that borrows"))
+            .unwrap();
+        manager
+            .add_link(id("a"), id("b"), "prerequisite".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "if the borrow fails".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch, id("c"), "fallback".to_string())
+            .unwrap();
+
+        assert_eq!(
+            ids(manager.search("ownership RUST").unwrap()),
+            vec![id("a"), id("b")]
+        );
+        assert_eq!(
+            ids(manager.search("\"borrow checker\"").unwrap()),
+            vec![id("b")]
+        );
+        assert_eq!(
+            ids(manager.search("borrow*").unwrap()),
+            vec![id("b"), id("c"), id("a")]
+        );
+        assert_eq!(ids(manager.search("prereq*").unwrap()), vec![id("a")]);
+        assert!(manager.search("missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_index_follows_mutations() {
+        let mut manager = super::super::NotesManager::default();
+        manager
+            .add_note(titled_note("a", "title", "old body"))
+            .unwrap();
+
+        manager
+            .change_note_body(id("a"), "new body".to_string())
+            .unwrap();
+
+        assert!(manager.search("old").unwrap().is_empty());
+        assert_eq!(ids(manager.search("new").unwrap()), vec![id("a")]);
+
+        manager.undo().unwrap();
+
+        assert_eq!(ids(manager.search("old").unwrap()), vec![id("a")]);
+        assert!(manager.search("new").unwrap().is_empty());
+
+        manager.delete_note(id("a")).unwrap();
+
+        assert!(manager.search("title").unwrap().is_empty());
+        assert!(manager.search.terms.is_empty());
+    }
+}
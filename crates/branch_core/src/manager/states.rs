@@ -0,0 +1,179 @@
+use error_stack::{ensure, Result};
+
+use crate::errors::ChangeError;
+use crate::types::{NoteId, TaskState, TaskStates};
+
+impl super::NotesManager {
+    ///
+    /// [`set_task_states`] sets the states notes can be in and the moves allowed between them.
+    /// Notes keep the state they are in, a state the new configuration does not know can only be
+    /// left for the initial state.
+    ///
+    pub fn set_task_states(&mut self, states: TaskStates) {
+        self.states = states;
+    }
+
+    pub fn task_states(&self) -> &TaskStates {
+        &self.states
+    }
+
+    ///
+    /// [`is_open`] checks that a note exists and is not in a terminal state.
+    ///
+    pub(super) fn is_open(&self, note: &NoteId) -> bool {
+        self.notes
+            .get(note)
+            .is_some_and(|note| !self.states.is_terminal(&note.state))
+    }
+
+    ///
+    /// [`change_state`] moves a note to another state, if the move is allowed and, when the done
+    /// rule is enforced, the notes it links to are all closed. Moving a note to the state it is
+    /// already in changes nothing.
+    ///
+    pub(super) fn change_state(
+        &mut self,
+        note: &NoteId,
+        state: TaskState,
+    ) -> Result<(), ChangeError> {
+        let note_ = self.notes.get(note).ok_or(ChangeError::NoteDoesNotExist)?;
+
+        if note_.state == state {
+            return Ok(());
+        }
+
+        self.states.check_transition(&note_.state, &state)?;
+
+        ensure!(
+            !self.states.is_done_enforced()
+                || &state != self.states.done()
+                || note_.open_forwardlinks(|id| self.is_open(id)).is_empty(),
+            ChangeError::OpenForwardlinks
+        );
+
+        let at = self.transaction_time();
+        self.note_mut(note)
+            .ok_or(ChangeError::NoteDoesNotExist)?
+            .change_state(state, at);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ChangeError;
+    use crate::manager_impl::{AddLink, AddNote, ChangeNote, ReadLink, ReadNote};
+    use crate::test_util::{id, note};
+    use crate::types::{TaskState, TaskStates};
+
+    fn state(manager: &super::super::NotesManager, note: &str) -> TaskState {
+        manager.read_note(id(note)).unwrap().state.clone()
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        assert_eq!(state(&manager, "a"), TaskState::todo());
+
+        manager
+            .change_note_state(id("a"), TaskState::in_progress())
+            .unwrap();
+        manager
+            .change_note_state(id("a"), TaskState::blocked())
+            .unwrap();
+
+        assert_eq!(
+            manager
+                .change_note_state(id("a"), TaskState::done())
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::InvalidTransition {
+                from: TaskState::blocked(),
+                to: TaskState::done(),
+            }
+        );
+        assert_eq!(
+            manager
+                .change_note_state(id("a"), TaskState::new("archived"))
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::UnknownState(TaskState::new("archived"))
+        );
+
+        manager
+            .change_note_state(id("a"), TaskState::cancelled())
+            .unwrap();
+        manager.unmark_note(id("a")).unwrap();
+        manager.mark_note(id("a")).unwrap();
+
+        // Moving to the current state is not a change.
+        manager.mark_note(id("a")).unwrap();
+
+        let note = manager.read_note(id("a")).unwrap();
+        assert_eq!(note.state, TaskState::done());
+        assert_eq!(
+            note.state_history
+                .iter()
+                .map(|change| (change.from.as_str(), change.to.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("todo", "in-progress"),
+                ("in-progress", "blocked"),
+                ("blocked", "cancelled"),
+                ("cancelled", "todo"),
+                ("todo", "done"),
+            ]
+        );
+
+        // Undoing a move takes it out of the history as well.
+        manager.undo().unwrap();
+        let note = manager.read_note(id("a")).unwrap();
+        assert_eq!(note.state, TaskState::todo());
+        assert_eq!(note.state_history.len(), 4);
+    }
+
+    #[test]
+    fn test_custom_states() {
+        let open = TaskState::new("open");
+        let closed = TaskState::new("closed");
+
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+
+        manager.set_task_states(
+            TaskStates::new(open.clone(), closed.clone())
+                .with_transition(open.clone(), closed.clone())
+                .with_done_enforced(true),
+        );
+
+        // The notes are still in a state the configuration does not know, so they can only go to
+        // the initial one.
+        assert!(manager.mark_note(id("a")).is_err());
+        manager.unmark_note(id("a")).unwrap();
+        manager.unmark_note(id("b")).unwrap();
+
+        assert_eq!(
+            manager.mark_note(id("a")).unwrap_err().current_context(),
+            &ChangeError::OpenForwardlinks
+        );
+        assert_eq!(
+            manager.list_unmarked_forwardlinks(id("a")).unwrap().len(),
+            1
+        );
+
+        manager.mark_note(id("b")).unwrap();
+        assert!(manager
+            .list_unmarked_forwardlinks(id("a"))
+            .unwrap()
+            .is_empty());
+
+        manager.change_note_state(id("a"), closed.clone()).unwrap();
+        assert_eq!(state(&manager, "a"), closed);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::types::{Note, NoteId, Tag};
+
+///
+/// [`TagIndex`] maps every tag in use to the notes carrying it. Tags are kept sorted, so the tags
+/// under a given tag are found with a range scan.
+///
+/// The index is updated note by note, see [`update`].
+///
+#[derive(Default)]
+pub(super) struct TagIndex {
+    notes: BTreeMap<Tag, BTreeSet<NoteId>>,
+    tags: HashMap<NoteId, BTreeSet<Tag>>,
+}
+
+impl TagIndex {
+    ///
+    /// [`update`] replaces the indexed tags of a single note, `None` removes the note from the
+    /// index.
+    ///
+    pub(super) fn update(&mut self, note_id: &NoteId, note: Option<&Note>) {
+        for tag in self.tags.remove(note_id).unwrap_or_default() {
+            if let Some(notes) = self.notes.get_mut(&tag) {
+                notes.remove(note_id);
+                if notes.is_empty() {
+                    self.notes.remove(&tag);
+                }
+            }
+        }
+
+        if let Some(note) = note.filter(|note| !note.tags.is_empty()) {
+            for tag in &note.tags {
+                self.notes
+                    .entry(tag.clone())
+                    .or_default()
+                    .insert(note_id.clone());
+            }
+
+            self.tags.insert(note_id.clone(), note.tags.clone());
+        }
+    }
+
+    ///
+    /// [`tagged`] lists the notes carrying `tag` itself or one of its descendants.
+    ///
+    pub(super) fn tagged(&self, tag: &Tag) -> BTreeSet<&NoteId> {
+        self.notes
+            .range(tag..)
+            .take_while(|(tag_, _)| tag_.as_str().starts_with(tag.as_str()))
+            .filter(|(tag_, _)| tag_.is_under(tag))
+            .flat_map(|(_, notes)| notes)
+            .collect()
+    }
+
+    pub(super) fn counts(&self) -> Vec<(&Tag, usize)> {
+        self.notes
+            .iter()
+            .map(|(tag, notes)| (tag, notes.len()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{AddError, ChangeError, DeleteError};
+    use crate::manager_impl::{AddNote, AddTag, ChangeTag, DeleteNote, DeleteTag, ReadTag};
+    use crate::test_util::{id, note};
+    use crate::types;
+
+    fn tag(tag: &str) -> types::Tag {
+        tag.parse().unwrap()
+    }
+
+    #[test]
+    fn test_tag_queries() {
+        let mut manager = super::super::NotesManager::default();
+        for note_id in ["a", "b", "c", "d"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+
+        manager
+            .add_tag(id("a"), tag("project/backend/auth"))
+            .unwrap();
+        manager.add_tag(id("a"), tag("urgent")).unwrap();
+        manager.add_tag(id("b"), tag("project/backend")).unwrap();
+        manager.add_tag(id("c"), tag("project/frontend")).unwrap();
+        manager.add_tag(id("c"), tag("urgent")).unwrap();
+        manager.add_tag(id("d"), tag("projects")).unwrap();
+
+        assert_eq!(
+            manager
+                .add_tag(id("a"), tag("urgent"))
+                .unwrap_err()
+                .current_context(),
+            &AddError::TagAlreadyExists
+        );
+
+        assert_eq!(
+            manager.list_tagged_notes(tag("project")).unwrap(),
+            vec![&id("a"), &id("b"), &id("c")]
+        );
+        assert_eq!(
+            manager.list_tagged_notes(tag("project/backend")).unwrap(),
+            vec![&id("a"), &id("b")]
+        );
+        assert_eq!(
+            manager
+                .find_notes_by_tags(&[tag("project"), tag("urgent")], &[tag("project/backend")])
+                .unwrap(),
+            vec![&id("c")]
+        );
+        assert_eq!(
+            manager.find_notes_by_tags(&[], &[tag("project")]).unwrap(),
+            vec![&id("d")]
+        );
+        assert_eq!(
+            manager.tag_counts().unwrap(),
+            vec![
+                (&tag("project/backend"), 1),
+                (&tag("project/backend/auth"), 1),
+                (&tag("project/frontend"), 1),
+                (&tag("projects"), 1),
+                (&tag("urgent"), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rename_and_remove_tag() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("b")).unwrap();
+
+        manager.add_tag(id("a"), tag("project/backend")).unwrap();
+        manager.add_tag(id("a"), tag("work")).unwrap();
+        manager.add_tag(id("b"), tag("project")).unwrap();
+        manager.add_tag(id("b"), tag("projects")).unwrap();
+
+        manager.rename_tag(tag("project"), tag("work")).unwrap();
+
+        assert_eq!(
+            manager.list_tags(id("a")).unwrap(),
+            vec![&tag("work"), &tag("work/backend")]
+        );
+        assert_eq!(
+            manager.list_tags(id("b")).unwrap(),
+            vec![&tag("projects"), &tag("work")]
+        );
+        assert!(manager
+            .list_tagged_notes(tag("project"))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            manager
+                .rename_tag(tag("project"), tag("work"))
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::TagDoesNotExist
+        );
+
+        manager.undo().unwrap();
+        assert_eq!(
+            manager.list_tagged_notes(tag("project")).unwrap(),
+            vec![&id("a"), &id("b")]
+        );
+
+        manager.remove_tag(id("b"), tag("project")).unwrap();
+        assert_eq!(
+            manager
+                .remove_tag(id("b"), tag("project"))
+                .unwrap_err()
+                .current_context(),
+            &DeleteError::TagDoesNotExist
+        );
+
+        manager.delete_note(id("a")).unwrap();
+        assert_eq!(manager.tag_counts().unwrap(), vec![(&tag("projects"), 1)]);
+    }
+}
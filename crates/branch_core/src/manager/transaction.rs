@@ -0,0 +1,280 @@
+use error_stack::{Context, Result};
+
+use time::OffsetDateTime;
+
+use super::history::Revision;
+use crate::types::{self, Note, NoteId, TrashId};
+
+///
+/// [`Clock`] holds the time of the outermost transaction, every change made in it is stamped with
+/// that time. A pinned time is used instead of the current one to replay recorded operations.
+///
+#[derive(Default)]
+pub(super) struct Clock {
+    pinned: Option<OffsetDateTime>,
+    current: Option<OffsetDateTime>,
+}
+
+impl super::NotesManager {
+    ///
+    /// [`transaction`] applies a batch of mutations all-or-nothing. If the closure returns an error
+    /// every note it touched is restored, otherwise the changes are recorded as a single entry on
+    /// the undo stack.
+    ///
+    /// Transactions can be nested, a failing inner transaction only reverts its own changes. Every
+    /// mutation of the manager runs in a transaction, so an operation that fails halfway never
+    /// leaves the graph half modified.
+    ///
+    /// The notes, links and branches changed by a successful transaction have their `modified`
+    /// time set to the time the outermost transaction started.
+    ///
+    /// ```rust
+    /// use branch_core::errors::ChangeError;
+    /// use branch_core::prelude::*;
+    /// use error_stack::ResultExt;
+    ///
+    /// let mut manager = NotesManager::default();
+    /// let note = manager
+    ///     .add_note(Note::new("title".to_string(), None, "body".to_string()))
+    ///     .unwrap();
+    ///
+    /// let result = manager.transaction(|tx| {
+    ///     tx.change_note_title(note.clone(), "renamed".to_string())?;
+    ///     tx.add_link(note.clone(), NoteId::new(), "missing".to_string())
+    ///         .change_context(ChangeError::NoteDoesNotExist)?;
+    ///     Ok(())
+    /// });
+    ///
+    /// assert!(result.is_err());
+    /// assert_eq!(manager.read_note(note).unwrap().title, "title");
+    /// ```
+    ///
+    pub fn transaction<T, C: Context>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, C>,
+    ) -> Result<T, C> {
+        if self.savepoints.is_empty() {
+            self.clock.current = Some(self.clock_time());
+        }
+
+        self.savepoints.push(Revision::default());
+
+        let output = operation(self);
+
+        let mut revision = self.savepoints.pop().unwrap_or_default();
+
+        if output.is_err() {
+            let restored = self.restore(revision);
+            self.reindex(restored.notes.keys());
+            return output;
+        }
+
+        let at = self.transaction_time();
+        for (note_id, before) in &revision.notes {
+            if let (Some(note), Some(before)) = (self.notes.get_mut(note_id), before) {
+                note.stamp(before, at);
+            }
+        }
+
+        self.reindex(revision.notes.keys());
+
+        match self.savepoints.last_mut() {
+            Some(parent) => {
+                for (note_id, before) in revision.notes {
+                    parent.notes.entry(note_id).or_insert(before);
+                }
+                for (entry_id, before) in revision.trash {
+                    parent.trash.entry(entry_id).or_insert(before);
+                }
+            }
+            None => {
+                revision
+                    .notes
+                    .retain(|note_id, before| self.notes.get(note_id) != before.as_ref());
+                revision
+                    .trash
+                    .retain(|entry_id, before| self.trash.get(entry_id) != before.as_ref());
+
+                if !revision.is_empty() {
+                    self.history.push(revision);
+                }
+            }
+        }
+
+        output
+    }
+
+    ///
+    /// [`rolled_back`] runs an operation in a transaction that is reverted whatever its outcome,
+    /// to find out what the operation would do.
+    ///
+    pub(super) fn rolled_back<T, C: Context>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, C>,
+    ) -> Result<T, C> {
+        self.savepoints.push(Revision::default());
+
+        let output = operation(self);
+
+        let revision = self.savepoints.pop().unwrap_or_default();
+        let restored = self.restore(revision);
+        self.reindex(restored.notes.keys());
+
+        output
+    }
+
+    ///
+    /// [`transaction_time`] is the time of the running transaction, or of the last one once it is
+    /// over.
+    ///
+    pub(crate) fn transaction_time(&self) -> OffsetDateTime {
+        self.clock.current.unwrap_or_else(types::now)
+    }
+
+    ///
+    /// [`clock_time`] is the time a transaction started now would run at.
+    ///
+    pub(super) fn clock_time(&self) -> OffsetDateTime {
+        self.clock.pinned.unwrap_or_else(types::now)
+    }
+
+    ///
+    /// [`pin_clock`] makes the following transactions run at the given time instead of the
+    /// current one, until it is unpinned with `None`.
+    ///
+    pub fn pin_clock(&mut self, at: Option<OffsetDateTime>) {
+        self.clock.pinned = at;
+    }
+
+    ///
+    /// [`note_mut`] gives mutable access to a note, recording its current state first. All the
+    /// mutations go through [`note_mut`], [`insert_note`] and [`remove_note`].
+    ///
+    pub(super) fn note_mut(&mut self, note: &NoteId) -> Option<&mut Note> {
+        self.touch(note);
+        self.notes.get_mut(note)
+    }
+
+    pub(super) fn insert_note(&mut self, note: Note) {
+        let note_id = note.get_id();
+        self.touch(&note_id);
+        self.notes.insert(note_id, note);
+    }
+
+    pub(super) fn remove_note(&mut self, note: &NoteId) -> Option<Note> {
+        self.touch(note);
+        self.notes.remove(note)
+    }
+
+    ///
+    /// [`reindex`] brings the search and tag indexes up to date for the given notes, it is called
+    /// at the end of every transaction and whenever a revision is restored.
+    ///
+    pub(super) fn reindex<'a>(&mut self, notes: impl IntoIterator<Item = &'a NoteId>) {
+        for note_id in notes {
+            let note = self.notes.get(note_id);
+            self.search.update(note_id, note);
+            self.tags.update(note_id, note);
+        }
+    }
+
+    fn touch(&mut self, note: &NoteId) {
+        if let Some(revision) = self.savepoints.last_mut() {
+            if !revision.notes.contains_key(note) {
+                revision
+                    .notes
+                    .insert(note.clone(), self.notes.get(note).cloned());
+            }
+        }
+    }
+
+    ///
+    /// [`touch_trash`] records the current state of a trash entry before it is changed, the same
+    /// way [`note_mut`] does for notes.
+    ///
+    pub(super) fn touch_trash(&mut self, entry: TrashId) {
+        if let Some(revision) = self.savepoints.last_mut() {
+            revision
+                .trash
+                .entry(entry)
+                .or_insert_with(|| self.trash.get(&entry).cloned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::{AddError, DeleteError};
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ChangeNote, DeleteNote, Trash};
+    use crate::test_util::{id, note};
+    use error_stack::ResultExt;
+
+    #[test]
+    fn test_failed_operation_rolls_back() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+        manager.add_note(note("c")).unwrap();
+        manager
+            .add_link(id("a"), id("c"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch, id("c"), "option".to_string())
+            .unwrap();
+
+        let before = manager.notes.clone();
+
+        // Deleting `c` changes the links and the branch of `a` and puts `c` in the trash, the
+        // missing note makes all of it revert.
+        let result = manager.transaction(|tx| {
+            tx.delete_note(id("c"))?;
+            assert!(!tx.notes.contains_key(&id("c")));
+            tx.delete_note(id("missing"))
+        });
+
+        assert_eq!(
+            result.unwrap_err().current_context(),
+            &DeleteError::NoteDoesNotExist
+        );
+        assert_eq!(manager.notes, before);
+        assert!(manager.list_trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(note("a")).unwrap();
+
+        let result = manager.transaction(|tx| {
+            tx.add_note(note("b"))?;
+            tx.add_link(id("a"), id("b"), "reason".to_string())?;
+
+            let inner = tx.transaction(|tx| {
+                tx.add_note(note("c"))?;
+                tx.add_note(note("c"))
+            });
+            assert!(inner.is_err());
+
+            tx.change_note_title(id("b"), "b".to_string())
+                .change_context(AddError::NoteDoesNotExist)?;
+            Ok(())
+        });
+
+        assert!(result.is_ok());
+        assert_eq!(manager.notes.len(), 2);
+
+        manager.undo().unwrap();
+        assert_eq!(manager.notes.len(), 1);
+
+        let result = manager.transaction(|tx| {
+            tx.add_note(note("b"))?;
+            tx.add_link(id("a"), id("missing"), "reason".to_string())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(manager.notes.len(), 1);
+        assert!(manager.can_redo());
+    }
+}
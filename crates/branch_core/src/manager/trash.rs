@@ -0,0 +1,581 @@
+use std::collections::BTreeMap;
+
+use error_stack::{ensure, Result};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::{AddError, DeleteError, TrashError};
+use crate::manager_impl::Trash;
+use crate::types::{BranchId, FLink, Note, NoteId, TrashEntry, TrashId, TrashedLink};
+
+///
+/// [`TrashBin`] holds what was deleted from the manager. Entries are changed through transactions
+/// like notes are, so undoing a deletion takes its entry out of the trash again.
+///
+#[derive(Default)]
+pub(super) struct TrashBin {
+    entries: BTreeMap<TrashId, TrashEntry>,
+    next: u64,
+    retention: Option<Duration>,
+    collecting: bool,
+}
+
+impl TrashBin {
+    pub(super) fn get(&self, entry: &TrashId) -> Option<&TrashEntry> {
+        self.entries.get(entry)
+    }
+
+    pub(super) fn replace(
+        &mut self,
+        entry: TrashId,
+        value: Option<TrashEntry>,
+    ) -> Option<TrashEntry> {
+        match value {
+            Some(value) => self.entries.insert(entry, value),
+            None => self.entries.remove(&entry),
+        }
+    }
+
+    pub(super) fn entries(&self) -> Vec<&TrashEntry> {
+        self.entries.values().collect()
+    }
+}
+
+impl super::NotesManager {
+    ///
+    /// [`set_trash_retention`] sets how long deleted notes and links are kept in the trash. Older
+    /// entries are purged whenever something is deleted, and right away for the ones that are
+    /// already older. `None` keeps them until the trash is emptied.
+    ///
+    pub fn set_trash_retention(&mut self, retention: Option<Duration>) {
+        self.trash.retention = retention;
+
+        if let Some(retention) = retention {
+            let cutoff = self.clock_time() - retention;
+            self.trash
+                .entries
+                .retain(|_, entry| entry.deleted >= cutoff);
+        }
+    }
+
+    pub fn trash_retention(&self) -> Option<Duration> {
+        self.trash.retention
+    }
+
+    ///
+    /// [`load_trash`] replaces the trash with entries read back from a storage.
+    ///
+    pub(super) fn load_trash(&mut self, entries: Vec<TrashEntry>) {
+        self.trash.entries = entries.into_iter().map(|entry| (entry.id, entry)).collect();
+        self.trash.next = self
+            .trash
+            .entries
+            .keys()
+            .next_back()
+            .map_or(0, |entry| entry.0 + 1);
+
+        self.set_trash_retention(self.trash.retention);
+    }
+
+    ///
+    /// [`trashing`] runs a deletion in a transaction and moves what it removed to the trash, as a
+    /// single entry. Deletions it cascades into are part of the same entry.
+    ///
+    pub(super) fn trashing(
+        &mut self,
+        deletion: impl FnOnce(&mut Self) -> Result<(), DeleteError>,
+    ) -> Result<(), DeleteError> {
+        if self.trash.collecting {
+            return self.transaction(deletion);
+        }
+
+        self.transaction(|manager| {
+            let (notes, links) = manager.untrashed(deletion)?;
+            manager.add_to_trash(notes, links);
+
+            if let Some(retention) = manager.trash.retention {
+                let cutoff = manager.transaction_time() - retention;
+                manager.purge(|entry| entry.deleted < cutoff);
+            }
+
+            Ok(())
+        })
+    }
+
+    ///
+    /// [`untrashed`] runs a deletion without adding what it removes to the trash, and lists what
+    /// it removed instead. It has to run in a transaction.
+    ///
+    pub(super) fn untrashed(
+        &mut self,
+        deletion: impl FnOnce(&mut Self) -> Result<(), DeleteError>,
+    ) -> Result<(Vec<Note>, Vec<TrashedLink>), DeleteError> {
+        let collecting = std::mem::replace(&mut self.trash.collecting, true);
+        let output = deletion(self);
+        self.trash.collecting = collecting;
+        output?;
+
+        Ok(self.removed())
+    }
+
+    ///
+    /// [`add_to_trash`] adds what a deletion removed to the trash as a single entry.
+    ///
+    fn add_to_trash(&mut self, notes: Vec<Note>, links: Vec<TrashedLink>) {
+        if notes.is_empty() && links.is_empty() {
+            return;
+        }
+
+        let id = TrashId(self.trash.next);
+        self.trash.next += 1;
+
+        self.touch_trash(id);
+        self.trash.entries.insert(
+            id,
+            TrashEntry {
+                id,
+                deleted: self.transaction_time(),
+                notes,
+                links,
+            },
+        );
+    }
+
+    ///
+    /// [`removed`] compares the notes touched by the running transaction with their state before
+    /// it, and lists the notes, links and branches that are gone.
+    ///
+    fn removed(&self) -> (Vec<Note>, Vec<TrashedLink>) {
+        let mut notes = Vec::new();
+        let mut links = Vec::new();
+
+        let Some(revision) = self.savepoints.last() else {
+            return (notes, links);
+        };
+
+        for (note_id, before) in &revision.notes {
+            let Some(before) = before else {
+                continue;
+            };
+
+            match self.notes.get(note_id) {
+                Some(after) => removed_options(
+                    note_id,
+                    None,
+                    &before.forwardlinks,
+                    &after.forwardlinks,
+                    &mut links,
+                ),
+                None => notes.push(before.clone()),
+            }
+        }
+
+        notes.sort_by_key(|note| note.get_id());
+        links.sort_by(|a, b| a.note.cmp(&b.note));
+
+        (notes, links)
+    }
+
+    ///
+    /// [`purge`] removes the entries matching the predicate from the trash for good.
+    ///
+    fn purge(&mut self, expired: impl Fn(&TrashEntry) -> bool) -> usize {
+        let expired = self
+            .trash
+            .entries
+            .values()
+            .filter(|entry| expired(entry))
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+
+        for entry in &expired {
+            self.touch_trash(*entry);
+            self.trash.entries.remove(entry);
+        }
+
+        expired.len()
+    }
+}
+
+impl Trash for super::NotesManager {
+    fn list_trash(&self) -> Result<Vec<&TrashEntry>, TrashError> {
+        Ok(self.trash.entries())
+    }
+
+    fn restore_from_trash(&mut self, entry: TrashId) -> Result<(), TrashError> {
+        self.transaction(|manager| {
+            manager.touch_trash(entry);
+            let entry = manager
+                .trash
+                .entries
+                .remove(&entry)
+                .ok_or(TrashError::EntryDoesNotExist)?;
+
+            // Every link that comes back adds a backlink to its target, the backlinks the notes had
+            // come back with the links pointing to them.
+            let mut restored = Vec::new();
+
+            for note in entry.notes {
+                let note_id = note.get_id();
+
+                ensure!(
+                    !manager.notes.contains_key(&note_id),
+                    TrashError::NoteAlreadyExists
+                );
+
+                for flink in &note.forwardlinks {
+                    restored.extend(
+                        flink
+                            .targets()
+                            .into_iter()
+                            .map(|link| (note_id.clone(), link.id.clone())),
+                    );
+                }
+
+                let mut note = note;
+                note.backlinks.clear();
+                manager.insert_note(note);
+            }
+
+            for trashed in entry.links {
+                let note = manager
+                    .note_mut(&trashed.note)
+                    .ok_or(TrashError::NoteDoesNotExist)?;
+
+                let options = match &trashed.branch {
+                    Some(branch) => {
+                        &mut note
+                            .branch_mut(branch)
+                            .ok_or(TrashError::BranchDoesNotExist)?
+                            .branches
+                    }
+                    None => &mut note.forwardlinks,
+                };
+
+                if options
+                    .iter()
+                    .any(|option| same_option(option, &trashed.link))
+                {
+                    continue;
+                }
+
+                restored.extend(
+                    trashed
+                        .link
+                        .targets()
+                        .into_iter()
+                        .map(|link| (trashed.note.clone(), link.id.clone())),
+                );
+                options.insert(trashed.position.min(options.len()), trashed.link);
+            }
+
+            for (from_note, to_note) in &restored {
+                manager
+                    .note_mut(to_note)
+                    .ok_or(TrashError::NoteDoesNotExist)?
+                    .add_backlink(from_note.clone());
+            }
+
+            // In strict mode a link that comes back may not close a cycle with the links added
+            // since it was deleted. Checking once everything is back also catches the cycles only
+            // the restored links form together.
+            for (from_note, to_note) in &restored {
+                if let Err(error) = manager.ensure_acyclic(from_note, to_note) {
+                    if let AddError::CycleDetected(cycle) = error.current_context() {
+                        let cycle = cycle.clone();
+                        return Err(error.change_context(TrashError::CycleDetected(cycle)));
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn empty_trash(&mut self) -> Result<(), TrashError> {
+        self.transaction(|manager| {
+            manager.purge(|_| true);
+            Ok(())
+        })
+    }
+
+    fn purge_trash(&mut self, before: OffsetDateTime) -> Result<usize, TrashError> {
+        self.transaction(|manager| Ok(manager.purge(|entry| entry.deleted < before)))
+    }
+}
+
+///
+/// [`removed_options`] lists the links and branches of `before` that are no longer in `after`.
+/// The options of a branch that is still there are compared the same way.
+///
+fn removed_options(
+    note: &NoteId,
+    branch: Option<&BranchId>,
+    before: &[FLink],
+    after: &[FLink],
+    removed: &mut Vec<TrashedLink>,
+) {
+    for (position, option) in before.iter().enumerate() {
+        match (
+            option,
+            after.iter().find(|other| same_option(option, other)),
+        ) {
+            (FLink::Branch(previous), Some(FLink::Branch(current))) => removed_options(
+                note,
+                Some(previous.id()),
+                &previous.branches,
+                &current.branches,
+                removed,
+            ),
+            (_, Some(_)) => {}
+            (_, None) => removed.push(TrashedLink {
+                note: note.clone(),
+                branch: branch.cloned(),
+                position,
+                link: option.clone(),
+            }),
+        }
+    }
+}
+
+///
+/// [`same_option`] matches links by their target and branches by their id.
+///
+fn same_option(a: &FLink, b: &FLink) -> bool {
+    match (a, b) {
+        (FLink::Link(a), FLink::Link(b)) => a.id == b.id,
+        (FLink::Branch(a), FLink::Branch(b)) => a.id() == b.id(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{id, note};
+    use time::{Duration, OffsetDateTime};
+
+    use crate::errors::TrashError;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, DeleteBranch, DeleteLink, Trash};
+    use crate::types;
+
+    ///
+    /// a -> b -> c
+    /// a: condition? c | (nested? d)
+    ///
+    fn graph() -> (super::super::NotesManager, types::BranchId) {
+        let mut manager = super::super::NotesManager::default();
+        manager.pin_clock(Some(OffsetDateTime::UNIX_EPOCH));
+        for note_id in ["a", "b", "c", "d"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch.clone(), id("c"), "option".to_string())
+            .unwrap();
+        let nested = manager
+            .create_sub_branching(id("a"), branch.clone(), "nested".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), nested, id("d"), "nested option".to_string())
+            .unwrap();
+
+        (manager, branch)
+    }
+
+    #[test]
+    fn test_delete_and_restore() {
+        let (mut manager, branch) = graph();
+        let mut before = manager.notes.clone();
+
+        // `b` is deleted along with the only link to it, `c` is still linked from the branch.
+        manager.delete_link(id("a"), id("b")).unwrap();
+        // The branch is removed with its nested branch, and `c` and `d` go with the last links
+        // leading to them.
+        manager.delete_branch(id("a"), branch.clone()).unwrap();
+
+        assert_eq!(manager.notes.len(), 1);
+        let trash = manager.list_trash().unwrap();
+        assert_eq!(trash.len(), 2);
+        assert_eq!(trash[0].notes[0].get_id(), id("b"));
+        assert_eq!(
+            trash[1]
+                .notes
+                .iter()
+                .map(|note| note.get_id())
+                .collect::<Vec<_>>(),
+            vec![id("c"), id("d")]
+        );
+        assert_eq!(trash[0].links[0].position, 0);
+        assert_eq!(
+            trash[1]
+                .links
+                .iter()
+                .map(|trashed| (trashed.branch.as_ref(), trashed.position))
+                .collect::<Vec<_>>(),
+            vec![(None, 0)]
+        );
+        assert!(trash[1].links[0].link.is_branch(&branch));
+
+        // `b` links to `c`, so the later entry is restored first.
+        let entries = trash.iter().map(|entry| entry.id).collect::<Vec<_>>();
+        for entry in entries.into_iter().rev() {
+            manager.restore_from_trash(entry).unwrap();
+        }
+
+        // The backlinks of `c` come back in the order the links were restored.
+        for note in manager.notes.values_mut().chain(before.values_mut()) {
+            note.backlinks.sort();
+        }
+
+        assert_eq!(manager.notes, before);
+        assert!(manager.list_trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cascade_is_one_entry() {
+        let mut manager = super::super::NotesManager::default();
+        manager.pin_clock(Some(OffsetDateTime::UNIX_EPOCH));
+        for note_id in ["a", "b", "c"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("c"), "reason".to_string())
+            .unwrap();
+        let before = manager.notes.clone();
+
+        // `b` and `c` are left without backlinks, so they are deleted along with the link.
+        manager.delete_link(id("a"), id("b")).unwrap();
+        assert_eq!(manager.notes.len(), 1);
+
+        let entry = manager.list_trash().unwrap()[0].clone();
+        assert_eq!(
+            entry
+                .notes
+                .iter()
+                .map(|note| note.get_id())
+                .collect::<Vec<_>>(),
+            vec![id("b"), id("c")]
+        );
+        assert_eq!(entry.links[0].note, id("a"));
+
+        // A restore is all-or-nothing.
+        manager.add_note(note("c")).unwrap();
+        assert_eq!(
+            manager
+                .restore_from_trash(entry.id)
+                .unwrap_err()
+                .current_context(),
+            &TrashError::NoteAlreadyExists
+        );
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+
+        manager.undo().unwrap();
+        manager.restore_from_trash(entry.id).unwrap();
+        assert_eq!(manager.notes, before);
+        assert_eq!(
+            manager
+                .restore_from_trash(entry.id)
+                .unwrap_err()
+                .current_context(),
+            &TrashError::EntryDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_restore_in_strict_mode() {
+        let mut manager = super::super::NotesManager::default();
+        manager.pin_clock(Some(OffsetDateTime::UNIX_EPOCH));
+        for note_id in ["a", "b"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        manager.set_deletion_policy(types::DeletionPolicy::KeepOrphans);
+        manager.delete_link(id("a"), id("b")).unwrap();
+
+        // The link back is fine while `a -> b` is in the trash, restoring it would close a cycle.
+        manager.set_acyclic(true);
+        manager
+            .add_link(id("b"), id("a"), "reason".to_string())
+            .unwrap();
+        let before = manager.notes.clone();
+
+        let entry = manager.list_trash().unwrap()[0].id;
+        assert_eq!(
+            manager
+                .restore_from_trash(entry)
+                .unwrap_err()
+                .current_context(),
+            &TrashError::CycleDetected(vec![id("a"), id("b")])
+        );
+        assert_eq!(manager.notes, before);
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+
+        manager.set_acyclic(false);
+        manager.restore_from_trash(entry).unwrap();
+        assert_eq!(manager.find_cycles(), vec![vec![id("a"), id("b")]]);
+    }
+
+    #[test]
+    fn test_undo_deletion() {
+        let (mut manager, branch) = graph();
+
+        manager.delete_branch(id("a"), branch).unwrap();
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+
+        manager.undo().unwrap();
+        assert!(manager.list_trash().unwrap().is_empty());
+
+        manager.redo().unwrap();
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+
+        manager.empty_trash().unwrap();
+        assert!(manager.list_trash().unwrap().is_empty());
+        manager.undo().unwrap();
+        assert_eq!(manager.list_trash().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_retention() {
+        let day = |day: i64| OffsetDateTime::UNIX_EPOCH + Duration::days(day);
+
+        let (mut manager, branch) = graph();
+        manager.set_trash_retention(Some(Duration::days(7)));
+
+        manager.pin_clock(Some(day(1)));
+        manager.delete_link(id("a"), id("b")).unwrap();
+        manager.pin_clock(Some(day(5)));
+        manager
+            .delete_branch_link(id("a"), branch.clone(), id("c"))
+            .unwrap();
+        assert_eq!(manager.list_trash().unwrap().len(), 2);
+
+        manager.pin_clock(Some(day(10)));
+        manager.delete_branch(id("a"), branch).unwrap();
+        assert_eq!(
+            manager
+                .list_trash()
+                .unwrap()
+                .iter()
+                .map(|entry| entry.deleted)
+                .collect::<Vec<_>>(),
+            vec![day(5), day(10)]
+        );
+
+        assert_eq!(manager.purge_trash(day(10)).unwrap(), 1);
+        manager.pin_clock(Some(day(20)));
+        manager.set_trash_retention(Some(Duration::days(7)));
+        assert!(manager.list_trash().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+use error_stack::Result;
+
+use crate::errors::{ChangeError, DeleteError, ReadError};
+use crate::manager_impl::DeleteLink;
+use crate::types::{FLink, Link, NoteId};
+use crate::wiki::{self, Problem, Reference, UnresolvedReference};
+
+impl super::NotesManager {
+    ///
+    /// [`list_unresolved_references`] lists the references in the body of a note that are not
+    /// linked, because they match no note or several notes by title, or because the link would
+    /// close a cycle in strict mode.
+    ///
+    pub fn list_unresolved_references(
+        &self,
+        note: NoteId,
+    ) -> Result<Vec<UnresolvedReference>, ReadError> {
+        let note_ = self.notes.get(&note).ok_or(ReadError::NoteDoesNotExist)?;
+
+        Ok(wiki::parse(&note_.body)
+            .into_iter()
+            .filter_map(|reference| {
+                let problem = match self.resolve_reference(&reference.target) {
+                    Ok(target) if note_.link(&target).is_some() => return None,
+                    Ok(target) => self
+                        .ensure_acyclic(&note, &target)
+                        .err()
+                        .and_then(|error| match error.current_context() {
+                            crate::errors::AddError::CycleDetected(cycle) => {
+                                Some(Problem::CycleDetected(cycle.clone()))
+                            }
+                            _ => None,
+                        })?,
+                    Err(problem) => problem,
+                };
+
+                Some(UnresolvedReference { reference, problem })
+            })
+            .collect())
+    }
+
+    ///
+    /// [`resolve_reference`] finds the note a reference points to, by id first and then by title.
+    /// Titles are compared without regard to case or surrounding spaces.
+    ///
+    fn resolve_reference(&self, target: &str) -> std::result::Result<NoteId, Problem> {
+        if let Some(note) = target
+            .parse::<NoteId>()
+            .ok()
+            .filter(|note| self.notes.contains_key(note))
+        {
+            return Ok(note);
+        }
+
+        let title = target.trim().to_lowercase();
+
+        let mut matches = self
+            .notes
+            .values()
+            .filter(|note| note.title.trim().to_lowercase() == title)
+            .map(|note| note.get_id())
+            .collect::<Vec<_>>();
+
+        matches.sort();
+
+        match matches.len() {
+            0 => Err(Problem::NoteDoesNotExist),
+            1 => Ok(matches.remove(0)),
+            _ => Err(Problem::AmbiguousTitle(matches)),
+        }
+    }
+
+    ///
+    /// [`resolved_references`] maps the notes the references of a body resolve to onto their first
+    /// reference.
+    ///
+    fn resolved_references(&self, references: &[Reference]) -> Vec<(NoteId, Reference)> {
+        let mut resolved: Vec<(NoteId, Reference)> = Vec::new();
+
+        for reference in references {
+            if let Ok(target) = self.resolve_reference(&reference.target) {
+                if !resolved.iter().any(|(note, _)| note == &target) {
+                    resolved.push((target, reference.clone()));
+                }
+            }
+        }
+
+        resolved
+    }
+
+    ///
+    /// [`sync_references`] updates the links of a note after its body changed. Only the links
+    /// carrying the [`ANNOTATION`] marker were created from a reference, so only those follow the
+    /// body: a link added by hand keeps its reason, and stays when its reference is taken out.
+    ///
+    /// A marked link is removed once its reference is gone, through [`delete_link`], so it goes
+    /// to the trash and the deletion policy applies to the note it led to. The reference is the
+    /// one recorded when the link was created: a link to a note that was renamed since stays as
+    /// long as the old title is in the body and leads to no other note.
+    ///
+    /// [`ANNOTATION`]: wiki::ANNOTATION
+    /// [`delete_link`]: crate::prelude::DeleteLink::delete_link
+    ///
+    pub(super) fn sync_references(&mut self, note: &NoteId) -> Result<(), ChangeError> {
+        let note_ = self.notes.get(note).ok_or(ChangeError::NoteDoesNotExist)?;
+        let references = wiki::parse(&note_.body);
+        let current = self.resolved_references(&references);
+
+        let stale = note_
+            .forwardlinks
+            .iter()
+            .filter_map(|option| match option {
+                FLink::Link(link) => Some(link),
+                FLink::Branch(_) => None,
+            })
+            .filter(|link| {
+                let Some(marker) = link.annotations.get(wiki::ANNOTATION) else {
+                    return false;
+                };
+
+                !current.iter().any(|(target, _)| target == &link.id)
+                    && !references.iter().any(|reference| {
+                        same_target(&reference.target, marker)
+                            && self.resolve_reference(&reference.target).is_err()
+                    })
+            })
+            .map(|link| link.id.clone())
+            .collect::<Vec<_>>();
+
+        for target in stale {
+            self.delete_link(note.clone(), target).map_err(|error| {
+                let context = match error.current_context() {
+                    DeleteError::WouldOrphan(orphan) => ChangeError::WouldOrphan(orphan.clone()),
+                    _ => ChangeError::LinkDoesNotExist,
+                };
+
+                error.change_context(context)
+            })?;
+        }
+
+        for (target, reference) in current {
+            if let Some(link) = self.notes.get(note).and_then(|note| note.link(&target)) {
+                let outdated = link
+                    .annotations
+                    .get(wiki::ANNOTATION)
+                    .is_some_and(|marker| {
+                        marker != &reference.target || link.reason != reference.sentence
+                    });
+
+                if outdated {
+                    self.edit_link(note, &target, |link| {
+                        link.reason = reference.sentence;
+                        link.annotations
+                            .insert(wiki::ANNOTATION.to_string(), reference.target);
+                    })?;
+                }
+
+                continue;
+            }
+
+            if self.ensure_acyclic(note, &target).is_err() {
+                continue;
+            }
+
+            let mut link = Link::new(target.clone(), reference.sentence);
+            link.annotations
+                .insert(wiki::ANNOTATION.to_string(), reference.target);
+
+            self.note_mut(note)
+                .ok_or(ChangeError::NoteDoesNotExist)?
+                .forwardlinks
+                .push(FLink::Link(link));
+
+            self.note_mut(&target)
+                .ok_or(ChangeError::NoteDoesNotExist)?
+                .add_backlink(note.clone());
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// [`same_target`] compares the targets of two references the way titles are resolved, without
+/// regard to case or surrounding spaces.
+///
+fn same_target(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::ChangeError;
+    use crate::manager_impl::{AddLink, AddNote, ChangeNote, ReadLink, ReadNote, Trash};
+    use crate::test_util::{id, titled_note};
+    use crate::types::DeletionPolicy;
+    use crate::wiki::{self, Problem};
+
+    fn links(manager: &super::super::NotesManager, note: &str) -> Vec<(String, String)> {
+        manager
+            .list_pure_links(id(note))
+            .unwrap()
+            .into_iter()
+            .map(|link| (link.id.to_string(), link.reason.clone()))
+            .collect()
+    }
+
+    fn marker(manager: &super::super::NotesManager, from: &str, to: &str) -> Option<String> {
+        manager
+            .list_pure_links(id(from))
+            .unwrap()
+            .into_iter()
+            .find(|link| link.id == id(to))
+            .and_then(|link| link.annotations.get(wiki::ANNOTATION).cloned())
+    }
+
+    #[test]
+    fn test_sync_references() {
+        let mut manager = super::super::NotesManager::default();
+        manager.add_note(titled_note("a", "Start", "")).unwrap();
+        manager.add_note(titled_note("b", "Design", "")).unwrap();
+        manager.add_note(titled_note("c", "Plan", "")).unwrap();
+        manager.add_note(titled_note("d", "Plan", "")).unwrap();
+        manager
+            .add_link(id("a"), id("c"), "by hand".to_string())
+            .unwrap();
+
+        manager
+            .change_note_body(
+                id("a"),
+                "Read [[design]] first. Then [[c|the plan]]! Or [[Plan]], or [[Missing]]."
+                    .to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            links(&manager, "a"),
+            vec![
+                ("c".to_string(), "by hand".to_string()),
+                ("b".to_string(), "Read design first.".to_string()),
+            ]
+        );
+        assert_eq!(manager.list_backlinks(id("b")).unwrap(), vec![&id("a")]);
+        assert_eq!(
+            manager
+                .list_unresolved_references(id("a"))
+                .unwrap()
+                .into_iter()
+                .map(|unresolved| (unresolved.reference.target, unresolved.problem))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "Plan".to_string(),
+                    Problem::AmbiguousTitle(vec![id("c"), id("d")])
+                ),
+                ("Missing".to_string(), Problem::NoteDoesNotExist),
+            ]
+        );
+
+        // Editing the sentence updates the reason. The link added by hand is not marked, so it
+        // stays when its reference is taken out.
+        manager
+            .change_note_body(id("a"), "Read [[Design]] again.".to_string())
+            .unwrap();
+
+        assert_eq!(
+            links(&manager, "a"),
+            vec![
+                ("c".to_string(), "by hand".to_string()),
+                ("b".to_string(), "Read Design again.".to_string()),
+            ]
+        );
+        assert_eq!(marker(&manager, "a", "b"), Some("Design".to_string()));
+        assert_eq!(marker(&manager, "a", "c"), None);
+
+        // Removing the reference deletes the link like any other deletion, `b` is left without
+        // backlinks and goes to the trash with it.
+        manager.change_note_body(id("a"), String::new()).unwrap();
+
+        assert_eq!(
+            links(&manager, "a"),
+            vec![("c".to_string(), "by hand".to_string())]
+        );
+        assert!(manager.read_note(id("b")).is_err());
+
+        let entry = manager.list_trash().unwrap()[0].id;
+        manager.restore_from_trash(entry).unwrap();
+        assert_eq!(manager.list_backlinks(id("b")).unwrap(), vec![&id("a")]);
+    }
+
+    #[test]
+    fn test_renamed_reference() {
+        let mut manager = super::super::NotesManager::default();
+        manager.set_deletion_policy(DeletionPolicy::RefuseOrphans);
+        manager.add_note(titled_note("a", "Start", "")).unwrap();
+        manager.add_note(titled_note("b", "Design", "")).unwrap();
+
+        manager
+            .change_note_body(id("a"), "See [[Design]].".to_string())
+            .unwrap();
+        manager
+            .change_note_title(id("b"), "Draft".to_string())
+            .unwrap();
+
+        // The old title leads nowhere now, the link stays while it is in the body.
+        manager
+            .change_note_body(id("a"), "See [[Design]] first.".to_string())
+            .unwrap();
+        assert_eq!(
+            links(&manager, "a"),
+            vec![("b".to_string(), "See Design.".to_string())]
+        );
+
+        assert_eq!(
+            manager
+                .change_note_body(id("a"), "Nothing to see.".to_string())
+                .unwrap_err()
+                .current_context(),
+            &ChangeError::WouldOrphan(id("b"))
+        );
+        assert_eq!(
+            manager.read_note(id("a")).unwrap().body,
+            "See [[Design]] first."
+        );
+
+        manager.set_deletion_policy(DeletionPolicy::KeepOrphans);
+        manager
+            .change_note_body(id("a"), "Nothing to see.".to_string())
+            .unwrap();
+        assert!(links(&manager, "a").is_empty());
+        assert!(manager.read_note(id("b")).is_ok());
+    }
+
+    #[test]
+    fn test_restored_revision_is_synced() {
+        let mut manager = super::super::NotesManager::default();
+        manager.set_deletion_policy(DeletionPolicy::KeepOrphans);
+        manager.add_note(titled_note("a", "Start", "")).unwrap();
+        manager.add_note(titled_note("b", "Design", "")).unwrap();
+        manager.add_note(titled_note("c", "Plan", "")).unwrap();
+
+        manager
+            .change_note_body(id("a"), "See [[Design]].".to_string())
+            .unwrap();
+        manager
+            .change_note_body(id("a"), "See [[Plan]].".to_string())
+            .unwrap();
+        assert_eq!(
+            links(&manager, "a"),
+            vec![("c".to_string(), "See Plan.".to_string())]
+        );
+
+        let revision = manager
+            .read_note(id("a"))
+            .unwrap()
+            .revisions
+            .iter()
+            .position(|revision| revision.body == "See [[Design]].")
+            .unwrap();
+        manager.restore_revision(id("a"), revision).unwrap();
+
+        assert_eq!(
+            links(&manager, "a"),
+            vec![("b".to_string(), "See Design.".to_string())]
+        );
+        assert!(manager.list_backlinks(id("c")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cycles_are_reported() {
+        let mut manager = super::super::NotesManager::default();
+        manager.set_acyclic(true);
+        manager.add_note(titled_note("a", "A", "")).unwrap();
+        manager.add_note(titled_note("b", "B", "")).unwrap();
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+
+        manager
+            .change_note_body(id("b"), "Back to [[A]].".to_string())
+            .unwrap();
+
+        assert!(links(&manager, "b").is_empty());
+        assert_eq!(
+            manager.list_unresolved_references(id("b")).unwrap()[0].problem,
+            Problem::CycleDetected(vec![id("b"), id("a")])
+        );
+    }
+}
@@ -1,28 +1,36 @@
-use crate::errors::{AddError, ChangeError, DeleteError, ReadError};
-use crate::types::{BranchId, FLink, Link, Note, NoteId};
+use crate::errors::{AddError, ChangeError, DeleteError, ReadError, SearchError, TrashError};
+use crate::types::{
+    BranchId, DateQuery, FLink, FLinkId, Link, Note, NoteId, NoteRevision, ReconsileReport,
+    Relation, RevisionDiff, SearchHit, SortKey, Tag, TaskState, TrashEntry, TrashId,
+};
 use error_stack::Result;
+use time::OffsetDateTime;
 
 ///
 /// [`AddNote`] is a trait that defines the method to add a note.
 ///
-pub trait Add: AddNote + AddLink + AddBranch {}
+pub trait Add: AddNote + AddLink + AddBranch + AddTag {}
 
 ///
-/// [`Change`] is a trait that combines the [`ChangeNote`], [`ChangeLink`], and [`ChangeBranch`] traits.
+/// [`Change`] is a trait that combines the [`ChangeNote`], [`ChangeLink`], [`ChangeBranch`] and [`ChangeTag`] traits.
 ///
-pub trait Change: ChangeNote + ChangeLink + ChangeBranch {}
+pub trait Change: ChangeNote + ChangeLink + ChangeBranch + ChangeTag {}
 
 ///
-/// [`Delete`] is a trait that combines the [`DeleteNote`], [`DeleteLink`], and [`DeleteBranch`] traits.
+/// [`Delete`] is a trait that combines the [`DeleteNote`], [`DeleteLink`], [`DeleteBranch`] and [`DeleteTag`] traits.
 ///
 ///
-pub trait Delete: DeleteNote + DeleteLink + DeleteBranch {}
+pub trait Delete: DeleteNote + DeleteLink + DeleteBranch + DeleteTag {}
 
-pub trait Read: ReadNote + ReadLink + ReadBranch {}
+///
+/// [`Read`] is a trait that combines the [`ReadNote`], [`ReadLink`], [`ReadBranch`] and [`ReadTag`] traits.
+///
+pub trait Read: ReadNote + ReadLink + ReadBranch + ReadTag {}
 
-impl<T> Add for T where T: AddNote + AddLink + AddBranch {}
-impl<T> Change for T where T: ChangeNote + ChangeLink + ChangeBranch {}
-impl<T> Delete for T where T: DeleteNote + DeleteLink + DeleteBranch {}
+impl<T> Add for T where T: AddNote + AddLink + AddBranch + AddTag {}
+impl<T> Change for T where T: ChangeNote + ChangeLink + ChangeBranch + ChangeTag {}
+impl<T> Delete for T where T: DeleteNote + DeleteLink + DeleteBranch + DeleteTag {}
+impl<T> Read for T where T: ReadNote + ReadLink + ReadBranch + ReadTag {}
 
 pub trait AddNote {
     fn add_note(&mut self, note: Note) -> Result<NoteId, AddError>;
@@ -35,10 +43,44 @@ pub trait AddLink {
         to_note: NoteId,
         reason: String,
     ) -> Result<(), AddError>;
+
+    ///
+    /// [`add_typed_link`] adds a link with a [`Relation`], the reason is kept as free text next
+    /// to it.
+    ///
+    fn add_typed_link(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Relation,
+        reason: String,
+    ) -> Result<(), AddError>;
+
+    ///
+    /// [`add_bidirectional_link`] links two notes both ways as a single relation. Changing or
+    /// deleting the link from either note changes or deletes it from the other one as well.
+    ///
+    fn add_bidirectional_link(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    ) -> Result<(), AddError>;
 }
 
 pub trait AddBranch {
     fn create_branching(&mut self, note: NoteId, condition: String) -> Result<BranchId, AddError>;
+
+    ///
+    /// [`create_sub_branching`] adds a branching as an option of another branch of the note, the
+    /// parent can itself be nested. The new branch is addressed by its id like any other branch.
+    ///
+    fn create_sub_branching(
+        &mut self,
+        note: NoteId,
+        on_branch: BranchId,
+        condition: String,
+    ) -> Result<BranchId, AddError>;
     fn add_branch(
         &mut self,
         note: NoteId,
@@ -48,18 +90,45 @@ pub trait AddBranch {
     ) -> Result<(), AddError>;
 }
 
+pub trait AddTag {
+    fn add_tag(&mut self, note: NoteId, tag: Tag) -> Result<(), AddError>;
+}
+
 pub trait ChangeNote {
     fn change_note_title(&mut self, note: NoteId, title: String) -> Result<(), ChangeError>;
     fn change_note_subtitle(&mut self, note: NoteId, subtitle: String) -> Result<(), ChangeError>;
     fn change_note_body(&mut self, note: NoteId, body: String) -> Result<(), ChangeError>;
+
+    ///
+    /// [`change_note_state`] moves a note to another state. The move has to be allowed by the
+    /// configured [`TaskStates`], and is kept in the state history of the note.
+    ///
+    /// [`TaskStates`]: crate::types::TaskStates
+    ///
+    fn change_note_state(&mut self, note: NoteId, state: TaskState) -> Result<(), ChangeError>;
+
+    ///
+    /// [`mark_note`] moves a note to the done state, and [`unmark_note`] moves it back to the
+    /// initial state, the same way [`change_note_state`] does.
+    ///
     fn mark_note(&mut self, note: NoteId) -> Result<(), ChangeError>;
     fn unmark_note(&mut self, note: NoteId) -> Result<(), ChangeError>;
 
     ///
     /// [`reconsile_nodes`] is a method that reconsiles the nodes in the note-taking app. This will
-    /// resolve links and branches that are not valid, mark notes whose forward links are all marked, And perform vacuuming.
+    /// resolve links and branches that are not valid, move notes whose forward links all lead to closed notes to done, And perform vacuuming.
+    /// Every fix that is made is listed in the returned [`ReconsileReport`].
+    ///
+    /// Notes left without backlinks by the repairs follow the deletion policy: they are kept, moved
+    /// to the trash, or the whole pass fails with [`ChangeError::WouldOrphan`].
+    ///
+    fn reconsile_nodes(&mut self) -> Result<ReconsileReport, ChangeError>;
+
+    ///
+    /// [`restore_revision`] brings back the title, subtitle and body of an earlier revision. The
+    /// content it replaces is kept as a revision of its own, so a restore can be reverted.
     ///
-    fn reconsile_nodes(&mut self) -> Result<(), ChangeError>;
+    fn restore_revision(&mut self, note: NoteId, revision: usize) -> Result<(), ChangeError>;
 }
 
 pub trait ChangeLink {
@@ -69,6 +138,58 @@ pub trait ChangeLink {
         to_note: NoteId,
         reason: String,
     ) -> Result<(), ChangeError>;
+
+    ///
+    /// [`change_link_relation`] sets or removes the relation of a link.
+    ///
+    fn change_link_relation(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Option<Relation>,
+    ) -> Result<(), ChangeError>;
+
+    ///
+    /// [`change_link_weight`] sets or removes the weight of a link.
+    ///
+    fn change_link_weight(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        weight: Option<f64>,
+    ) -> Result<(), ChangeError>;
+
+    ///
+    /// [`change_link_annotation`] sets the value of an annotation of a link, or removes the
+    /// annotation when the value is `None`.
+    ///
+    fn change_link_annotation(
+        &mut self,
+        from_note: NoteId,
+        to_note: NoteId,
+        key: String,
+        value: Option<String>,
+    ) -> Result<(), ChangeError>;
+
+    ///
+    /// [`move_forwardlink`] moves a link or branch of a note to a position among its forward
+    /// links.
+    ///
+    fn move_forwardlink(
+        &mut self,
+        note: NoteId,
+        item: FLinkId,
+        position: usize,
+    ) -> Result<(), ChangeError>;
+
+    fn swap_forwardlinks(
+        &mut self,
+        note: NoteId,
+        first: FLinkId,
+        second: FLinkId,
+    ) -> Result<(), ChangeError>;
+
+    fn sort_forwardlinks(&mut self, note: NoteId, key: SortKey) -> Result<(), ChangeError>;
 }
 
 pub trait ChangeBranch {
@@ -86,12 +207,51 @@ pub trait ChangeBranch {
         reason: String,
     ) -> Result<(), ChangeError>;
 
+    ///
+    /// [`collapse_branch`] replaces a branch with the link to one of its options, which can be an
+    /// option of a branch nested in it. A nested branch is replaced within its parent.
+    ///
     fn collapse_branch(
         &mut self,
         note: NoteId,
         branch: BranchId,
         link_note: NoteId,
     ) -> Result<(), ChangeError>;
+
+    ///
+    /// [`move_branch_option`] moves a link or nested branch to a position among the options of
+    /// a branch.
+    ///
+    fn move_branch_option(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        item: FLinkId,
+        position: usize,
+    ) -> Result<(), ChangeError>;
+
+    fn swap_branch_options(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        first: FLinkId,
+        second: FLinkId,
+    ) -> Result<(), ChangeError>;
+
+    fn sort_branch_options(
+        &mut self,
+        note: NoteId,
+        branch: BranchId,
+        key: SortKey,
+    ) -> Result<(), ChangeError>;
+}
+
+pub trait ChangeTag {
+    ///
+    /// [`rename_tag`] renames a tag on every note that carries it. Tags under it are moved along,
+    /// renaming `project` to `work` turns `project/backend` into `work/backend`.
+    ///
+    fn rename_tag(&mut self, from: Tag, to: Tag) -> Result<(), ChangeError>;
 }
 
 pub trait DeleteNote {
@@ -103,6 +263,11 @@ pub trait DeleteLink {
 }
 
 pub trait DeleteBranch {
+    ///
+    /// [`delete_branch`] removes a branch along with its options, top level and nested branches
+    /// alike. Branches nested in it are deleted with it, and the notes its options led to are
+    /// subject to the deletion policy.
+    ///
     fn delete_branch(&mut self, note: NoteId, branch: BranchId) -> Result<(), DeleteError>;
     fn delete_branch_link(
         &mut self,
@@ -112,19 +277,159 @@ pub trait DeleteBranch {
     ) -> Result<(), DeleteError>;
 }
 
+pub trait DeleteTag {
+    fn remove_tag(&mut self, note: NoteId, tag: Tag) -> Result<(), DeleteError>;
+}
+
+///
+/// [`Trash`] gives access to what was deleted. Deleting notes, links and branches moves them to
+/// the trash, together with the links they had, instead of removing them for good.
+///
+pub trait Trash {
+    ///
+    /// [`list_trash`] lists the entries of the trash, oldest deletion first.
+    ///
+    fn list_trash(&self) -> Result<Vec<&TrashEntry>, TrashError>;
+
+    ///
+    /// [`restore_from_trash`] puts back everything a deletion removed and takes the entry out of
+    /// the trash. The notes it links to, and the notes and branches its links were removed from,
+    /// have to exist. Links that were added again in the meantime are left as they are. In strict
+    /// mode the restore fails with [`TrashError::CycleDetected`] if a link would close a cycle.
+    ///
+    fn restore_from_trash(&mut self, entry: TrashId) -> Result<(), TrashError>;
+
+    ///
+    /// [`empty_trash`] removes every entry of the trash for good.
+    ///
+    fn empty_trash(&mut self) -> Result<(), TrashError>;
+
+    ///
+    /// [`purge_trash`] removes for good the entries deleted before the given time, returning how
+    /// many were removed.
+    ///
+    fn purge_trash(&mut self, before: OffsetDateTime) -> Result<usize, TrashError>;
+}
+
 pub trait ReadNote {
     fn read_note(&self, note: NoteId) -> Result<&Note, ReadError>;
+
+    ///
+    /// [`list_root_notes`] lists the notes that are not linked to by any other note, i.e. notes
+    /// without backlinks.
+    ///
     fn list_root_notes(&self) -> Result<Vec<&NoteId>, ReadError>;
+
+    ///
+    /// [`query_notes_by_date`] lists the notes created or modified in a range of time, sorted by
+    /// the timestamp the query asks for.
+    ///
+    fn query_notes_by_date(&self, query: &DateQuery) -> Result<Vec<&Note>, ReadError>;
+
+    ///
+    /// [`list_revisions`] lists the earlier revisions of a note, oldest first. The revisions are
+    /// numbered by their position in this list.
+    ///
+    fn list_revisions(&self, note: NoteId) -> Result<Vec<&NoteRevision>, ReadError>;
+
+    ///
+    /// [`diff_revisions`] compares two revisions of a note line by line. The number following the
+    /// last revision stands for the current content.
+    ///
+    fn diff_revisions(
+        &self,
+        note: NoteId,
+        from: usize,
+        to: usize,
+    ) -> Result<RevisionDiff, ReadError>;
 }
 
 pub trait ReadLink {
     fn list_forwardlinks(&self, note: NoteId) -> Result<Vec<&FLink>, ReadError>;
+
+    ///
+    /// [`list_unmarked_forwardlinks`] lists the forward links that still point to open notes, the
+    /// ones that are not in a terminal state. A link is included if its target is open, a branch
+    /// is included if any of its options is.
+    ///
     fn list_unmarked_forwardlinks(&self, note: NoteId) -> Result<Vec<&FLink>, ReadError>;
     fn list_pure_links(&self, note: NoteId) -> Result<Vec<&Link>, ReadError>;
     fn list_backlinks(&self, note: NoteId) -> Result<Vec<&NoteId>, ReadError>;
+
+    ///
+    /// [`list_forwardlinks_by_relation`] lists the links of a note that have the relation.
+    ///
+    fn list_forwardlinks_by_relation(
+        &self,
+        note: NoteId,
+        relation: &Relation,
+    ) -> Result<Vec<&Link>, ReadError>;
+
+    ///
+    /// [`list_backlinks_by_relation`] lists the notes linking to a note with the relation. With
+    /// [`Relation::depends_on`] these are the notes the note blocks.
+    ///
+    fn list_backlinks_by_relation(
+        &self,
+        note: NoteId,
+        relation: &Relation,
+    ) -> Result<Vec<&NoteId>, ReadError>;
 }
 
 pub trait ReadBranch {
+    ///
+    /// [`list_branches`] lists the branches at the top of a note, see [`list_sub_branches`] for
+    /// the branches nested in them.
+    ///
     fn list_branches(&self, note: NoteId) -> Result<Vec<&BranchId>, ReadError>;
     fn list_branch_links(&self, branch: BranchId) -> Result<Vec<&Link>, ReadError>;
+
+    ///
+    /// [`find_branch_note`] finds the note that owns the branch, this allows a branch to be
+    /// addressed without knowing the note it lives in.
+    ///
+    fn find_branch_note(&self, branch: BranchId) -> Result<&NoteId, ReadError>;
+
+    fn list_sub_branches(&self, branch: BranchId) -> Result<Vec<&BranchId>, ReadError>;
+
+    ///
+    /// [`find_branch_path`] lists the branches leading to a nested branch, from the branch at the
+    /// top of its note down to the branch itself.
+    ///
+    fn find_branch_path(&self, branch: BranchId) -> Result<Vec<BranchId>, ReadError>;
+}
+
+pub trait ReadTag {
+    fn list_tags(&self, note: NoteId) -> Result<Vec<&Tag>, ReadError>;
+
+    ///
+    /// [`list_tagged_notes`] lists the notes carrying the tag or any tag under it.
+    ///
+    fn list_tagged_notes(&self, tag: Tag) -> Result<Vec<&NoteId>, ReadError>;
+
+    ///
+    /// [`find_notes_by_tags`] lists the notes that are under every tag of `include` and under none
+    /// of the tags of `exclude`. An empty `include` matches every note.
+    ///
+    fn find_notes_by_tags(
+        &self,
+        include: &[Tag],
+        exclude: &[Tag],
+    ) -> Result<Vec<&NoteId>, ReadError>;
+
+    ///
+    /// [`tag_counts`] lists every tag in use along with the number of notes carrying it directly.
+    ///
+    fn tag_counts(&self) -> Result<Vec<(&Tag, usize)>, ReadError>;
+}
+
+pub trait Search {
+    ///
+    /// [`search`] finds the notes matching every part of the query, best match first. Titles,
+    /// subtitles, bodies, link reasons and branch conditions are searched.
+    ///
+    /// A query is made of words, `"quoted phrases"` that must appear in that order, and `prefix*`
+    /// words. Matching ignores case.
+    ///
+    fn search(&self, query: &str) -> Result<Vec<SearchHit>, SearchError>;
 }
@@ -0,0 +1,455 @@
+//!
+//! The operation module describes every mutation that can be made through the [`Add`],
+//! [`Change`], [`Delete`] and [`Trash`] traits as a typed record. Records can be applied back to a
+//! [`NotesManager`], which is what journals use to rebuild a manager.
+//!
+//! [`Add`]: crate::prelude::Add
+//! [`Change`]: crate::prelude::Change
+//! [`Delete`]: crate::prelude::Delete
+//! [`Trash`]: crate::prelude::Trash
+//!
+
+use error_stack::{Result, ResultExt};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::OperationError;
+use crate::manager_impl::{
+    AddBranch, AddLink, AddNote, AddTag, ChangeBranch, ChangeLink, ChangeNote, ChangeTag,
+    DeleteBranch, DeleteLink, DeleteNote, DeleteTag, Trash,
+};
+use crate::prelude::NotesManager;
+use crate::types::{
+    BranchId, DeletionPolicy, FLinkId, Note, NoteId, Relation, SortKey, Tag, TaskState, TaskStates,
+    TrashId,
+};
+
+///
+/// [`Operation`] is a single call on one of the mutating traits, together with its arguments.
+/// Generated values, like the id of a new branching, are part of the record so that applying it
+/// again produces the same state.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "operation", rename_all = "snake_case")
+)]
+pub enum Operation {
+    AddNote {
+        note: Note,
+    },
+    AddLink {
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    },
+    AddTypedLink {
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Relation,
+        reason: String,
+    },
+    AddBidirectionalLink {
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    },
+    CreateBranching {
+        note: NoteId,
+        branch: BranchId,
+        condition: String,
+    },
+    CreateSubBranching {
+        note: NoteId,
+        on_branch: BranchId,
+        branch: BranchId,
+        condition: String,
+    },
+    AddBranch {
+        note: NoteId,
+        on_branch: BranchId,
+        link_note: NoteId,
+        reason: String,
+    },
+    AddTag {
+        note: NoteId,
+        tag: Tag,
+    },
+    ChangeNoteTitle {
+        note: NoteId,
+        title: String,
+    },
+    ChangeNoteSubtitle {
+        note: NoteId,
+        subtitle: String,
+    },
+    ChangeNoteBody {
+        note: NoteId,
+        body: String,
+    },
+    ChangeNoteState {
+        note: NoteId,
+        state: TaskState,
+    },
+    MarkNote {
+        note: NoteId,
+    },
+    UnmarkNote {
+        note: NoteId,
+    },
+    ReconsileNodes,
+    RestoreRevision {
+        note: NoteId,
+        revision: usize,
+    },
+    ChangeLinkReason {
+        from_note: NoteId,
+        to_note: NoteId,
+        reason: String,
+    },
+    ChangeLinkRelation {
+        from_note: NoteId,
+        to_note: NoteId,
+        relation: Option<Relation>,
+    },
+    ChangeLinkWeight {
+        from_note: NoteId,
+        to_note: NoteId,
+        weight: Option<f64>,
+    },
+    ChangeLinkAnnotation {
+        from_note: NoteId,
+        to_note: NoteId,
+        key: String,
+        value: Option<String>,
+    },
+    MoveForwardlink {
+        note: NoteId,
+        item: FLinkId,
+        position: usize,
+    },
+    SwapForwardlinks {
+        note: NoteId,
+        first: FLinkId,
+        second: FLinkId,
+    },
+    SortForwardlinks {
+        note: NoteId,
+        key: SortKey,
+    },
+    ChangeBranchCondition {
+        note: NoteId,
+        branch: BranchId,
+        condition: String,
+    },
+    ChangeBranchReason {
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+        reason: String,
+    },
+    CollapseBranch {
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+    },
+    MoveBranchOption {
+        note: NoteId,
+        branch: BranchId,
+        item: FLinkId,
+        position: usize,
+    },
+    SwapBranchOptions {
+        note: NoteId,
+        branch: BranchId,
+        first: FLinkId,
+        second: FLinkId,
+    },
+    SortBranchOptions {
+        note: NoteId,
+        branch: BranchId,
+        key: SortKey,
+    },
+    RenameTag {
+        from: Tag,
+        to: Tag,
+    },
+    DeleteNote {
+        note: NoteId,
+    },
+    DeleteLink {
+        from_note: NoteId,
+        to_note: NoteId,
+    },
+    DeleteBranch {
+        note: NoteId,
+        branch: BranchId,
+    },
+    DeleteBranchLink {
+        note: NoteId,
+        branch: BranchId,
+        link_note: NoteId,
+    },
+    RemoveTag {
+        note: NoteId,
+        tag: Tag,
+    },
+    RestoreFromTrash {
+        entry: TrashId,
+    },
+    EmptyTrash,
+    PurgeTrash {
+        before: OffsetDateTime,
+    },
+    SetDeletionPolicy {
+        policy: DeletionPolicy,
+    },
+    SetTaskStates {
+        states: TaskStates,
+    },
+    SetAcyclic {
+        acyclic: bool,
+    },
+    SetRevisionRetention {
+        retention: usize,
+    },
+    SetTrashRetention {
+        retention: Option<Duration>,
+    },
+    SetHistoryDepth {
+        depth: usize,
+    },
+}
+
+impl Operation {
+    ///
+    /// [`apply`] performs the operation on the manager.
+    ///
+    pub fn apply(&self, manager: &mut NotesManager) -> Result<(), OperationError> {
+        match self.clone() {
+            Operation::AddNote { note } => manager
+                .add_note(note)
+                .map(|_| ())
+                .change_context(OperationError::ApplyFailed),
+            Operation::AddLink {
+                from_note,
+                to_note,
+                reason,
+            } => manager
+                .add_link(from_note, to_note, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::AddTypedLink {
+                from_note,
+                to_note,
+                relation,
+                reason,
+            } => manager
+                .add_typed_link(from_note, to_note, relation, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::AddBidirectionalLink {
+                from_note,
+                to_note,
+                reason,
+            } => manager
+                .add_bidirectional_link(from_note, to_note, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::CreateBranching {
+                note,
+                branch,
+                condition,
+            } => manager
+                .create_branching_with_id(note, branch, condition)
+                .map(|_| ())
+                .change_context(OperationError::ApplyFailed),
+            Operation::CreateSubBranching {
+                note,
+                on_branch,
+                branch,
+                condition,
+            } => manager
+                .create_sub_branching_with_id(note, on_branch, branch, condition)
+                .map(|_| ())
+                .change_context(OperationError::ApplyFailed),
+            Operation::AddBranch {
+                note,
+                on_branch,
+                link_note,
+                reason,
+            } => manager
+                .add_branch(note, on_branch, link_note, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::AddTag { note, tag } => manager
+                .add_tag(note, tag)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeNoteTitle { note, title } => manager
+                .change_note_title(note, title)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeNoteSubtitle { note, subtitle } => manager
+                .change_note_subtitle(note, subtitle)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeNoteBody { note, body } => manager
+                .change_note_body(note, body)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeNoteState { note, state } => manager
+                .change_note_state(note, state)
+                .change_context(OperationError::ApplyFailed),
+            Operation::MarkNote { note } => manager
+                .mark_note(note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::UnmarkNote { note } => manager
+                .unmark_note(note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ReconsileNodes => manager
+                .reconsile_nodes()
+                .map(|_| ())
+                .change_context(OperationError::ApplyFailed),
+            Operation::RestoreRevision { note, revision } => manager
+                .restore_revision(note, revision)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeLinkReason {
+                from_note,
+                to_note,
+                reason,
+            } => manager
+                .change_link_reason(from_note, to_note, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeLinkRelation {
+                from_note,
+                to_note,
+                relation,
+            } => manager
+                .change_link_relation(from_note, to_note, relation)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeLinkWeight {
+                from_note,
+                to_note,
+                weight,
+            } => manager
+                .change_link_weight(from_note, to_note, weight)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeLinkAnnotation {
+                from_note,
+                to_note,
+                key,
+                value,
+            } => manager
+                .change_link_annotation(from_note, to_note, key, value)
+                .change_context(OperationError::ApplyFailed),
+            Operation::MoveForwardlink {
+                note,
+                item,
+                position,
+            } => manager
+                .move_forwardlink(note, item, position)
+                .change_context(OperationError::ApplyFailed),
+            Operation::SwapForwardlinks {
+                note,
+                first,
+                second,
+            } => manager
+                .swap_forwardlinks(note, first, second)
+                .change_context(OperationError::ApplyFailed),
+            Operation::SortForwardlinks { note, key } => manager
+                .sort_forwardlinks(note, key)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeBranchCondition {
+                note,
+                branch,
+                condition,
+            } => manager
+                .change_branch_condition(note, branch, condition)
+                .change_context(OperationError::ApplyFailed),
+            Operation::ChangeBranchReason {
+                note,
+                branch,
+                link_note,
+                reason,
+            } => manager
+                .change_branch_reason(note, branch, link_note, reason)
+                .change_context(OperationError::ApplyFailed),
+            Operation::CollapseBranch {
+                note,
+                branch,
+                link_note,
+            } => manager
+                .collapse_branch(note, branch, link_note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::MoveBranchOption {
+                note,
+                branch,
+                item,
+                position,
+            } => manager
+                .move_branch_option(note, branch, item, position)
+                .change_context(OperationError::ApplyFailed),
+            Operation::SwapBranchOptions {
+                note,
+                branch,
+                first,
+                second,
+            } => manager
+                .swap_branch_options(note, branch, first, second)
+                .change_context(OperationError::ApplyFailed),
+            Operation::SortBranchOptions { note, branch, key } => manager
+                .sort_branch_options(note, branch, key)
+                .change_context(OperationError::ApplyFailed),
+            Operation::RenameTag { from, to } => manager
+                .rename_tag(from, to)
+                .change_context(OperationError::ApplyFailed),
+            Operation::DeleteNote { note } => manager
+                .delete_note(note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::DeleteLink { from_note, to_note } => manager
+                .delete_link(from_note, to_note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::DeleteBranch { note, branch } => manager
+                .delete_branch(note, branch)
+                .change_context(OperationError::ApplyFailed),
+            Operation::DeleteBranchLink {
+                note,
+                branch,
+                link_note,
+            } => manager
+                .delete_branch_link(note, branch, link_note)
+                .change_context(OperationError::ApplyFailed),
+            Operation::RemoveTag { note, tag } => manager
+                .remove_tag(note, tag)
+                .change_context(OperationError::ApplyFailed),
+            Operation::RestoreFromTrash { entry } => manager
+                .restore_from_trash(entry)
+                .change_context(OperationError::ApplyFailed),
+            Operation::EmptyTrash => manager
+                .empty_trash()
+                .change_context(OperationError::ApplyFailed),
+            Operation::PurgeTrash { before } => manager
+                .purge_trash(before)
+                .map(|_| ())
+                .change_context(OperationError::ApplyFailed),
+            Operation::SetDeletionPolicy { policy } => {
+                manager.set_deletion_policy(policy);
+                Ok(())
+            }
+            Operation::SetTaskStates { states } => {
+                manager.set_task_states(states);
+                Ok(())
+            }
+            Operation::SetAcyclic { acyclic } => {
+                manager.set_acyclic(acyclic);
+                Ok(())
+            }
+            Operation::SetRevisionRetention { retention } => {
+                manager.set_revision_retention(retention);
+                Ok(())
+            }
+            Operation::SetTrashRetention { retention } => {
+                manager.set_trash_retention(retention);
+                Ok(())
+            }
+            Operation::SetHistoryDepth { depth } => {
+                manager.set_history_depth(depth);
+                Ok(())
+            }
+        }
+        .attach_printable_lazy(|| format!("{:?}", self))
+    }
+}
@@ -0,0 +1,33 @@
+//!
+//! The prelude re-exports the [`NotesManager`] along with the traits that operate on it, so a
+//! single glob import is enough to work with notes.
+//!
+//! ```rust
+//! use branch_core::prelude::*;
+//!
+//! let mut manager = NotesManager::default();
+//!
+//! let from = manager
+//!     .add_note(Note::new("from".to_string(), None, "body".to_string()))
+//!     .unwrap();
+//! let to = manager
+//!     .add_note(Note::new("to".to_string(), None, "body".to_string()))
+//!     .unwrap();
+//!
+//! manager.add_link(from.clone(), to.clone(), "reason".to_string()).unwrap();
+//!
+//! assert_eq!(manager.list_backlinks(to).unwrap(), vec![&from]);
+//! ```
+//!
+
+pub use crate::manager::{NotesManager, DEFAULT_HISTORY_DEPTH, DEFAULT_REVISION_RETENTION};
+pub use crate::manager_impl::{
+    Add, AddBranch, AddLink, AddNote, AddTag, Change, ChangeBranch, ChangeLink, ChangeNote,
+    ChangeTag, Delete, DeleteBranch, DeleteLink, DeleteNote, DeleteTag, Read, ReadBranch, ReadLink,
+    ReadNote, ReadTag, Search, Trash,
+};
+pub use crate::types::{
+    Branch, BranchId, DateField, DateQuery, DeletionPolicy, DeletionReport, DiffLine, FLink,
+    FLinkId, Link, Note, NoteId, NoteRevision, Relation, RevisionDiff, SearchHit, Settings,
+    SortKey, StateChange, Tag, TaskState, TaskStates, TrashEntry, TrashId, TrashedLink,
+};
@@ -0,0 +1,345 @@
+//!
+//! The session module walks a decision tree. A [`DecisionSession`] starts at a note, presents the
+//! branches of the current note, and moves to the option that is picked. Choices can be taken
+//! back, and nothing is written to the graph until the [`Outcome`] of the session is committed.
+//!
+//! ```rust
+//! use branch_core::prelude::*;
+//! use branch_core::session::DecisionSession;
+//!
+//! let mut manager = NotesManager::default();
+//! let trip = manager
+//!     .add_note(Note::new("trip".to_string(), None, String::new()))
+//!     .unwrap();
+//! let train = manager
+//!     .add_note(Note::new("train".to_string(), None, String::new()))
+//!     .unwrap();
+//! let plane = manager
+//!     .add_note(Note::new("plane".to_string(), None, String::new()))
+//!     .unwrap();
+//! let branch = manager
+//!     .create_branching(trip.clone(), "how to travel".to_string())
+//!     .unwrap();
+//! manager.add_branch(trip.clone(), branch.clone(), train.clone(), "cheaper".to_string()).unwrap();
+//! manager.add_branch(trip.clone(), branch.clone(), plane.clone(), "faster".to_string()).unwrap();
+//!
+//! let mut session = DecisionSession::start(&manager, trip.clone()).unwrap();
+//! assert_eq!(session.decisions().unwrap()[0].condition, "how to travel");
+//!
+//! session.choose(branch.clone(), plane.clone()).unwrap();
+//! assert!(session.is_finished());
+//!
+//! let outcome = session.finish();
+//! assert_eq!(outcome.path, vec![trip.clone(), plane.clone()]);
+//!
+//! outcome.commit(&mut manager).unwrap();
+//! assert_eq!(manager.list_branches(trip).unwrap().len(), 0);
+//! ```
+//!
+
+use error_stack::{ensure, Result, ResultExt};
+
+use crate::errors::{ChangeError, SessionError};
+use crate::manager_impl::{ChangeBranch, ReadNote};
+use crate::prelude::NotesManager;
+use crate::types::{Branch, BranchId, FLink, Note, NoteId};
+
+///
+/// [`Choice`] is an option picked on a branch during a session.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Choice {
+    pub note: NoteId,
+    pub branch: BranchId,
+    pub option: NoteId,
+}
+
+///
+/// [`Outcome`] is the result of a session: the notes that were walked through, starting with the
+/// note the session started at, and the choices that led there.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub path: Vec<NoteId>,
+    pub choices: Vec<Choice>,
+}
+
+///
+/// [`DecisionSession`] walks the branches of a graph without modifying it.
+///
+pub struct DecisionSession<'a, R: ?Sized> {
+    reader: &'a R,
+    start: NoteId,
+    choices: Vec<Choice>,
+}
+
+impl<'a, R: ReadNote + ?Sized> DecisionSession<'a, R> {
+    pub fn start(reader: &'a R, note: NoteId) -> Result<Self, SessionError> {
+        reader
+            .read_note(note.clone())
+            .change_context(SessionError::NoteDoesNotExist)?;
+
+        Ok(DecisionSession {
+            reader,
+            start: note,
+            choices: Vec::new(),
+        })
+    }
+
+    ///
+    /// [`current`] is the note the session is at, the last option that was picked.
+    ///
+    pub fn current(&self) -> &NoteId {
+        self.choices
+            .last()
+            .map(|choice| &choice.option)
+            .unwrap_or(&self.start)
+    }
+
+    pub fn choices(&self) -> &[Choice] {
+        &self.choices
+    }
+
+    pub fn path(&self) -> Vec<NoteId> {
+        std::iter::once(&self.start)
+            .chain(self.choices.iter().map(|choice| &choice.option))
+            .cloned()
+            .collect()
+    }
+
+    fn current_note(&self) -> Result<&'a Note, SessionError> {
+        self.reader
+            .read_note(self.current().clone())
+            .change_context(SessionError::NoteDoesNotExist)
+    }
+
+    ///
+    /// [`decisions`] lists the branches of the current note that are still open. A branch that was
+    /// already decided earlier in the session is not presented again.
+    ///
+    pub fn decisions(&self) -> Result<Vec<&'a Branch>, SessionError> {
+        Ok(self
+            .current_note()?
+            .forwardlinks
+            .iter()
+            .filter_map(|flink| match flink {
+                FLink::Branch(branch) => Some(branch),
+                FLink::Link(_) => None,
+            })
+            .filter(|branch| {
+                !self
+                    .choices
+                    .iter()
+                    .any(|choice| &choice.branch == branch.id())
+            })
+            .collect())
+    }
+
+    ///
+    /// [`is_finished`] is true once the current note has no open decision left.
+    ///
+    pub fn is_finished(&self) -> bool {
+        self.decisions()
+            .map(|decisions| decisions.is_empty())
+            .unwrap_or(true)
+    }
+
+    ///
+    /// [`choose`] picks an option on one of the open branches of the current note, and moves the
+    /// session to that option. Options of the branches nested in it can be picked directly.
+    ///
+    pub fn choose(&mut self, branch: BranchId, option: NoteId) -> Result<(), SessionError> {
+        ensure!(
+            !self.choices.iter().any(|choice| choice.branch == branch),
+            SessionError::BranchAlreadyDecided
+        );
+
+        let branch_ = self
+            .decisions()?
+            .into_iter()
+            .find(|branch_| branch_.id() == &branch)
+            .ok_or(SessionError::BranchDoesNotExist)?;
+
+        ensure!(
+            branch_.targets().iter().any(|link| link.id == option),
+            SessionError::OptionDoesNotExist
+        );
+        ensure!(
+            self.reader.read_note(option.clone()).is_ok(),
+            SessionError::NoteDoesNotExist
+        );
+
+        self.choices.push(Choice {
+            note: self.current().clone(),
+            branch,
+            option,
+        });
+
+        Ok(())
+    }
+
+    ///
+    /// [`back`] takes back the last choice, returning the session to the note it was made on.
+    ///
+    pub fn back(&mut self) -> Result<Choice, SessionError> {
+        Ok(self.choices.pop().ok_or(SessionError::NothingToBacktrack)?)
+    }
+
+    pub fn finish(self) -> Outcome {
+        Outcome {
+            path: self.path(),
+            choices: self.choices,
+        }
+    }
+}
+
+impl Outcome {
+    ///
+    /// [`commit`] collapses every branch that was decided onto the chosen option, all-or-nothing.
+    ///
+    pub fn commit(&self, manager: &mut NotesManager) -> Result<(), ChangeError> {
+        manager.transaction(|manager| {
+            for choice in &self.choices {
+                manager.collapse_branch(
+                    choice.note.clone(),
+                    choice.branch.clone(),
+                    choice.option.clone(),
+                )?;
+            }
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote, ReadLink};
+    use crate::test_util::{id, note};
+
+    ///
+    /// a: first? b | c
+    /// b: second? d | e
+    /// a -> e (plain link)
+    ///
+    fn graph() -> (NotesManager, BranchId, BranchId) {
+        let mut manager = NotesManager::default();
+        for note_id in ["a", "b", "c", "d", "e"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        let first = manager
+            .create_branching(id("a"), "first".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), first.clone(), id("b"), "b".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), first.clone(), id("c"), "c".to_string())
+            .unwrap();
+        let second = manager
+            .create_branching(id("b"), "second".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("b"), second.clone(), id("d"), "d".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("b"), second.clone(), id("e"), "e".to_string())
+            .unwrap();
+        manager
+            .add_link(id("a"), id("e"), "reason".to_string())
+            .unwrap();
+
+        (manager, first, second)
+    }
+
+    #[test]
+    fn test_walk_and_backtrack() {
+        let (manager, first, second) = graph();
+        let before = manager
+            .sorted_notes()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut session = DecisionSession::start(&manager, id("a")).unwrap();
+
+        assert_eq!(session.decisions().unwrap()[0].id(), &first);
+        assert_eq!(
+            session
+                .choose(first.clone(), id("e"))
+                .unwrap_err()
+                .current_context(),
+            &SessionError::OptionDoesNotExist
+        );
+        assert_eq!(
+            session
+                .choose(second.clone(), id("d"))
+                .unwrap_err()
+                .current_context(),
+            &SessionError::BranchDoesNotExist
+        );
+
+        session.choose(first.clone(), id("c")).unwrap();
+        assert!(session.is_finished());
+
+        assert_eq!(session.back().unwrap().option, id("c"));
+        assert_eq!(session.current(), &id("a"));
+
+        session.choose(first.clone(), id("b")).unwrap();
+        session.choose(second.clone(), id("e")).unwrap();
+        assert!(session.is_finished());
+
+        let outcome = session.finish();
+
+        assert_eq!(outcome.path, vec![id("a"), id("b"), id("e")]);
+        assert_eq!(outcome.choices.len(), 2);
+        assert_eq!(
+            manager
+                .sorted_notes()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+            before
+        );
+    }
+
+    #[test]
+    fn test_commit() {
+        let (mut manager, first, second) = graph();
+
+        let mut session = DecisionSession::start(&manager, id("a")).unwrap();
+        session.choose(first, id("b")).unwrap();
+        session.choose(second, id("d")).unwrap();
+        let outcome = session.finish();
+
+        outcome.commit(&mut manager).unwrap();
+
+        assert_eq!(
+            manager
+                .list_pure_links(id("a"))
+                .unwrap()
+                .iter()
+                .map(|link| &link.id)
+                .collect::<Vec<_>>(),
+            vec![&id("b"), &id("e")]
+        );
+        assert_eq!(manager.list_pure_links(id("b")).unwrap()[0].id, id("d"));
+
+        // The branches are gone, so committing the same outcome again fails without changes.
+        let before = manager
+            .sorted_notes()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(outcome.commit(&mut manager).is_err());
+        assert_eq!(
+            manager
+                .sorted_notes()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+            before
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//!
+//! The storage module defines how a [`NotesManager`] is persisted. A [`Storage`] only deals with
+//! notes, the trash and the settings, the manager takes care of rebuilding the derived state (like backlinks)
+//! when loading.
+//!
+//! [`NotesManager`]: crate::prelude::NotesManager
+//!
+
+use error_stack::Result;
+
+use crate::errors::StorageError;
+use crate::types::{Note, NoteId, Settings, TrashEntry};
+
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "markdown")]
+mod markdown;
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "json")]
+pub use json::JsonStorage;
+#[cfg(feature = "markdown")]
+pub use markdown::MarkdownStorage;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+///
+/// [`Storage`] is a backend that notes can be loaded from and saved to.
+///
+/// Backlinks are derived from the forward links, so a storage is not required to persist them.
+///
+pub trait Storage {
+    ///
+    /// [`load_notes`] reads every note in the storage.
+    ///
+    fn load_notes(&self) -> Result<Vec<Note>, StorageError>;
+
+    ///
+    /// [`load_note`] reads a single note without loading the rest of the storage. The backlinks of
+    /// the returned note are only filled in if the storage is able to derive them.
+    ///
+    fn load_note(&self, note: &NoteId) -> Result<Option<Note>, StorageError>;
+
+    ///
+    /// [`save_notes`] replaces the content of the storage with the given notes. Notes that are
+    /// present in the storage but not in `notes` are removed.
+    ///
+    fn save_notes(&mut self, notes: &[&Note]) -> Result<(), StorageError>;
+
+    ///
+    /// [`load_trash`] reads the entries of the trash. A storage that does not keep the trash
+    /// returns an empty one.
+    ///
+    fn load_trash(&self) -> Result<Vec<TrashEntry>, StorageError> {
+        Ok(Vec::new())
+    }
+
+    ///
+    /// [`save_trash`] replaces the entries of the trash kept in the storage. A storage that does
+    /// not keep the trash ignores them.
+    ///
+    fn save_trash(&mut self, _trash: &[&TrashEntry]) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    ///
+    /// [`load_settings`] reads the settings of the manager. A storage that does not keep them
+    /// returns the default ones.
+    ///
+    fn load_settings(&self) -> Result<Settings, StorageError> {
+        Ok(Settings::default())
+    }
+
+    ///
+    /// [`save_settings`] replaces the settings kept in the storage. A storage that does not keep
+    /// them ignores them.
+    ///
+    fn save_settings(&mut self, _settings: &Settings) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+///
+/// [`format_timestamp`] renders a timestamp as an RFC 3339 string, keeping its offset.
+///
+#[cfg(any(feature = "markdown", feature = "sqlite"))]
+fn format_timestamp(timestamp: &time::OffsetDateTime) -> Result<String, StorageError> {
+    use error_stack::ResultExt;
+
+    timestamp
+        .format(&time::format_description::well_known::Rfc3339)
+        .change_context(StorageError::WriteFailed)
+}
+
+#[cfg(any(feature = "markdown", feature = "sqlite"))]
+fn parse_timestamp(timestamp: &str) -> Result<time::OffsetDateTime, StorageError> {
+    use error_stack::ResultExt;
+
+    time::OffsetDateTime::parse(timestamp, &time::format_description::well_known::Rfc3339)
+        .change_context(StorageError::MalformedNote)
+        .attach_printable_lazy(|| format!("invalid timestamp `{}`", timestamp))
+}
+
+#[cfg(any(feature = "markdown", feature = "sqlite"))]
+fn parse_id<T: std::str::FromStr<Err = crate::errors::ParseIdError>>(
+    id: &str,
+) -> Result<T, StorageError> {
+    use error_stack::ResultExt;
+
+    id.parse::<T>()
+        .map_err(error_stack::Report::new)
+        .change_context(StorageError::MalformedNote)
+        .attach_printable_lazy(|| format!("invalid id `{}`", id))
+}
+
+#[cfg(any(feature = "markdown", feature = "sqlite"))]
+fn parse_tag(tag: &str) -> Result<crate::types::Tag, StorageError> {
+    use error_stack::ResultExt;
+
+    tag.parse::<crate::types::Tag>()
+        .map_err(error_stack::Report::new)
+        .change_context(StorageError::MalformedNote)
+        .attach_printable_lazy(|| format!("invalid tag `{}`", tag))
+}
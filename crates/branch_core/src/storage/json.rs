@@ -0,0 +1,386 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use error_stack::{ensure, ResultExt};
+
+use crate::errors::StorageError;
+use crate::types::{Note, NoteId, Settings, TrashEntry};
+
+const FORMAT: &str = "de_note";
+
+///
+/// [`FORMAT_VERSION`] is the version of the document written by [`JsonStorage`]. It is bumped
+/// whenever a change to the document would be misread by an older reader.
+///
+pub const FORMAT_VERSION: u32 = 1;
+
+///
+/// [`JsonStorage`] stores the whole graph in a single, versioned JSON document. It is meant for
+/// backups, moving notes between machines and scripting with tools like `jq`.
+///
+/// ```json
+/// {
+///   "format": "de_note",
+///   "version": 1,
+///   "notes": [
+///     {
+///       "id": "V1StGXR8_Z5jdHi6B-myT",
+///       "state": "in-progress",
+///       "state_history": [
+///         {
+///           "from": "todo",
+///           "to": "in-progress",
+///           "timestamp": "2024-09-02 08:30:00.0 +02:00:00"
+///         }
+///       ],
+///       "title": "Title",
+///       "subtitle": null,
+///       "body": "Body of the note.",
+///       "backlinks": [],
+///       "forwardlinks": [
+///         {
+///           "kind": "link",
+///           "id": "Uakgb_J5m9g-0JDMbcJqL",
+///           "reason": "Reason",
+///           "created": "2024-09-01 10:00:00.0 +00:00:00",
+///           "modified": "2024-09-01 10:00:00.0 +00:00:00"
+///         },
+///         {
+///           "kind": "branch",
+///           "id": "3MbcJqLUakgb_J5m9g-0J",
+///           "condition": "Condition",
+///           "branches": [],
+///           "created": "2024-09-01 10:00:00.0 +00:00:00",
+///           "modified": "2024-09-02 08:30:00.0 +02:00:00"
+///         }
+///       ],
+///       "created": "2024-09-01 10:00:00.0 +00:00:00",
+///       "modified": "2024-09-02 08:30:00.0 +02:00:00"
+///     }
+///   ],
+///   "trash": [],
+///   "settings": {
+///     "acyclic": false,
+///     "deletion_policy": "cascade_orphans",
+///     "revision_retention": 50,
+///     "trash_retention": null,
+///     "history_depth": 100,
+///     "task_states": { ... }
+///   }
+/// }
+/// ```
+///
+/// Notes are written sorted by id, so that two dumps of the same graph are identical. The
+/// `backlinks` are written for convenience, but they are rebuilt from the forward links when the
+/// document is loaded into a manager. The entries of the trash are kept in a `trash` list next to
+/// the notes, and the settings of the manager in a `settings` object.
+///
+pub struct JsonStorage {
+    path: PathBuf,
+}
+
+#[derive(serde::Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Document<N, T> {
+    format: String,
+    version: u32,
+    notes: Vec<N>,
+    #[serde(default = "Vec::new")]
+    trash: Vec<T>,
+    #[serde(default)]
+    settings: Settings,
+}
+
+impl JsonStorage {
+    ///
+    /// [`new`] creates a storage backed by the given file, the file is created when the notes are
+    /// first saved.
+    ///
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonStorage { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read_document(&self) -> error_stack::Result<Document<Note, TrashEntry>, StorageError> {
+        if !self.path.exists() {
+            return Ok(Document {
+                format: FORMAT.to_string(),
+                version: FORMAT_VERSION,
+                notes: Vec::new(),
+                trash: Vec::new(),
+                settings: Settings::default(),
+            });
+        }
+
+        let content = fs::read_to_string(&self.path)
+            .change_context(StorageError::ReadFailed)
+            .attach_printable_lazy(|| self.path.display().to_string())?;
+
+        parse_document(&content).attach_printable_lazy(|| self.path.display().to_string())
+    }
+
+    fn write_document(
+        &self,
+        mut notes: Vec<&Note>,
+        trash: Vec<&TrashEntry>,
+        settings: Settings,
+    ) -> error_stack::Result<(), StorageError> {
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .change_context(StorageError::WriteFailed)
+                .attach_printable_lazy(|| parent.display().to_string())?;
+        }
+
+        notes.sort_by_key(|note| note.get_id());
+
+        let document = Document {
+            format: FORMAT.to_string(),
+            version: FORMAT_VERSION,
+            notes,
+            trash,
+            settings,
+        };
+
+        let mut content =
+            serde_json::to_string_pretty(&document).change_context(StorageError::WriteFailed)?;
+        content.push('\n');
+
+        let temporary = self.path.with_extension("json.tmp");
+
+        fs::write(&temporary, content)
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| self.path.display().to_string())
+    }
+}
+
+impl super::Storage for JsonStorage {
+    fn load_notes(&self) -> error_stack::Result<Vec<Note>, StorageError> {
+        Ok(self.read_document()?.notes)
+    }
+
+    fn load_note(&self, note: &NoteId) -> error_stack::Result<Option<Note>, StorageError> {
+        Ok(self
+            .load_notes()?
+            .into_iter()
+            .find(|stored| &stored.get_id() == note))
+    }
+
+    ///
+    /// [`save_notes`] keeps the trash and the settings already in the file, unless the file cannot
+    /// be read.
+    ///
+    fn save_notes(&mut self, notes: &[&Note]) -> error_stack::Result<(), StorageError> {
+        let (trash, settings) = self
+            .read_document()
+            .map(|document| (document.trash, document.settings))
+            .unwrap_or_default();
+
+        self.write_document(notes.to_vec(), trash.iter().collect(), settings)
+    }
+
+    fn load_trash(&self) -> error_stack::Result<Vec<TrashEntry>, StorageError> {
+        Ok(self.read_document()?.trash)
+    }
+
+    fn save_trash(&mut self, trash: &[&TrashEntry]) -> error_stack::Result<(), StorageError> {
+        let document = self.read_document()?;
+
+        self.write_document(
+            document.notes.iter().collect(),
+            trash.to_vec(),
+            document.settings,
+        )
+    }
+
+    fn load_settings(&self) -> error_stack::Result<Settings, StorageError> {
+        Ok(self.read_document()?.settings)
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> error_stack::Result<(), StorageError> {
+        let document = self.read_document()?;
+
+        self.write_document(
+            document.notes.iter().collect(),
+            document.trash.iter().collect(),
+            settings.clone(),
+        )
+    }
+}
+
+///
+/// [`parse_document`] checks the header of the document before reading the notes, so that a
+/// document written by a newer version is reported as such instead of as a malformed note.
+///
+fn parse_document(content: &str) -> error_stack::Result<Document<Note, TrashEntry>, StorageError> {
+    let header: Header =
+        serde_json::from_str(content).change_context(StorageError::MalformedNote)?;
+
+    ensure!(
+        header.format == FORMAT,
+        StorageError::UnsupportedFormat(header.format)
+    );
+    ensure!(
+        header.version == FORMAT_VERSION,
+        StorageError::UnsupportedVersion(header.version)
+    );
+
+    serde_json::from_str(content).change_context(StorageError::MalformedNote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{
+        AddBranch, AddLink, AddNote, AddTag, ChangeNote, DeleteNote, ReadLink, ReadNote, Trash,
+    };
+    use crate::prelude::NotesManager;
+    use crate::storage::Storage;
+    use crate::types::{DeletionPolicy, TaskStates};
+
+    #[test]
+    fn test_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut storage = JsonStorage::new(directory.path().join("notes.json"));
+
+        let mut manager = NotesManager::default();
+        let from = manager
+            .add_note(Note::new(
+                "from \"quoted\"".to_string(),
+                Some("subtitle".to_string()),
+                "# Body\n\nwith lines ✓\n".to_string(),
+            ))
+            .unwrap();
+        let to = manager
+            .add_note(Note::new("to".to_string(), None, String::new()))
+            .unwrap();
+        let other = manager
+            .add_note(Note::new("other".to_string(), None, String::new()))
+            .unwrap();
+        manager
+            .add_link(from.clone(), to.clone(), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(from.clone(), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(
+                from.clone(),
+                branch.clone(),
+                other.clone(),
+                "option".to_string(),
+            )
+            .unwrap();
+        manager.mark_note(to.clone()).unwrap();
+        manager
+            .add_tag(to.clone(), "project/backend".parse().unwrap())
+            .unwrap();
+
+        manager.save(&mut storage).unwrap();
+        let loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.sorted_notes(), manager.sorted_notes());
+        assert_eq!(
+            storage.load_note(&other).unwrap().as_ref(),
+            manager.read_note(other).ok()
+        );
+
+        let first = fs::read_to_string(storage.path()).unwrap();
+        loaded.save(&mut storage).unwrap();
+
+        assert_eq!(fs::read_to_string(storage.path()).unwrap(), first);
+    }
+
+    #[test]
+    fn test_trash_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut storage = JsonStorage::new(directory.path().join("notes.json"));
+
+        let mut manager = NotesManager::default();
+        let from = manager
+            .add_note(Note::new("from".to_string(), None, String::new()))
+            .unwrap();
+        let to = manager
+            .add_note(Note::new("to".to_string(), None, String::new()))
+            .unwrap();
+        manager
+            .add_link(from.clone(), to.clone(), "reason".to_string())
+            .unwrap();
+        manager.delete_note(to.clone()).unwrap();
+
+        manager.save(&mut storage).unwrap();
+        manager.save(&mut storage).unwrap();
+        let mut loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.list_trash().unwrap(), manager.list_trash().unwrap());
+
+        let entry = loaded.list_trash().unwrap()[0].id;
+        loaded.restore_from_trash(entry).unwrap();
+        assert_eq!(loaded.list_backlinks(to).unwrap(), vec![&from]);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut storage = JsonStorage::new(directory.path().join("notes.json"));
+
+        let mut manager = NotesManager::default();
+        manager.apply_settings(Settings {
+            acyclic: true,
+            deletion_policy: DeletionPolicy::RefuseOrphans,
+            revision_retention: 5,
+            trash_retention: Some(time::Duration::days(30)),
+            history_depth: 10,
+            task_states: TaskStates::default().with_done_enforced(true),
+        });
+        manager
+            .add_note(Note::new("a".to_string(), None, String::new()))
+            .unwrap();
+
+        manager.save(&mut storage).unwrap();
+        let loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.settings(), manager.settings());
+        assert_ne!(loaded.settings(), Settings::default());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        assert_eq!(
+            parse_document(r#"{ "format": "de_note", "version": 2, "notes": [] }"#)
+                .unwrap_err()
+                .current_context(),
+            &StorageError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            parse_document(r#"{ "format": "other", "version": 1, "notes": [] }"#)
+                .unwrap_err()
+                .current_context(),
+            &StorageError::UnsupportedFormat("other".to_string())
+        );
+        assert_eq!(
+            parse_document(r#"{ "format": "de_note", "version": 1, "notes": [{}] }"#)
+                .unwrap_err()
+                .current_context(),
+            &StorageError::MalformedNote
+        );
+        assert!(
+            parse_document(r#"{ "format": "de_note", "version": 1, "notes": [] }"#)
+                .unwrap()
+                .notes
+                .is_empty()
+        );
+    }
+}
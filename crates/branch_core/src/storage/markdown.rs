@@ -0,0 +1,841 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use error_stack::{Report, ResultExt};
+
+use super::{format_timestamp, parse_id, parse_tag, parse_timestamp};
+use crate::errors::StorageError;
+use crate::types::{
+    Branch, FLink, Link, Note, NoteId, NoteRevision, Relation, Settings, StateChange, TaskState,
+    TrashEntry,
+};
+
+const DELIMITER: &str = "+++";
+const EXTENSION: &str = "md";
+const SETTINGS_FILE: &str = ".settings.toml";
+const TRASH_FILE: &str = ".trash.toml";
+
+///
+/// [`MarkdownStorage`] stores every note as a Markdown file inside a directory. The metadata of
+/// the note is kept in a TOML frontmatter, and the body is the Markdown content of the file.
+///
+/// ```text
+/// +++
+/// id = "V1StGXR8_Z5jdHi6B-myT"
+/// title = "Title"
+/// subtitle = "Subtitle"
+/// state = "in-progress"
+/// created = "2024-09-01T10:00:00Z"
+/// modified = "2024-09-02T08:30:00+02:00"
+/// tags = ["project/backend"]
+///
+/// [[forwardlinks]]
+/// kind = "link"
+/// id = "Uakgb_J5m9g-0JDMbcJqL"
+/// reason = "Reason"
+/// relation = "depends-on"
+/// inverse = "blocks"
+/// weight = 0.8
+/// bidirectional = true
+/// created = "2024-09-01T10:00:00Z"
+/// modified = "2024-09-01T10:00:00Z"
+///
+/// [forwardlinks.annotations]
+/// source = "review"
+///
+/// [[revisions]]
+/// title = "Old title"
+/// body = "Old body of the note."
+/// timestamp = "2024-09-02T08:30:00+02:00"
+///
+/// [[state_history]]
+/// from = "todo"
+/// to = "in-progress"
+/// timestamp = "2024-09-02T08:30:00+02:00"
+/// +++
+///
+/// Body of the note.
+/// ```
+///
+/// Files are named after the id of the note, so that renaming a note does not move the file.
+///
+/// The settings of the manager and the trash are kept next to the notes, in the hidden
+/// `.settings.toml` and `.trash.toml` files.
+///
+pub struct MarkdownStorage {
+    root: PathBuf,
+}
+
+///
+/// [`TrashFile`] is the content of `.trash.toml`, TOML needs a table at the top.
+///
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TrashFile<E> {
+    #[serde(default = "Vec::new")]
+    trash: Vec<E>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Frontmatter {
+    id: String,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subtitle: Option<String>,
+    state: String,
+    created: String,
+    modified: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    forwardlinks: Vec<FrontmatterLink>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<FrontmatterRevision>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    state_history: Vec<FrontmatterStateChange>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FrontmatterRevision {
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subtitle: Option<String>,
+    body: String,
+    timestamp: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct FrontmatterStateChange {
+    from: String,
+    to: String,
+    timestamp: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum FrontmatterLink {
+    Link {
+        id: String,
+        #[serde(default)]
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        inverse: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weight: Option<f64>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        bidirectional: bool,
+        created: String,
+        modified: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        annotations: BTreeMap<String, String>,
+    },
+    Branch {
+        id: String,
+        condition: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<FrontmatterOption>,
+        created: String,
+        modified: String,
+    },
+}
+
+///
+/// [`FrontmatterOption`] is an option of a branch, a nested branch is told apart from a link by
+/// its condition.
+///
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+enum FrontmatterOption {
+    Branch {
+        id: String,
+        condition: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        options: Vec<FrontmatterOption>,
+        created: String,
+        modified: String,
+    },
+    Link {
+        id: String,
+        #[serde(default)]
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        inverse: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        weight: Option<f64>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        bidirectional: bool,
+        created: String,
+        modified: String,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        annotations: BTreeMap<String, String>,
+    },
+}
+
+impl MarkdownStorage {
+    ///
+    /// [`new`] creates a storage rooted at the given directory, the directory is created when the
+    /// notes are first saved.
+    ///
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        MarkdownStorage { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn note_path(&self, note: &NoteId) -> PathBuf {
+        self.root.join(format!("{}.{}", note, EXTENSION))
+    }
+
+    ///
+    /// [`note_files`] lists the Markdown files in the root directory that hold a note, along with
+    /// the note. Other Markdown files are left alone, even with a frontmatter of their own, like
+    /// the pages of a static site generator kept next to the notes.
+    ///
+    fn note_files(&self) -> error_stack::Result<Vec<(PathBuf, Note)>, StorageError> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.root)
+            .change_context(StorageError::ReadFailed)
+            .attach_printable_lazy(|| self.root.display().to_string())?
+        {
+            let path = entry.change_context(StorageError::ReadFailed)?.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+
+            let content = fs::read_to_string(&path)
+                .change_context(StorageError::ReadFailed)
+                .attach_printable_lazy(|| path.display().to_string())?;
+
+            if let Ok(note) = parse_note(&content) {
+                files.push((path, note));
+            }
+        }
+
+        files.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(files)
+    }
+
+    ///
+    /// [`read_sidecar`] reads one of the hidden files kept next to the notes, or returns `None` if
+    /// it was never written.
+    ///
+    fn read_sidecar<T: serde::de::DeserializeOwned>(
+        &self,
+        name: &str,
+    ) -> error_stack::Result<Option<T>, StorageError> {
+        let path = self.root.join(name);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        fs::read_to_string(&path)
+            .change_context(StorageError::ReadFailed)
+            .and_then(|content| {
+                toml::from_str(&content).change_context(StorageError::MalformedNote)
+            })
+            .attach_printable_lazy(|| path.display().to_string())
+            .map(Some)
+    }
+
+    fn write_sidecar(
+        &self,
+        name: &str,
+        value: &impl serde::Serialize,
+    ) -> error_stack::Result<(), StorageError> {
+        let path = self.root.join(name);
+
+        fs::create_dir_all(&self.root)
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| self.root.display().to_string())?;
+
+        let content = toml::to_string(value)
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| path.display().to_string())?;
+
+        write_atomic(&path, &content)
+    }
+
+    fn read_note(&self, path: &Path) -> error_stack::Result<Note, StorageError> {
+        let content = fs::read_to_string(path)
+            .change_context(StorageError::ReadFailed)
+            .attach_printable_lazy(|| path.display().to_string())?;
+
+        parse_note(&content).attach_printable_lazy(|| path.display().to_string())
+    }
+}
+
+impl super::Storage for MarkdownStorage {
+    fn load_notes(&self) -> error_stack::Result<Vec<Note>, StorageError> {
+        Ok(self
+            .note_files()?
+            .into_iter()
+            .map(|(_, note)| note)
+            .collect())
+    }
+
+    fn load_note(&self, note: &NoteId) -> error_stack::Result<Option<Note>, StorageError> {
+        let path = self.note_path(note);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        self.read_note(&path).map(Some)
+    }
+
+    fn save_notes(&mut self, notes: &[&Note]) -> error_stack::Result<(), StorageError> {
+        fs::create_dir_all(&self.root)
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| self.root.display().to_string())?;
+
+        let stale = self.note_files()?;
+
+        let mut written = Vec::with_capacity(notes.len());
+
+        for note in notes {
+            let path = self.note_path(&note.get_id());
+            write_atomic(&path, &render_note(note)?)?;
+            written.push(path);
+        }
+
+        // A note that is still there was written to the file named after it, so any other file
+        // holding it is stale as well.
+        for (path, _) in stale {
+            if !written.contains(&path) {
+                fs::remove_file(&path)
+                    .change_context(StorageError::WriteFailed)
+                    .attach_printable_lazy(|| path.display().to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load_trash(&self) -> error_stack::Result<Vec<TrashEntry>, StorageError> {
+        Ok(self
+            .read_sidecar::<TrashFile<TrashEntry>>(TRASH_FILE)?
+            .map_or_else(Vec::new, |file| file.trash))
+    }
+
+    fn save_trash(&mut self, trash: &[&TrashEntry]) -> error_stack::Result<(), StorageError> {
+        self.write_sidecar(
+            TRASH_FILE,
+            &TrashFile {
+                trash: trash.to_vec(),
+            },
+        )
+    }
+
+    fn load_settings(&self) -> error_stack::Result<Settings, StorageError> {
+        Ok(self.read_sidecar(SETTINGS_FILE)?.unwrap_or_default())
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> error_stack::Result<(), StorageError> {
+        self.write_sidecar(SETTINGS_FILE, settings)
+    }
+}
+
+///
+/// [`write_atomic`] writes the content to a temporary file first, so that a crash never leaves a
+/// half written note behind.
+///
+fn write_atomic(path: &Path, content: &str) -> error_stack::Result<(), StorageError> {
+    let temporary = path.with_extension(format!("{}.tmp", EXTENSION));
+
+    fs::write(&temporary, content)
+        .and_then(|_| fs::rename(&temporary, path))
+        .change_context(StorageError::WriteFailed)
+        .attach_printable_lazy(|| path.display().to_string())
+}
+
+///
+/// [`split_frontmatter`] splits a file into its frontmatter and body, returning `None` if the file
+/// does not start with a frontmatter.
+///
+fn split_frontmatter(content: &str) -> Option<(&str, &str)> {
+    let rest = content
+        .strip_prefix(DELIMITER)?
+        .strip_prefix("\r\n")
+        .or_else(|| content.strip_prefix(DELIMITER)?.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == DELIMITER {
+            let frontmatter = &rest[..offset];
+            let body = &rest[offset + line.len()..];
+            let body = body
+                .strip_prefix("\r\n")
+                .or_else(|| body.strip_prefix('\n'))
+                .unwrap_or(body);
+            return Some((frontmatter, body));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+fn parse_note(content: &str) -> error_stack::Result<Note, StorageError> {
+    let (frontmatter, body) = split_frontmatter(content)
+        .ok_or(Report::new(StorageError::MalformedNote))
+        .attach_printable("missing frontmatter")?;
+
+    let frontmatter: Frontmatter =
+        toml::from_str(frontmatter).change_context(StorageError::MalformedNote)?;
+
+    let mut note = Note::new(frontmatter.title, frontmatter.subtitle, body.to_string())
+        .with_id(parse_id(&frontmatter.id)?);
+    note.state = TaskState::new(frontmatter.state);
+    note.created = parse_timestamp(&frontmatter.created)?;
+    note.modified = parse_timestamp(&frontmatter.modified)?;
+
+    for tag in frontmatter.tags {
+        note.tags.insert(parse_tag(&tag)?);
+    }
+
+    for flink in frontmatter.forwardlinks {
+        note.forwardlinks.push(parse_option(flink.into())?);
+    }
+
+    for revision in frontmatter.revisions {
+        note.revisions.push(NoteRevision {
+            title: revision.title,
+            subtitle: revision.subtitle,
+            body: revision.body,
+            timestamp: parse_timestamp(&revision.timestamp)?,
+        });
+    }
+
+    for change in frontmatter.state_history {
+        note.state_history.push(StateChange {
+            from: TaskState::new(change.from),
+            to: TaskState::new(change.to),
+            timestamp: parse_timestamp(&change.timestamp)?,
+        });
+    }
+
+    Ok(note)
+}
+
+///
+/// [`parse_option`] reads a link or a branch.
+///
+fn parse_option(option: FrontmatterOption) -> error_stack::Result<FLink, StorageError> {
+    Ok(match option {
+        FrontmatterOption::Link {
+            id,
+            reason,
+            relation,
+            inverse,
+            weight,
+            bidirectional,
+            created,
+            modified,
+            annotations,
+        } => {
+            let mut link = Link::new(parse_id(&id)?, reason);
+            link.created = parse_timestamp(&created)?;
+            link.modified = parse_timestamp(&modified)?;
+
+            // A relation without an inverse name reads the same both ways.
+            link.relation = relation.map(|name| {
+                let inverse = inverse.unwrap_or_else(|| name.clone());
+                Relation::new(name, inverse)
+            });
+            link.weight = weight;
+            link.bidirectional = bidirectional;
+            link.annotations = annotations;
+
+            FLink::Link(link)
+        }
+        FrontmatterOption::Branch {
+            id,
+            condition,
+            options,
+            created,
+            modified,
+        } => {
+            let mut branch = Branch::new(condition).with_id(parse_id(&id)?);
+            branch.created = parse_timestamp(&created)?;
+            branch.modified = parse_timestamp(&modified)?;
+
+            for option in options {
+                branch.branches.push(parse_option(option)?);
+            }
+
+            FLink::Branch(branch)
+        }
+    })
+}
+
+fn render_option(option: &FLink) -> error_stack::Result<FrontmatterOption, StorageError> {
+    Ok(match option {
+        FLink::Link(link) => FrontmatterOption::Link {
+            id: link.id.to_string(),
+            reason: link.reason.clone(),
+            relation: link.relation.as_ref().map(|relation| relation.name.clone()),
+            inverse: link
+                .relation
+                .as_ref()
+                .map(|relation| relation.inverse.clone()),
+            weight: link.weight,
+            bidirectional: link.bidirectional,
+            created: format_timestamp(&link.created)?,
+            modified: format_timestamp(&link.modified)?,
+            annotations: link.annotations.clone(),
+        },
+        FLink::Branch(branch) => FrontmatterOption::Branch {
+            id: branch.id().to_string(),
+            condition: branch.condition.clone(),
+            options: branch
+                .branches
+                .iter()
+                .map(render_option)
+                .collect::<error_stack::Result<_, StorageError>>()?,
+            created: format_timestamp(&branch.created)?,
+            modified: format_timestamp(&branch.modified)?,
+        },
+    })
+}
+
+impl From<FrontmatterLink> for FrontmatterOption {
+    fn from(flink: FrontmatterLink) -> Self {
+        match flink {
+            FrontmatterLink::Link {
+                id,
+                reason,
+                relation,
+                inverse,
+                weight,
+                bidirectional,
+                created,
+                modified,
+                annotations,
+            } => FrontmatterOption::Link {
+                id,
+                reason,
+                relation,
+                inverse,
+                weight,
+                bidirectional,
+                created,
+                modified,
+                annotations,
+            },
+            FrontmatterLink::Branch {
+                id,
+                condition,
+                options,
+                created,
+                modified,
+            } => FrontmatterOption::Branch {
+                id,
+                condition,
+                options,
+                created,
+                modified,
+            },
+        }
+    }
+}
+
+impl From<FrontmatterOption> for FrontmatterLink {
+    fn from(option: FrontmatterOption) -> Self {
+        match option {
+            FrontmatterOption::Link {
+                id,
+                reason,
+                relation,
+                inverse,
+                weight,
+                bidirectional,
+                created,
+                modified,
+                annotations,
+            } => FrontmatterLink::Link {
+                id,
+                reason,
+                relation,
+                inverse,
+                weight,
+                bidirectional,
+                created,
+                modified,
+                annotations,
+            },
+            FrontmatterOption::Branch {
+                id,
+                condition,
+                options,
+                created,
+                modified,
+            } => FrontmatterLink::Branch {
+                id,
+                condition,
+                options,
+                created,
+                modified,
+            },
+        }
+    }
+}
+
+fn render_note(note: &Note) -> error_stack::Result<String, StorageError> {
+    let frontmatter = Frontmatter {
+        id: note.get_id().to_string(),
+        title: note.title.clone(),
+        subtitle: note.subtitle.clone(),
+        state: note.state.to_string(),
+        created: format_timestamp(&note.created)?,
+        modified: format_timestamp(&note.modified)?,
+        tags: note.tags.iter().map(|tag| tag.to_string()).collect(),
+        forwardlinks: note
+            .forwardlinks
+            .iter()
+            .map(|flink| Ok(render_option(flink)?.into()))
+            .collect::<error_stack::Result<_, StorageError>>()?,
+        revisions: note
+            .revisions
+            .iter()
+            .map(|revision| {
+                Ok(FrontmatterRevision {
+                    title: revision.title.clone(),
+                    subtitle: revision.subtitle.clone(),
+                    body: revision.body.clone(),
+                    timestamp: format_timestamp(&revision.timestamp)?,
+                })
+            })
+            .collect::<error_stack::Result<_, StorageError>>()?,
+        state_history: note
+            .state_history
+            .iter()
+            .map(|change| {
+                Ok(FrontmatterStateChange {
+                    from: change.from.to_string(),
+                    to: change.to.to_string(),
+                    timestamp: format_timestamp(&change.timestamp)?,
+                })
+            })
+            .collect::<error_stack::Result<_, StorageError>>()?,
+    };
+
+    let frontmatter = toml::to_string(&frontmatter).change_context(StorageError::WriteFailed)?;
+
+    Ok(format!(
+        "{DELIMITER}\n{frontmatter}{DELIMITER}\n\n{}",
+        note.body
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{
+        AddBranch, AddLink, AddNote, AddTag, ChangeLink, ChangeNote, ReadLink, ReadNote,
+    };
+    use crate::manager_impl::{DeleteNote, Trash};
+    use crate::prelude::NotesManager;
+    use crate::types::{DeletionPolicy, TaskStates};
+
+    #[test]
+    fn test_split_frontmatter() {
+        assert_eq!(
+            split_frontmatter("+++\na = 1\n+++\n\nbody\n"),
+            Some(("a = 1\n", "body\n"))
+        );
+        assert_eq!(
+            split_frontmatter("+++\r\na = 1\r\n+++\r\nbody"),
+            Some(("a = 1\r\n", "body"))
+        );
+        assert_eq!(split_frontmatter("# heading\n"), None);
+        assert_eq!(split_frontmatter("+++\na = 1\n"), None);
+    }
+
+    #[test]
+    fn test_settings_and_trash_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut storage = MarkdownStorage::new(directory.path());
+
+        let mut manager = NotesManager::default();
+        manager.apply_settings(Settings {
+            acyclic: true,
+            deletion_policy: DeletionPolicy::RefuseOrphans,
+            revision_retention: 5,
+            trash_retention: Some(time::Duration::days(30)),
+            history_depth: 10,
+            task_states: TaskStates::default().with_done_enforced(true),
+        });
+        let from = manager
+            .add_note(Note::new("from".to_string(), None, String::new()))
+            .unwrap();
+        let to = manager
+            .add_note(Note::new("to".to_string(), None, String::new()))
+            .unwrap();
+        manager
+            .add_link(from.clone(), to.clone(), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(from.clone(), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(from.clone(), branch, to.clone(), "option".to_string())
+            .unwrap();
+        manager.delete_note(to.clone()).unwrap();
+
+        manager.save(&mut storage).unwrap();
+        let mut loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.settings(), manager.settings());
+        assert_eq!(loaded.list_trash().unwrap(), manager.list_trash().unwrap());
+        assert_eq!(loaded.list_root_notes().unwrap(), vec![&from]);
+
+        let entry = loaded.list_trash().unwrap()[0].id;
+        loaded.restore_from_trash(entry).unwrap();
+        assert_eq!(loaded.list_backlinks(to).unwrap(), vec![&from, &from]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut storage = MarkdownStorage::new(directory.path());
+
+        let mut manager = NotesManager::default();
+        let from = manager
+            .add_note(Note::new(
+                "from".to_string(),
+                Some("subtitle".to_string()),
+                "# Body\n\nwith lines\n".to_string(),
+            ))
+            .unwrap();
+        let to = manager
+            .add_note(Note::new("to".to_string(), None, String::new()))
+            .unwrap();
+        manager
+            .add_typed_link(
+                from.clone(),
+                to.clone(),
+                Relation::depends_on(),
+                "reason".to_string(),
+            )
+            .unwrap();
+        manager
+            .change_link_weight(from.clone(), to.clone(), Some(0.75))
+            .unwrap();
+        manager
+            .change_link_annotation(
+                from.clone(),
+                to.clone(),
+                "source".to_string(),
+                Some("review".to_string()),
+            )
+            .unwrap();
+        let branch = manager
+            .create_branching(from.clone(), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(
+                from.clone(),
+                branch.clone(),
+                to.clone(),
+                "option".to_string(),
+            )
+            .unwrap();
+        let sub_branch = manager
+            .create_sub_branching(from.clone(), branch.clone(), "nested".to_string())
+            .unwrap();
+        manager
+            .add_branch(
+                from.clone(),
+                sub_branch,
+                to.clone(),
+                "nested option".to_string(),
+            )
+            .unwrap();
+        let other = manager
+            .add_note(Note::new("other".to_string(), None, String::new()))
+            .unwrap();
+        manager
+            .add_bidirectional_link(other.clone(), from.clone(), "related".to_string())
+            .unwrap();
+        manager
+            .add_tag(from.clone(), "project/backend".parse().unwrap())
+            .unwrap();
+        manager
+            .change_note_body(from.clone(), "# Body\n\nwith changed lines\n".to_string())
+            .unwrap();
+        manager
+            .change_note_state(from.clone(), TaskState::in_progress())
+            .unwrap();
+
+        manager.save(&mut storage).unwrap();
+        fs::write(directory.path().join("README.md"), "# not a note\n").unwrap();
+        fs::write(
+            directory.path().join("about.md"),
+            "+++\ntitle = \"About\"\n+++\n\nA page of the site.\n",
+        )
+        .unwrap();
+
+        let loaded = NotesManager::load(&storage).unwrap();
+
+        let note = loaded.read_note(from.clone()).unwrap();
+        let original = manager.read_note(from.clone()).unwrap();
+        assert_eq!(note.title, "from");
+        assert_eq!(note.subtitle.as_deref(), Some("subtitle"));
+        assert_eq!(note.body, original.body);
+        assert_eq!(note.created, original.created);
+        assert_eq!(note.modified, original.modified);
+        assert_eq!(note.tags, original.tags);
+        assert_eq!(note.revisions, original.revisions);
+        assert_eq!(note.state, TaskState::in_progress());
+        assert_eq!(note.state_history, original.state_history);
+        assert_eq!(
+            loaded.list_backlinks(to.clone()).unwrap(),
+            vec![&from, &from, &from]
+        );
+
+        assert_eq!(
+            loaded.list_forwardlinks(from.clone()).unwrap()[0],
+            &original.forwardlinks[0]
+        );
+        assert_eq!(
+            loaded.list_pure_links(other.clone()).unwrap(),
+            manager.list_pure_links(other).unwrap()
+        );
+
+        match loaded.list_forwardlinks(from).unwrap()[1] {
+            FLink::Branch(loaded_branch) => {
+                assert_eq!(loaded_branch.id(), &branch);
+                assert_eq!(
+                    FLink::Branch(loaded_branch.clone()),
+                    original.forwardlinks[1]
+                );
+            }
+            FLink::Link(_) => panic!("expected a branch"),
+        }
+
+        let manager = NotesManager::default();
+        manager.save(&mut storage).unwrap();
+
+        assert!(NotesManager::load(&storage)
+            .unwrap()
+            .list_root_notes()
+            .unwrap()
+            .is_empty());
+        assert!(directory.path().join("README.md").exists());
+        assert!(directory.path().join("about.md").exists());
+    }
+}
@@ -0,0 +1,1200 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use error_stack::ResultExt;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{format_timestamp, parse_id, parse_tag, parse_timestamp};
+use crate::errors::StorageError;
+use crate::types::{
+    Branch, BranchId, FLink, Link, Note, NoteId, NoteRevision, Relation, Settings, StateChange,
+    TaskState, TrashEntry, TrashId,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS notes (
+        id TEXT PRIMARY KEY,
+        title TEXT NOT NULL,
+        subtitle TEXT,
+        body TEXT NOT NULL,
+        state TEXT NOT NULL,
+        created TEXT NOT NULL,
+        modified TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS links (
+        from_note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        to_note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        reason TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        created TEXT NOT NULL,
+        modified TEXT NOT NULL,
+        relation TEXT,
+        inverse TEXT,
+        weight REAL,
+        bidirectional INTEGER NOT NULL,
+        PRIMARY KEY (from_note, to_note)
+    );
+
+    CREATE TABLE IF NOT EXISTS link_annotations (
+        from_note TEXT NOT NULL,
+        to_note TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (from_note, to_note, key),
+        FOREIGN KEY (from_note, to_note) REFERENCES links(from_note, to_note) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS branchings (
+        id TEXT PRIMARY KEY,
+        note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        condition TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        parent TEXT REFERENCES branchings(id) ON DELETE CASCADE,
+        created TEXT NOT NULL,
+        modified TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS branch_options (
+        branching TEXT NOT NULL REFERENCES branchings(id) ON DELETE CASCADE,
+        to_note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        reason TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        created TEXT NOT NULL,
+        modified TEXT NOT NULL,
+        relation TEXT,
+        inverse TEXT,
+        weight REAL,
+        PRIMARY KEY (branching, to_note)
+    );
+
+    CREATE TABLE IF NOT EXISTS option_annotations (
+        branching TEXT NOT NULL,
+        to_note TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (branching, to_note, key),
+        FOREIGN KEY (branching, to_note)
+            REFERENCES branch_options(branching, to_note) ON DELETE CASCADE
+    );
+
+    CREATE TABLE IF NOT EXISTS tags (
+        note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (note, tag)
+    );
+
+    CREATE TABLE IF NOT EXISTS revisions (
+        note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        subtitle TEXT,
+        body TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        PRIMARY KEY (note, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS state_history (
+        note TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        from_state TEXT NOT NULL,
+        to_state TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        PRIMARY KEY (note, position)
+    );
+
+    CREATE TABLE IF NOT EXISTS trash (
+        id INTEGER PRIMARY KEY,
+        deleted TEXT NOT NULL,
+        notes TEXT NOT NULL,
+        links TEXT NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS settings (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        value TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS links_to_note ON links(to_note);
+    CREATE INDEX IF NOT EXISTS branchings_note ON branchings(note);
+    CREATE INDEX IF NOT EXISTS branchings_parent ON branchings(parent);
+    CREATE INDEX IF NOT EXISTS branch_options_to_note ON branch_options(to_note);
+    CREATE INDEX IF NOT EXISTS tags_tag ON tags(tag);
+";
+
+///
+/// [`SqliteStorage`] stores notes in a SQLite database, with one table each for notes, links,
+/// branchings, branch options, tags, revisions and state changes, and one for the annotations of
+/// links and of branch options. A nested branching keeps the note it belongs to and
+/// points to its parent, its position is shared with the options of the parent.
+///
+/// Backlinks are not stored, they are derived from the links and branch options pointing to a
+/// note, so a single note can be loaded with its backlinks without reading the whole database.
+///
+/// The trash has a table of its own, with the deleted notes and links of an entry kept as JSON:
+/// they are only ever read back as a whole, when the manager is loaded. The settings of the
+/// manager are kept as JSON too, in a single row.
+///
+/// Every change goes through a [`NotesManager`] which is then saved to the database. Saving runs in a single transaction, so a failure halfway through leaves the
+/// previous content in place. Only the notes that changed since they were last loaded or saved
+/// are written, the others are left untouched.
+///
+/// [`NotesManager`]: crate::prelude::NotesManager
+///
+pub struct SqliteStorage {
+    connection: Connection,
+    /// The notes as they were last loaded from or saved to the database.
+    stored: RefCell<HashMap<NoteId, Note>>,
+}
+
+impl SqliteStorage {
+    ///
+    /// [`open`] opens the database at the given path, creating it and its tables if needed.
+    ///
+    pub fn open(path: impl AsRef<Path>) -> error_stack::Result<Self, StorageError> {
+        let connection = Connection::open(path.as_ref())
+            .change_context(StorageError::ReadFailed)
+            .attach_printable_lazy(|| path.as_ref().display().to_string())?;
+
+        Self::with_connection(connection)
+    }
+
+    pub fn open_in_memory() -> error_stack::Result<Self, StorageError> {
+        let connection = Connection::open_in_memory().change_context(StorageError::ReadFailed)?;
+
+        Self::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> error_stack::Result<Self, StorageError> {
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .and_then(|_| connection.execute_batch(SCHEMA))
+            .change_context(StorageError::WriteFailed)?;
+
+        Ok(SqliteStorage {
+            connection,
+            stored: RefCell::default(),
+        })
+    }
+}
+
+impl super::Storage for SqliteStorage {
+    fn load_notes(&self) -> error_stack::Result<Vec<Note>, StorageError> {
+        let notes = load_notes(&self.connection)?;
+
+        *self.stored.borrow_mut() = notes
+            .iter()
+            .map(|note| (note.get_id(), note.clone()))
+            .collect();
+
+        Ok(notes)
+    }
+
+    fn load_note(&self, note: &NoteId) -> error_stack::Result<Option<Note>, StorageError> {
+        let loaded = load_note(&self.connection, note)?;
+
+        match &loaded {
+            Some(loaded) => self
+                .stored
+                .borrow_mut()
+                .insert(note.clone(), loaded.clone()),
+            None => self.stored.borrow_mut().remove(note),
+        };
+
+        Ok(loaded)
+    }
+
+    fn save_notes(&mut self, notes: &[&Note]) -> error_stack::Result<(), StorageError> {
+        let stored = self.stored.get_mut();
+        let changed = notes
+            .iter()
+            .copied()
+            .filter(|note| {
+                !stored
+                    .get(&note.get_id())
+                    .is_some_and(|before| same_rows(before, note))
+            })
+            .collect::<Vec<_>>();
+
+        let transaction = self
+            .connection
+            .transaction()
+            .change_context(StorageError::WriteFailed)?;
+
+        let kept = notes
+            .iter()
+            .map(|note| note.get_id())
+            .collect::<HashSet<_>>();
+        let removed = note_ids(&transaction)?
+            .into_iter()
+            .filter(|note| !kept.contains(note))
+            .collect::<Vec<_>>();
+
+        write_notes(&transaction, &changed, &removed)?;
+
+        transaction
+            .commit()
+            .change_context(StorageError::WriteFailed)?;
+
+        for note in &removed {
+            stored.remove(note);
+        }
+
+        for note in changed {
+            stored.insert(note.get_id(), note.clone());
+        }
+
+        Ok(())
+    }
+
+    fn load_trash(&self) -> error_stack::Result<Vec<TrashEntry>, StorageError> {
+        let rows = self
+            .connection
+            .prepare("SELECT id, deleted, notes, links FROM trash ORDER BY id")
+            .and_then(|mut statement| {
+                statement
+                    .query_map([], |row| {
+                        Ok((
+                            row.get::<_, u64>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, String>(3)?,
+                        ))
+                    })?
+                    .collect::<rusqlite::Result<Vec<_>>>()
+            })
+            .change_context(StorageError::ReadFailed)?;
+
+        rows.into_iter()
+            .map(|(id, deleted, notes, links)| {
+                Ok(TrashEntry {
+                    id: TrashId(id),
+                    deleted: parse_timestamp(&deleted)?,
+                    notes: serde_json::from_str(&notes)
+                        .change_context(StorageError::MalformedNote)?,
+                    links: serde_json::from_str(&links)
+                        .change_context(StorageError::MalformedNote)?,
+                })
+            })
+            .collect()
+    }
+
+    fn save_trash(&mut self, trash: &[&TrashEntry]) -> error_stack::Result<(), StorageError> {
+        let transaction = self
+            .connection
+            .transaction()
+            .change_context(StorageError::WriteFailed)?;
+
+        transaction
+            .execute("DELETE FROM trash", [])
+            .change_context(StorageError::WriteFailed)?;
+
+        for entry in trash {
+            let notes =
+                serde_json::to_string(&entry.notes).change_context(StorageError::WriteFailed)?;
+            let links =
+                serde_json::to_string(&entry.links).change_context(StorageError::WriteFailed)?;
+
+            transaction
+                .execute(
+                    "INSERT INTO trash (id, deleted, notes, links) VALUES (?1, ?2, ?3, ?4)",
+                    params![entry.id.0, format_timestamp(&entry.deleted)?, notes, links],
+                )
+                .change_context(StorageError::WriteFailed)?;
+        }
+
+        transaction
+            .commit()
+            .change_context(StorageError::WriteFailed)
+    }
+
+    fn load_settings(&self) -> error_stack::Result<Settings, StorageError> {
+        let value = self
+            .connection
+            .query_row("SELECT value FROM settings WHERE id = 0", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()
+            .change_context(StorageError::ReadFailed)?;
+
+        match value {
+            Some(value) => serde_json::from_str(&value).change_context(StorageError::MalformedNote),
+            None => Ok(Settings::default()),
+        }
+    }
+
+    fn save_settings(&mut self, settings: &Settings) -> error_stack::Result<(), StorageError> {
+        let value = serde_json::to_string(settings).change_context(StorageError::WriteFailed)?;
+
+        self.connection
+            .execute(
+                "INSERT INTO settings (id, value) VALUES (0, ?1)
+                 ON CONFLICT (id) DO UPDATE SET value = excluded.value",
+                params![value],
+            )
+            .change_context(StorageError::WriteFailed)?;
+
+        Ok(())
+    }
+}
+
+fn load_notes(connection: &Connection) -> error_stack::Result<Vec<Note>, StorageError> {
+    let ids = note_ids(connection)?;
+    let mut notes = Vec::with_capacity(ids.len());
+
+    for id in ids {
+        if let Some(note) = load_note(connection, &id)? {
+            notes.push(note);
+        }
+    }
+
+    Ok(notes)
+}
+
+fn note_ids(connection: &Connection) -> error_stack::Result<Vec<NoteId>, StorageError> {
+    connection
+        .prepare("SELECT id FROM notes ORDER BY id")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .change_context(StorageError::ReadFailed)?
+        .iter()
+        .map(|id| parse_id(id))
+        .collect()
+}
+
+fn load_note(
+    connection: &Connection,
+    note: &NoteId,
+) -> error_stack::Result<Option<Note>, StorageError> {
+    let row = connection
+        .query_row(
+            "SELECT title, subtitle, body, state, created, modified FROM notes WHERE id = ?1",
+            params![note.as_str()],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            },
+        )
+        .optional()
+        .change_context(StorageError::ReadFailed)?;
+
+    let Some((title, subtitle, body, state, created, modified)) = row else {
+        return Ok(None);
+    };
+
+    let mut note_ = Note::new(title, subtitle, body).with_id(note.clone());
+    note_.state = TaskState::new(state);
+    note_.created = parse_timestamp(&created)?;
+    note_.modified = parse_timestamp(&modified)?;
+
+    let forwardlinks = connection
+        .prepare(
+            "SELECT 0, to_note, reason, created, modified, position, relation, inverse, weight,
+                     bidirectional
+                 FROM links WHERE from_note = ?1
+             UNION ALL
+             SELECT 1, id, condition, created, modified, position, NULL, NULL, NULL, 0
+                 FROM branchings WHERE note = ?1 AND parent IS NULL
+             ORDER BY 6",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![note.as_str()], OptionRow::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .change_context(StorageError::ReadFailed)?;
+
+    for row in forwardlinks {
+        note_.forwardlinks.push(load_option(
+            connection,
+            LinkOwner::Note(note.as_str()),
+            row,
+        )?);
+    }
+
+    for backlink in backlinks(connection, note)? {
+        note_.add_backlink(parse_id(&backlink)?);
+    }
+
+    let tags = connection
+        .prepare("SELECT tag FROM tags WHERE note = ?1 ORDER BY tag")
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![note.as_str()], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .change_context(StorageError::ReadFailed)?;
+
+    for tag in tags {
+        note_.tags.insert(parse_tag(&tag)?);
+    }
+
+    let revisions = connection
+        .prepare(
+            "SELECT title, subtitle, body, timestamp FROM revisions WHERE note = ?1
+             ORDER BY position",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![note.as_str()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .change_context(StorageError::ReadFailed)?;
+
+    for (title, subtitle, body, timestamp) in revisions {
+        note_.revisions.push(NoteRevision {
+            title,
+            subtitle,
+            body,
+            timestamp: parse_timestamp(&timestamp)?,
+        });
+    }
+
+    let state_history = connection
+        .prepare(
+            "SELECT from_state, to_state, timestamp FROM state_history WHERE note = ?1
+             ORDER BY position",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![note.as_str()], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .change_context(StorageError::ReadFailed)?;
+
+    for (from, to, timestamp) in state_history {
+        note_.state_history.push(StateChange {
+            from: TaskState::new(from),
+            to: TaskState::new(to),
+            timestamp: parse_timestamp(&timestamp)?,
+        });
+    }
+
+    Ok(Some(note_))
+}
+
+///
+/// [`OptionRow`] is a forward link of a note or an option of a branching: the target note,
+/// reason, relation and weight of a link, or the id and condition of a branching.
+///
+struct OptionRow {
+    is_branch: bool,
+    id: String,
+    text: String,
+    created: String,
+    modified: String,
+    relation: Option<Relation>,
+    weight: Option<f64>,
+    bidirectional: bool,
+}
+
+impl OptionRow {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(OptionRow {
+            is_branch: row.get(0)?,
+            id: row.get(1)?,
+            text: row.get(2)?,
+            created: row.get(3)?,
+            modified: row.get(4)?,
+            relation: relation_from_columns(row.get(6)?, row.get(7)?),
+            weight: row.get(8)?,
+            bidirectional: row.get(9)?,
+        })
+    }
+}
+
+///
+/// [`LinkOwner`] is what a link belongs to, a note for a forward link or a branching for an
+/// option. Their annotations are kept in separate tables.
+///
+#[derive(Clone, Copy)]
+enum LinkOwner<'a> {
+    Note(&'a str),
+    Branching(&'a str),
+}
+
+impl LinkOwner<'_> {
+    fn id(&self) -> &str {
+        match self {
+            LinkOwner::Note(id) | LinkOwner::Branching(id) => id,
+        }
+    }
+}
+
+fn load_annotations(
+    connection: &Connection,
+    owner: LinkOwner,
+    to_note: &str,
+) -> error_stack::Result<BTreeMap<String, String>, StorageError> {
+    let statement = match owner {
+        LinkOwner::Note(_) => {
+            "SELECT key, value FROM link_annotations WHERE from_note = ?1 AND to_note = ?2"
+        }
+        LinkOwner::Branching(_) => {
+            "SELECT key, value FROM option_annotations WHERE branching = ?1 AND to_note = ?2"
+        }
+    };
+
+    connection
+        .prepare(statement)
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![owner.id(), to_note], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect()
+        })
+        .change_context(StorageError::ReadFailed)
+}
+
+fn insert_annotations(
+    connection: &Connection,
+    owner: LinkOwner,
+    to_note: &str,
+    annotations: &BTreeMap<String, String>,
+) -> error_stack::Result<(), StorageError> {
+    let statement = match owner {
+        LinkOwner::Note(_) => {
+            "INSERT OR REPLACE INTO link_annotations (from_note, to_note, key, value)
+             VALUES (?1, ?2, ?3, ?4)"
+        }
+        LinkOwner::Branching(_) => {
+            "INSERT OR REPLACE INTO option_annotations (branching, to_note, key, value)
+             VALUES (?1, ?2, ?3, ?4)"
+        }
+    };
+
+    for (key, value) in annotations {
+        connection
+            .execute(statement, params![owner.id(), to_note, key, value])
+            .change_context(StorageError::WriteFailed)?;
+    }
+
+    Ok(())
+}
+
+///
+/// [`relation_from_columns`] reads the relation of a link, a relation without an inverse name
+/// reads the same both ways.
+///
+fn relation_from_columns(relation: Option<String>, inverse: Option<String>) -> Option<Relation> {
+    relation.map(|name| {
+        let inverse = inverse.unwrap_or_else(|| name.clone());
+        Relation::new(name, inverse)
+    })
+}
+
+///
+/// [`relation_columns`] gives the values of the `relation` and `inverse` columns of a link.
+///
+fn relation_columns(relation: Option<&Relation>) -> (Option<&str>, Option<&str>) {
+    (
+        relation.map(|relation| relation.name.as_str()),
+        relation.map(|relation| relation.inverse.as_str()),
+    )
+}
+
+fn load_option(
+    connection: &Connection,
+    owner: LinkOwner,
+    row: OptionRow,
+) -> error_stack::Result<FLink, StorageError> {
+    let created = parse_timestamp(&row.created)?;
+    let modified = parse_timestamp(&row.modified)?;
+
+    if !row.is_branch {
+        let mut link = Link::new(parse_id(&row.id)?, row.text);
+        (link.created, link.modified) = (created, modified);
+        link.relation = row.relation;
+        link.weight = row.weight;
+        link.bidirectional = row.bidirectional;
+        link.annotations = load_annotations(connection, owner, &row.id)?;
+        return Ok(FLink::Link(link));
+    }
+
+    let mut branch = Branch::new(row.text).with_id(parse_id(&row.id)?);
+    (branch.created, branch.modified) = (created, modified);
+
+    for option in branch_options(connection, &row.id)? {
+        branch.branches.push(load_option(
+            connection,
+            LinkOwner::Branching(&row.id),
+            option,
+        )?);
+    }
+
+    Ok(FLink::Branch(branch))
+}
+
+///
+/// [`same_rows`] tells if a note is stored the same way as it was before. Backlinks are derived
+/// from the other notes, so they do not count.
+///
+fn same_rows(before: &Note, note: &Note) -> bool {
+    before.get_id() == note.get_id()
+        && before.state == note.state
+        && before.state_history == note.state_history
+        && before.title == note.title
+        && before.subtitle == note.subtitle
+        && before.body == note.body
+        && before.forwardlinks == note.forwardlinks
+        && before.created == note.created
+        && before.modified == note.modified
+        && before.tags == note.tags
+        && before.revisions == note.revisions
+}
+
+///
+/// [`write_notes`] brings the rows of the changed notes up to date and deletes the removed notes.
+/// The row of a changed note is updated in place rather than replaced, so the links pointing to it
+/// from the notes that did not change are kept.
+///
+fn write_notes(
+    connection: &Connection,
+    changed: &[&Note],
+    removed: &[NoteId],
+) -> error_stack::Result<(), StorageError> {
+    for note in changed {
+        upsert_note_row(connection, note)?;
+    }
+
+    // Everything that belongs to the changed notes is cleared before it is inserted again, so a
+    // branching that moved to another note does not collide with itself.
+    for note in changed {
+        clear_note(connection, &note.get_id())?;
+    }
+
+    for note in removed {
+        connection
+            .execute("DELETE FROM notes WHERE id = ?1", params![note.as_str()])
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| format!("note `{}`", note))?;
+    }
+
+    for note in changed {
+        insert_note_content(connection, note)?;
+        insert_forwardlinks(connection, note)?;
+    }
+
+    Ok(())
+}
+
+fn upsert_note_row(connection: &Connection, note: &Note) -> error_stack::Result<(), StorageError> {
+    connection
+        .execute(
+            "INSERT INTO notes (id, title, subtitle, body, state, created, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (id) DO UPDATE SET
+                 title = excluded.title,
+                 subtitle = excluded.subtitle,
+                 body = excluded.body,
+                 state = excluded.state,
+                 created = excluded.created,
+                 modified = excluded.modified",
+            params![
+                note.get_id().as_str(),
+                note.title,
+                note.subtitle,
+                note.body,
+                note.state.as_str(),
+                format_timestamp(&note.created)?,
+                format_timestamp(&note.modified)?,
+            ],
+        )
+        .change_context(StorageError::WriteFailed)
+        .attach_printable_lazy(|| format!("note `{}`", note.get_id()))?;
+
+    Ok(())
+}
+
+///
+/// [`clear_note`] deletes the tags, revisions, state changes, links and branchings of a note,
+/// keeping its row and the links pointing to it.
+///
+fn clear_note(connection: &Connection, note: &NoteId) -> error_stack::Result<(), StorageError> {
+    for statement in [
+        "DELETE FROM tags WHERE note = ?1",
+        "DELETE FROM revisions WHERE note = ?1",
+        "DELETE FROM state_history WHERE note = ?1",
+        "DELETE FROM links WHERE from_note = ?1",
+        "DELETE FROM branchings WHERE note = ?1",
+    ] {
+        connection
+            .execute(statement, params![note.as_str()])
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| format!("note `{}`", note))?;
+    }
+
+    Ok(())
+}
+
+fn insert_note_content(
+    connection: &Connection,
+    note: &Note,
+) -> error_stack::Result<(), StorageError> {
+    for tag in &note.tags {
+        connection
+            .execute(
+                "INSERT INTO tags (note, tag) VALUES (?1, ?2)",
+                params![note.get_id().as_str(), tag.as_str()],
+            )
+            .change_context(StorageError::WriteFailed)
+            .attach_printable_lazy(|| format!("tags of note `{}`", note.get_id()))?;
+    }
+
+    for (position, revision) in note.revisions.iter().enumerate() {
+        insert_revision(connection, &note.get_id(), position, revision)?;
+    }
+
+    for (position, change) in note.state_history.iter().enumerate() {
+        insert_state_change(connection, &note.get_id(), position, change)?;
+    }
+
+    Ok(())
+}
+
+fn insert_state_change(
+    connection: &Connection,
+    note: &NoteId,
+    position: usize,
+    change: &StateChange,
+) -> error_stack::Result<(), StorageError> {
+    connection
+        .execute(
+            "INSERT INTO state_history (note, position, from_state, to_state, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                note.as_str(),
+                position,
+                change.from.as_str(),
+                change.to.as_str(),
+                format_timestamp(&change.timestamp)?,
+            ],
+        )
+        .change_context(StorageError::WriteFailed)?;
+
+    Ok(())
+}
+
+fn insert_revision(
+    connection: &Connection,
+    note: &NoteId,
+    position: usize,
+    revision: &NoteRevision,
+) -> error_stack::Result<(), StorageError> {
+    connection
+        .execute(
+            "INSERT INTO revisions (note, position, title, subtitle, body, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                note.as_str(),
+                position,
+                revision.title,
+                revision.subtitle,
+                revision.body,
+                format_timestamp(&revision.timestamp)?,
+            ],
+        )
+        .change_context(StorageError::WriteFailed)
+        .attach_printable_lazy(|| format!("revisions of note `{}`", note))?;
+
+    Ok(())
+}
+
+fn insert_forwardlinks(
+    connection: &Connection,
+    note: &Note,
+) -> error_stack::Result<(), StorageError> {
+    let note_id = note.get_id();
+
+    for (position, flink) in note.forwardlinks.iter().enumerate() {
+        match flink {
+            FLink::Link(link) => connection
+                .execute(
+                    "INSERT INTO links
+                         (from_note, to_note, reason, position, created, modified, relation, inverse,
+                          weight, bidirectional)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        note_id.as_str(),
+                        link.id.as_str(),
+                        link.reason,
+                        position,
+                        format_timestamp(&link.created)?,
+                        format_timestamp(&link.modified)?,
+                        relation_columns(link.relation.as_ref()).0,
+                        relation_columns(link.relation.as_ref()).1,
+                        link.weight,
+                        link.bidirectional
+                    ],
+                )
+                .change_context(StorageError::WriteFailed)
+                .and_then(|_| {
+                    insert_annotations(
+                        connection,
+                        LinkOwner::Note(note_id.as_str()),
+                        link.id.as_str(),
+                        &link.annotations,
+                    )
+                }),
+            FLink::Branch(branch) => insert_branch(connection, &note_id, None, branch, position),
+        }
+        .attach_printable_lazy(|| format!("forward links of note `{}`", note_id))?;
+    }
+
+    Ok(())
+}
+
+fn insert_branch(
+    connection: &Connection,
+    note_id: &NoteId,
+    parent: Option<&BranchId>,
+    branch: &Branch,
+    position: usize,
+) -> error_stack::Result<(), StorageError> {
+    connection
+        .execute(
+            "INSERT INTO branchings (id, note, condition, position, parent, created, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                branch.id().as_str(),
+                note_id.as_str(),
+                branch.condition,
+                position,
+                parent.map(BranchId::as_str),
+                format_timestamp(&branch.created)?,
+                format_timestamp(&branch.modified)?
+            ],
+        )
+        .change_context(StorageError::WriteFailed)?;
+
+    for (position, option) in branch.branches.iter().enumerate() {
+        match option {
+            FLink::Link(link) => {
+                connection
+                    .execute(
+                        "INSERT INTO branch_options
+                             (branching, to_note, reason, position, created, modified, relation,
+                              inverse, weight)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                        params![
+                            branch.id().as_str(),
+                            link.id.as_str(),
+                            link.reason,
+                            position,
+                            format_timestamp(&link.created)?,
+                            format_timestamp(&link.modified)?,
+                            relation_columns(link.relation.as_ref()).0,
+                            relation_columns(link.relation.as_ref()).1,
+                            link.weight
+                        ],
+                    )
+                    .change_context(StorageError::WriteFailed)?;
+
+                insert_annotations(
+                    connection,
+                    LinkOwner::Branching(branch.id().as_str()),
+                    link.id.as_str(),
+                    &link.annotations,
+                )?;
+            }
+            FLink::Branch(sub_branch) => {
+                insert_branch(connection, note_id, Some(branch.id()), sub_branch, position)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+///
+/// [`backlinks`] derives the backlinks of a note from the links and branch options pointing to it.
+///
+fn backlinks(
+    connection: &Connection,
+    note: &NoteId,
+) -> error_stack::Result<Vec<String>, StorageError> {
+    connection
+        .prepare(
+            "SELECT from_note FROM links WHERE to_note = ?1
+             UNION ALL
+             SELECT branchings.note FROM branch_options
+                 JOIN branchings ON branchings.id = branch_options.branching
+                 WHERE branch_options.to_note = ?1
+             ORDER BY 1",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![note.as_str()], |row| row.get(0))?
+                .collect()
+        })
+        .change_context(StorageError::ReadFailed)
+}
+
+///
+/// [`branch_options`] lists the options of a branching in order, links and nested branchings
+/// alike.
+///
+fn branch_options(
+    connection: &Connection,
+    branch: &str,
+) -> error_stack::Result<Vec<OptionRow>, StorageError> {
+    connection
+        .prepare(
+            "SELECT 0, to_note, reason, created, modified, position, relation, inverse, weight, 0
+                 FROM branch_options WHERE branching = ?1
+             UNION ALL
+             SELECT 1, id, condition, created, modified, position, NULL, NULL, NULL, 0
+                 FROM branchings WHERE parent = ?1
+             ORDER BY 6",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(params![branch], OptionRow::from_row)?
+                .collect()
+        })
+        .change_context(StorageError::ReadFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::storage::Storage;
+
+    fn note(title: &str) -> Note {
+        Note::new(title.to_string(), None, "body".to_string())
+    }
+
+    #[test]
+    fn test_manager_round_trip() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut manager = NotesManager::default();
+        let a = manager.add_note(note("a")).unwrap();
+        let b = manager.add_note(note("b")).unwrap();
+        let c = manager.add_note(note("c")).unwrap();
+        manager
+            .add_typed_link(
+                a.clone(),
+                b.clone(),
+                Relation::refines(),
+                "reason".to_string(),
+            )
+            .unwrap();
+        manager
+            .change_link_weight(a.clone(), b.clone(), Some(0.5))
+            .unwrap();
+        manager
+            .change_link_annotation(
+                a.clone(),
+                b.clone(),
+                "source".to_string(),
+                Some("review".to_string()),
+            )
+            .unwrap();
+        manager
+            .add_bidirectional_link(b.clone(), c.clone(), "related".to_string())
+            .unwrap();
+        manager
+            .add_tag(a.clone(), "project/backend".parse().unwrap())
+            .unwrap();
+        manager
+            .change_note_body(a.clone(), "edited".to_string())
+            .unwrap();
+        manager
+            .change_note_state(c.clone(), TaskState::blocked())
+            .unwrap();
+
+        manager.save(&mut storage).unwrap();
+
+        // Backlinks are rebuilt on load, in an order that depends on the generated ids.
+        let normalized = |manager: &NotesManager| {
+            let mut notes = manager
+                .sorted_notes()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>();
+            notes.iter_mut().for_each(|note| note.backlinks.sort());
+            notes
+        };
+
+        let loaded = NotesManager::load(&storage).unwrap();
+        assert_eq!(normalized(&loaded), normalized(&manager));
+
+        let note = loaded.read_note(b.clone()).unwrap();
+        assert_eq!(note.backlinks.len(), 2);
+        assert_eq!(note.title, "b");
+
+        let links = loaded
+            .list_forwardlinks_by_relation(a.clone(), &Relation::refines())
+            .unwrap();
+        assert_eq!(links[0].id, b);
+        assert_eq!(links[0].reason, "reason");
+
+        // Deleting a note and saving again removes it from the database.
+        manager.delete_note(c.clone()).unwrap();
+        manager.save(&mut storage).unwrap();
+
+        assert!(storage.load_note(&c).unwrap().is_none());
+        assert_eq!(storage.load_note(&b).unwrap().unwrap().backlinks, vec![a]);
+    }
+
+    #[test]
+    fn test_nested_branches() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut manager = NotesManager::default();
+        let a = manager.add_note(note("a")).unwrap();
+        let b = manager.add_note(note("b")).unwrap();
+        let c = manager.add_note(note("c")).unwrap();
+
+        let top = manager
+            .create_branching(a.clone(), "top".to_string())
+            .unwrap();
+        manager
+            .add_branch(a.clone(), top.clone(), b.clone(), "b".to_string())
+            .unwrap();
+        let nested = manager
+            .create_sub_branching(a.clone(), top.clone(), "nested".to_string())
+            .unwrap();
+        manager
+            .add_branch(a.clone(), nested.clone(), b.clone(), "b again".to_string())
+            .unwrap();
+        manager
+            .add_branch(a.clone(), nested.clone(), c.clone(), "c".to_string())
+            .unwrap();
+        manager
+            .add_branch(a.clone(), top.clone(), c.clone(), "c".to_string())
+            .unwrap();
+
+        manager.save(&mut storage).unwrap();
+
+        let loaded = storage.load_note(&a).unwrap().unwrap();
+        let FLink::Branch(branch) = &loaded.forwardlinks[0] else {
+            panic!("expected a branch");
+        };
+        assert_eq!(branch.branches.len(), 3);
+        assert_eq!(branch.sub_branches().next().unwrap().id(), &nested);
+        assert_eq!(
+            loaded.branch_path(&nested).unwrap(),
+            vec![top.clone(), nested.clone()]
+        );
+        assert_eq!(storage.load_note(&b).unwrap().unwrap().backlinks.len(), 2);
+
+        // Saving the loaded notes again keeps the nesting.
+        let notes = storage.load_notes().unwrap();
+        storage
+            .save_notes(&notes.iter().collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(storage.load_note(&a).unwrap().unwrap(), loaded);
+    }
+
+    #[test]
+    fn test_only_changed_notes_are_written() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut manager = NotesManager::default();
+        manager.set_deletion_policy(DeletionPolicy::KeepOrphans);
+        let a = manager.add_note(note("a")).unwrap();
+        let b = manager.add_note(note("b")).unwrap();
+        let c = manager.add_note(note("c")).unwrap();
+        manager
+            .add_link(a.clone(), b.clone(), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(c.clone(), b.clone(), "reason".to_string())
+            .unwrap();
+        manager.save(&mut storage).unwrap();
+
+        // A row that is not written again keeps what was put in it behind the storage's back.
+        storage
+            .connection
+            .execute(
+                "UPDATE notes SET body = 'untouched' WHERE id = ?1",
+                params![a.as_str()],
+            )
+            .unwrap();
+
+        manager
+            .change_note_title(b.clone(), "renamed".to_string())
+            .unwrap();
+        manager.delete_note(c.clone()).unwrap();
+        manager.save(&mut storage).unwrap();
+
+        let loaded = NotesManager::load(&storage).unwrap();
+        assert_eq!(loaded.read_note(a.clone()).unwrap().body, "untouched");
+        assert_eq!(loaded.read_note(b.clone()).unwrap().title, "renamed");
+        assert!(loaded.read_note(c).is_err());
+        assert_eq!(loaded.list_backlinks(b).unwrap(), vec![&a]);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut manager = NotesManager::default();
+        manager.apply_settings(Settings {
+            acyclic: true,
+            deletion_policy: DeletionPolicy::RefuseOrphans,
+            revision_retention: 5,
+            trash_retention: Some(time::Duration::days(30)),
+            history_depth: 10,
+            task_states: TaskStates::default().with_done_enforced(true),
+        });
+
+        assert_eq!(storage.load_settings().unwrap(), Settings::default());
+
+        manager.save(&mut storage).unwrap();
+        manager.save(&mut storage).unwrap();
+        let loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.settings(), manager.settings());
+    }
+
+    #[test]
+    fn test_trash_round_trip() {
+        let mut storage = SqliteStorage::open_in_memory().unwrap();
+
+        let mut manager = NotesManager::default();
+        let from = manager.add_note(note("from")).unwrap();
+        let to = manager.add_note(note("to")).unwrap();
+        manager
+            .add_link(from.clone(), to.clone(), "reason".to_string())
+            .unwrap();
+        manager.delete_note(to.clone()).unwrap();
+
+        manager.save(&mut storage).unwrap();
+        manager.save(&mut storage).unwrap();
+        let mut loaded = NotesManager::load(&storage).unwrap();
+
+        assert_eq!(loaded.list_trash().unwrap(), manager.list_trash().unwrap());
+
+        let entry = loaded.list_trash().unwrap()[0].id;
+        loaded.restore_from_trash(entry).unwrap();
+        assert_eq!(loaded.list_backlinks(to).unwrap(), vec![&from]);
+    }
+}
@@ -0,0 +1,23 @@
+//!
+//! Fixtures shared by the tests of the crate.
+//!
+
+use crate::types::{Note, NoteId};
+
+///
+/// [`note`] is a note whose id and title are both `id`.
+///
+pub(crate) fn note(id: &str) -> Note {
+    titled_note(id, id, "body")
+}
+
+///
+/// [`titled_note`] is a note with a fixed id, for when the title or the body matter.
+///
+pub(crate) fn titled_note(id: &str, title: &str, body: &str) -> Note {
+    Note::new(title.to_string(), None, body.to_string()).set_id(id.to_string())
+}
+
+pub(crate) fn id(id: &str) -> NoteId {
+    NoteId::new_test(id.to_string())
+}
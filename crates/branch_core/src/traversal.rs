@@ -0,0 +1,430 @@
+//!
+//! The traversal module walks the graph formed by the forward links and branches of the notes.
+//! It works on anything that can read notes, so the same walks are available on a
+//! [`NotesManager`] and on the managers wrapping it.
+//!
+//! ```rust
+//! use branch_core::prelude::*;
+//! use branch_core::traversal::Traversal;
+//!
+//! let mut manager = NotesManager::default();
+//! let idea = manager
+//!     .add_note(Note::new("idea".to_string(), None, String::new()))
+//!     .unwrap();
+//! let plan = manager
+//!     .add_note(Note::new("plan".to_string(), None, String::new()))
+//!     .unwrap();
+//! let result = manager
+//!     .add_note(Note::new("result".to_string(), None, String::new()))
+//!     .unwrap();
+//! manager.add_link(idea.clone(), plan.clone(), "leads to".to_string()).unwrap();
+//! manager.add_link(plan.clone(), result.clone(), "produces".to_string()).unwrap();
+//!
+//! let path = Traversal::new(&manager).shortest_path(&idea, &result).unwrap();
+//! assert_eq!(path, Some(vec![idea, plan, result]));
+//! ```
+//!
+//! [`NotesManager`]: crate::prelude::NotesManager
+//!
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use error_stack::Result;
+
+use crate::errors::ReadError;
+use crate::manager_impl::ReadNote;
+use crate::types::{FLink, NoteId};
+
+///
+/// [`Direction`] selects which way the links are followed. Going backward follows the backlinks,
+/// i.e. it visits the notes that link to the current one.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Direction {
+    #[default]
+    Forward,
+    Backward,
+}
+
+///
+/// [`Visit`] is a note reached by a walk, along with its distance in links from the start.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Visit {
+    pub note: NoteId,
+    pub depth: usize,
+}
+
+///
+/// [`Traversal`] holds the options of a walk over the graph. By default links are followed forward
+/// and branch options count as edges.
+///
+pub struct Traversal<'a, R: ?Sized> {
+    reader: &'a R,
+    direction: Direction,
+    branches: bool,
+}
+
+impl<R: ?Sized> Clone for Traversal<'_, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R: ?Sized> Copy for Traversal<'_, R> {}
+
+impl<'a, R: ReadNote + ?Sized> Traversal<'a, R> {
+    pub fn new(reader: &'a R) -> Self {
+        Traversal {
+            reader,
+            direction: Direction::Forward,
+            branches: true,
+        }
+    }
+
+    pub fn direction(self, direction: Direction) -> Self {
+        Traversal { direction, ..self }
+    }
+
+    ///
+    /// [`branches`] sets whether the options of a branch are followed like links. When they are
+    /// not, only the plain links of a note are edges.
+    ///
+    pub fn branches(self, branches: bool) -> Self {
+        Traversal { branches, ..self }
+    }
+
+    ///
+    /// [`neighbors`] lists the notes one link away, in the order of the links. Links to notes that
+    /// do not exist are skipped.
+    ///
+    pub fn neighbors(&self, note: &NoteId) -> Result<Vec<NoteId>, ReadError> {
+        let note_ = self.reader.read_note(note.clone())?;
+
+        let mut neighbors = Vec::new();
+
+        match self.direction {
+            Direction::Forward => {
+                for flink in &note_.forwardlinks {
+                    match flink {
+                        FLink::Link(link) => neighbors.push(link.id.clone()),
+                        FLink::Branch(branch) if self.branches => neighbors
+                            .extend(branch.targets().into_iter().map(|link| link.id.clone())),
+                        FLink::Branch(_) => {}
+                    }
+                }
+            }
+            Direction::Backward => {
+                for backlink in &note_.backlinks {
+                    if self.branches || self.links_directly(backlink, note) {
+                        neighbors.push(backlink.clone());
+                    }
+                }
+            }
+        }
+
+        neighbors.retain(|neighbor| self.reader.read_note(neighbor.clone()).is_ok());
+        neighbors.dedup();
+
+        Ok(neighbors)
+    }
+
+    fn links_directly(&self, from: &NoteId, to: &NoteId) -> bool {
+        self.reader
+            .read_note(from.clone())
+            .map(|from| {
+                from.forwardlinks
+                    .iter()
+                    .any(|flink| matches!(flink, FLink::Link(link) if &link.id == to))
+            })
+            .unwrap_or(false)
+    }
+
+    ///
+    /// [`bfs`] visits the notes reachable from `start` breadth first, starting with `start` itself
+    /// at depth zero. Every note is visited once.
+    ///
+    pub fn bfs(self, start: &NoteId) -> Result<Bfs<'a, R>, ReadError> {
+        self.reader.read_note(start.clone())?;
+
+        Ok(Bfs {
+            traversal: self,
+            queue: VecDeque::from([Visit {
+                note: start.clone(),
+                depth: 0,
+            }]),
+            visited: HashSet::from([start.clone()]),
+        })
+    }
+
+    ///
+    /// [`dfs`] visits the notes reachable from `start` depth first, in pre-order. Every note is
+    /// visited once, the depth is the length of the path the walk took to reach it.
+    ///
+    pub fn dfs(self, start: &NoteId) -> Result<Dfs<'a, R>, ReadError> {
+        self.reader.read_note(start.clone())?;
+
+        Ok(Dfs {
+            traversal: self,
+            stack: vec![Visit {
+                note: start.clone(),
+                depth: 0,
+            }],
+            visited: HashSet::new(),
+        })
+    }
+
+    ///
+    /// [`neighborhood`] lists the notes at most `depth` links away from `start`, closest first.
+    ///
+    pub fn neighborhood(self, start: &NoteId, depth: usize) -> Result<Vec<Visit>, ReadError> {
+        Ok(self
+            .bfs(start)?
+            .take_while(|visit| visit.depth <= depth)
+            .collect())
+    }
+
+    ///
+    /// [`reachable`] lists every note that can be reached from `root`, including `root`, sorted by
+    /// id.
+    ///
+    pub fn reachable(self, root: &NoteId) -> Result<Vec<NoteId>, ReadError> {
+        let mut notes = self.bfs(root)?.map(|visit| visit.note).collect::<Vec<_>>();
+        notes.sort();
+
+        Ok(notes)
+    }
+
+    ///
+    /// [`shortest_path`] finds a path with the fewest links from `from` to `to`, both included.
+    /// `None` means `to` can not be reached.
+    ///
+    pub fn shortest_path(
+        self,
+        from: &NoteId,
+        to: &NoteId,
+    ) -> Result<Option<Vec<NoteId>>, ReadError> {
+        self.reader.read_note(from.clone())?;
+        self.reader.read_note(to.clone())?;
+
+        let mut parents = HashMap::<NoteId, NoteId>::new();
+        let mut queue = VecDeque::from([from.clone()]);
+        let mut visited = HashSet::from([from.clone()]);
+
+        while let Some(note) = queue.pop_front() {
+            if &note == to {
+                let mut path = vec![note];
+                while let Some(parent) = path.last().and_then(|note| parents.get(note)) {
+                    path.push(parent.clone());
+                }
+                path.reverse();
+
+                return Ok(Some(path));
+            }
+
+            for neighbor in self.neighbors(&note)? {
+                if visited.insert(neighbor.clone()) {
+                    parents.insert(neighbor.clone(), note.clone());
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+///
+/// [`Bfs`] is a breadth first walk, created by [`Traversal::bfs`].
+///
+pub struct Bfs<'a, R: ?Sized> {
+    traversal: Traversal<'a, R>,
+    queue: VecDeque<Visit>,
+    visited: HashSet<NoteId>,
+}
+
+impl<R: ReadNote + ?Sized> Iterator for Bfs<'_, R> {
+    type Item = Visit;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let visit = self.queue.pop_front()?;
+
+        for neighbor in self.traversal.neighbors(&visit.note).unwrap_or_default() {
+            if self.visited.insert(neighbor.clone()) {
+                self.queue.push_back(Visit {
+                    note: neighbor,
+                    depth: visit.depth + 1,
+                });
+            }
+        }
+
+        Some(visit)
+    }
+}
+
+///
+/// [`Dfs`] is a depth first walk, created by [`Traversal::dfs`].
+///
+pub struct Dfs<'a, R: ?Sized> {
+    traversal: Traversal<'a, R>,
+    stack: Vec<Visit>,
+    visited: HashSet<NoteId>,
+}
+
+impl<R: ReadNote + ?Sized> Iterator for Dfs<'_, R> {
+    type Item = Visit;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let visit = self.stack.pop()?;
+
+            if !self.visited.insert(visit.note.clone()) {
+                continue;
+            }
+
+            let neighbors = self.traversal.neighbors(&visit.note).unwrap_or_default();
+
+            // Pushed in reverse, so that the first link is walked first.
+            for neighbor in neighbors.into_iter().rev() {
+                if !self.visited.contains(&neighbor) {
+                    self.stack.push(Visit {
+                        note: neighbor,
+                        depth: visit.depth + 1,
+                    });
+                }
+            }
+
+            return Some(visit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager_impl::{AddBranch, AddLink, AddNote};
+    use crate::prelude::NotesManager;
+    use crate::test_util::{id, note};
+
+    ///
+    /// a -> b -> d
+    /// a -> c (branch option) -> d
+    /// d -> e
+    ///
+    fn graph() -> NotesManager {
+        let mut manager = NotesManager::default();
+        for note_id in ["a", "b", "c", "d", "e"] {
+            manager.add_note(note(note_id)).unwrap();
+        }
+        manager
+            .add_link(id("a"), id("b"), "reason".to_string())
+            .unwrap();
+        let branch = manager
+            .create_branching(id("a"), "condition".to_string())
+            .unwrap();
+        manager
+            .add_branch(id("a"), branch, id("c"), "option".to_string())
+            .unwrap();
+        manager
+            .add_link(id("b"), id("d"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("c"), id("d"), "reason".to_string())
+            .unwrap();
+        manager
+            .add_link(id("d"), id("e"), "reason".to_string())
+            .unwrap();
+        manager
+    }
+
+    fn notes(visits: impl IntoIterator<Item = Visit>) -> Vec<(NoteId, usize)> {
+        visits
+            .into_iter()
+            .map(|visit| (visit.note, visit.depth))
+            .collect()
+    }
+
+    #[test]
+    fn test_bfs_and_dfs() {
+        let manager = graph();
+        let traversal = Traversal::new(&manager);
+
+        assert_eq!(
+            notes(traversal.bfs(&id("a")).unwrap()),
+            vec![
+                (id("a"), 0),
+                (id("b"), 1),
+                (id("c"), 1),
+                (id("d"), 2),
+                (id("e"), 3)
+            ]
+        );
+        assert_eq!(
+            notes(traversal.dfs(&id("a")).unwrap()),
+            vec![
+                (id("a"), 0),
+                (id("b"), 1),
+                (id("d"), 2),
+                (id("e"), 3),
+                (id("c"), 1)
+            ]
+        );
+        assert_eq!(
+            notes(traversal.branches(false).bfs(&id("a")).unwrap()),
+            vec![(id("a"), 0), (id("b"), 1), (id("d"), 2), (id("e"), 3)]
+        );
+        assert_eq!(
+            traversal.bfs(&id("z")).err().unwrap().current_context(),
+            &ReadError::NoteDoesNotExist
+        );
+    }
+
+    #[test]
+    fn test_backward() {
+        let manager = graph();
+        let traversal = Traversal::new(&manager).direction(Direction::Backward);
+
+        assert_eq!(
+            notes(traversal.neighborhood(&id("d"), 1).unwrap()),
+            vec![(id("d"), 0), (id("b"), 1), (id("c"), 1)]
+        );
+        assert_eq!(
+            traversal.reachable(&id("c")).unwrap(),
+            vec![id("a"), id("c")]
+        );
+        assert_eq!(
+            traversal.branches(false).reachable(&id("c")).unwrap(),
+            vec![id("c")]
+        );
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let manager = graph();
+        let traversal = Traversal::new(&manager);
+
+        assert_eq!(
+            traversal.shortest_path(&id("a"), &id("e")).unwrap(),
+            Some(vec![id("a"), id("b"), id("d"), id("e")])
+        );
+        assert_eq!(
+            traversal.shortest_path(&id("a"), &id("a")).unwrap(),
+            Some(vec![id("a")])
+        );
+        assert_eq!(traversal.shortest_path(&id("e"), &id("a")).unwrap(), None);
+        assert_eq!(
+            traversal
+                .branches(false)
+                .shortest_path(&id("a"), &id("c"))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            traversal
+                .direction(Direction::Backward)
+                .shortest_path(&id("e"), &id("a"))
+                .unwrap(),
+            Some(vec![id("e"), id("d"), id("b"), id("a")])
+        );
+    }
+}
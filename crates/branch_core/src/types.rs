@@ -1,4 +1,11 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
 use nanoid::nanoid;
+use time::OffsetDateTime;
+
+use crate::errors::{ChangeError, ParseIdError, ParseTagError};
 
 ///
 /// [`NoteId`] is a unique identifier for a note.
@@ -13,11 +20,28 @@ use nanoid::nanoid;
 /// assert_ne!(id1, id2);
 /// ```
 ///
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+/// It can be printed and parsed back, which allows it to be stored outside the manager.
+///
+/// ```rust
+/// use branch_core::types::NoteId;
+///
+/// let id = NoteId::new();
+/// let parsed: NoteId = id.to_string().parse().unwrap();
+///
+/// assert_eq!(id, parsed);
+/// assert!("".parse::<NoteId>().is_err());
+/// ```
+///
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
 pub struct NoteId(String);
 
 ///
-/// [`NoteId`] is a unique identifier for a note.
+/// [`BranchId`] is a unique identifier for a branch.
 /// It is a string generated using nanoid.
 ///
 /// ```rust
@@ -30,28 +54,123 @@ pub struct NoteId(String);
 /// assert_ne!(id1, id2);
 /// ```
 ///
-#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
 pub struct BranchId(String);
 
+///
+/// [`Tag`] is a label attached to a note. Tags are hierarchical, the segments of a tag are
+/// separated by `/` and a tag is considered to be under each of its ancestors.
+///
+/// ```rust
+/// use branch_core::types::Tag;
+///
+/// let auth: Tag = "project/backend/auth".parse().unwrap();
+/// let backend: Tag = "project/backend".parse().unwrap();
+///
+/// assert!(auth.is_under(&backend));
+/// assert!(!backend.is_under(&auth));
+/// assert_eq!(auth.parent(), Some(backend));
+/// assert!("project//auth".parse::<Tag>().is_err());
+/// ```
+///
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct Tag(String);
+
 ///
 /// [`Link`] represents a link between two notes.
 /// It has a destination note id and an optional reason.
 ///
-#[derive(Clone)]
+/// A link can also have a [`Relation`], which says how the two notes relate to each other. The
+/// reason stays free text next to it. `weight` tells how strong the link is, and `annotations`
+/// hold any other key-value data about it.
+///
+/// A `bidirectional` link is one half of a pair: the note it points to links back with the same
+/// reason, weight and annotations, and with the inverse relation. The manager keeps both halves
+/// the same, and removes them together.
+///
+/// `created` and `modified` are kept up to date by the manager, `modified` changes along with
+/// the reason, the relation, the weight and the annotations.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Link {
     pub id: NoteId,
     pub reason: String,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub relation: Option<Relation>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub weight: Option<f64>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub annotations: BTreeMap<String, String>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub bidirectional: bool,
+    pub created: OffsetDateTime,
+    pub modified: OffsetDateTime,
+}
+
+///
+/// [`Relation`] is the type of a link. It is named from both sides: the note a link starts from
+/// `name`s the note it points to, which is shown as `inverse` on the side of the backlink. A
+/// relation that reads the same both ways has the same name and inverse.
+///
+/// Relations are told apart by both names, so any pair can be used as a type of its own.
+///
+/// ```rust
+/// use branch_core::types::Relation;
+///
+/// let blocks = Relation::depends_on().inverted();
+///
+/// assert_eq!(blocks.name, "blocks");
+/// assert_eq!(blocks.inverse, "depends-on");
+/// assert_eq!(Relation::symmetric("contradicts").inverse, "contradicts");
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Relation {
+    pub name: String,
+    pub inverse: String,
 }
 
 ///
 /// [`Branch`] represents a branch in a note. It has a condition and a list of branches.
 /// Each branch can be a link or another branch. This allows for a tree-like structure in a note.
 ///
-#[derive(Clone)]
+/// Branch ids are unique, so a nested branch is addressed by its own id, the same way a branch at
+/// the top of a note is. [`Note::branch_path`] gives the branches leading to it.
+///
+/// `modified` changes along with the condition and the options of the branch.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Branch {
     id: BranchId,
     pub condition: String,
-    pub branches: Vec<Link>,
+    pub branches: Vec<FLink>,
+    pub created: OffsetDateTime,
+    pub modified: OffsetDateTime,
 }
 
 ///
@@ -59,26 +178,369 @@ pub struct Branch {
 /// It can be a link or a branch. This allows for a tree-like structure in a note.
 ///
 ///
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "lowercase")
+)]
 pub enum FLink {
     Link(Link),
     Branch(Branch),
 }
 
+///
+/// [`FLinkId`] names a forward link of a note or an option of a branch: a link by the note it
+/// points to, and a branch by its id.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum FLinkId {
+    Link(NoteId),
+    Branch(BranchId),
+}
+
+///
+/// [`SortKey`] is what forward links and branch options are sorted by. `Title` uses the title of
+/// the note a link points to, and `Reason` the reason of the link, both without regard to case.
+/// Branches are sorted by their condition for both. `State` puts the ones leading to open notes
+/// first, a branch counts as closed when all the notes it leads to are in a terminal state.
+///
+/// Sorting is stable, so links that compare equal keep their order.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SortKey {
+    Title,
+    Reason,
+    State,
+}
+
 ///
 /// [`Note`] represents a note in the note-taking app.
 /// It has a unique id, a title, a subtitle, a body, a list of backlinks, and a list of forward links. backlinks are automatically generated when a note links to another note. Forward links are manually added by the user.
 ///
-#[cfg_attr(test, derive(Clone))]
+/// `modified` changes with every edit of the note, its links and branches included. Backlinks are
+/// derived from other notes, so gaining or losing one does not count as an edit.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Note {
     id: NoteId,
-    pub marked: bool,
+    pub state: TaskState,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub state_history: Vec<StateChange>,
     pub title: String,
     pub subtitle: Option<String>,
     pub body: String,
     pub backlinks: Vec<NoteId>,
     pub forwardlinks: Vec<FLink>,
-    pub timestamp: time::PrimitiveDateTime,
+    pub created: OffsetDateTime,
+    pub modified: OffsetDateTime,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tags: BTreeSet<Tag>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub revisions: Vec<NoteRevision>,
+}
+
+///
+/// [`NoteRevision`] is an earlier version of the title, subtitle and body of a note, kept when
+/// one of them is edited. `timestamp` is when the version was replaced.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoteRevision {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub body: String,
+    pub timestamp: OffsetDateTime,
+}
+
+///
+/// [`TaskState`] is the state of a note seen as a task, like `todo` or `done`. Which states exist
+/// and how a note moves between them is set by [`TaskStates`].
+///
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct TaskState(String);
+
+///
+/// [`StateChange`] is a move of a note from one state to another, kept in the state history of
+/// the note. `timestamp` is when the move happened.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateChange {
+    pub from: TaskState,
+    pub to: TaskState,
+    pub timestamp: OffsetDateTime,
+}
+
+///
+/// [`TaskStates`] is the state machine notes follow: the states they can be in, the moves allowed
+/// between them, and which states are terminal. A note that is not in a terminal state is open.
+/// New notes start in the `initial` state, and `done` is the state [`mark_note`] moves a note to.
+///
+/// When the done rule is enforced, a note can only move to `done` once every note it links to is
+/// in a terminal state.
+///
+/// The default states are `todo`, `in-progress`, `blocked`, `done` and `cancelled`, with `done`
+/// and `cancelled` terminal.
+///
+/// ```rust
+/// use branch_core::types::{TaskState, TaskStates};
+///
+/// let states = TaskStates::new(TaskState::new("open"), TaskState::new("closed"))
+///     .with_transition(TaskState::new("open"), TaskState::new("closed"))
+///     .with_done_enforced(true);
+///
+/// assert!(states.allows(&TaskState::new("open"), &TaskState::new("closed")));
+/// assert!(!states.allows(&TaskState::new("closed"), &TaskState::new("open")));
+/// assert!(states.is_terminal(&TaskState::new("closed")));
+/// assert!(TaskStates::default().is_terminal(&TaskState::cancelled()));
+/// ```
+///
+/// [`mark_note`]: crate::prelude::ChangeNote::mark_note
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaskStates {
+    initial: TaskState,
+    done: TaskState,
+    transitions: BTreeMap<TaskState, BTreeSet<TaskState>>,
+    terminal: BTreeSet<TaskState>,
+    enforce_done: bool,
+}
+
+///
+/// [`TrashId`] identifies an entry of the trash. Entries are numbered in the order they were
+/// deleted in.
+///
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct TrashId(pub(crate) u64);
+
+///
+/// [`TrashEntry`] is everything a single deletion removed. `notes` are the notes that were
+/// deleted, as they were, with their forward links. `links` are the links and branches that were
+/// removed from the notes that were kept.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashEntry {
+    pub id: TrashId,
+    pub deleted: OffsetDateTime,
+    pub notes: Vec<Note>,
+    pub links: Vec<TrashedLink>,
+}
+
+///
+/// [`TrashedLink`] is a link or a branch removed from a note that still exists. `branch` is the
+/// branching it was an option of, or `None` for a forward link of the note, and `position` is
+/// where it was in that list.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrashedLink {
+    pub note: NoteId,
+    pub branch: Option<BranchId>,
+    pub position: usize,
+    pub link: FLink,
+}
+
+///
+/// [`DeletionPolicy`] decides what happens to a note when a deletion removes the last link or
+/// branch option pointing to it. With `KeepOrphans` the note stays as a root, with
+/// `CascadeOrphans` it is deleted too, and with `RefuseOrphans` the deletion fails with
+/// [`DeleteError::WouldOrphan`].
+///
+/// [`DeleteError::WouldOrphan`]: crate::errors::DeleteError::WouldOrphan
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum DeletionPolicy {
+    KeepOrphans,
+    #[default]
+    CascadeOrphans,
+    RefuseOrphans,
+}
+
+///
+/// [`Settings`] are the options of a manager that are not part of any note. Storages that keep
+/// them hand them back on load, so a manager comes back configured the way it was saved.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Settings {
+    pub acyclic: bool,
+    pub deletion_policy: DeletionPolicy,
+    pub revision_retention: usize,
+    pub trash_retention: Option<time::Duration>,
+    pub history_depth: usize,
+    pub task_states: TaskStates,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            acyclic: false,
+            deletion_policy: DeletionPolicy::default(),
+            revision_retention: crate::manager::DEFAULT_REVISION_RETENTION,
+            trash_retention: None,
+            history_depth: crate::manager::DEFAULT_HISTORY_DEPTH,
+            task_states: TaskStates::default(),
+        }
+    }
+}
+
+///
+/// [`DeletionReport`] is what a deletion would remove: the notes, and the links and branches
+/// taken out of the notes that are kept.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeletionReport {
+    pub notes: Vec<NoteId>,
+    pub links: Vec<TrashedLink>,
+}
+
+///
+/// [`DateField`] selects one of the timestamps of a note.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    #[default]
+    Modified,
+}
+
+///
+/// [`DateQuery`] selects the notes whose timestamp falls in a range, `from` included and `to`
+/// excluded, and sorts them by one of their timestamps, oldest first.
+///
+/// ```rust
+/// use branch_core::prelude::*;
+/// use branch_core::types::{DateField, DateQuery};
+/// use time::{Duration, OffsetDateTime};
+///
+/// let mut manager = NotesManager::default();
+/// let note = manager
+///     .add_note(Note::new("title".to_string(), None, "body".to_string()))
+///     .unwrap();
+///
+/// let last_week = DateQuery::modified()
+///     .from(OffsetDateTime::now_utc() - Duration::days(7))
+///     .sorted_by(DateField::Created);
+/// assert_eq!(manager.query_notes_by_date(&last_week).unwrap()[0].get_id(), note);
+///
+/// let before_last_week = DateQuery::created().to(OffsetDateTime::now_utc() - Duration::days(7));
+/// assert!(manager.query_notes_by_date(&before_last_week).unwrap().is_empty());
+/// ```
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DateQuery {
+    pub field: DateField,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    pub sort: DateField,
+}
+
+///
+/// [`DiffLine`] is a line of a line-level diff, going from the older text to the newer one.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffLine {
+    Unchanged(String),
+    Removed(String),
+    Added(String),
+}
+
+///
+/// [`RevisionDiff`] holds the line-level diff of each field between two revisions of a note.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RevisionDiff {
+    pub title: Vec<DiffLine>,
+    pub subtitle: Vec<DiffLine>,
+    pub body: Vec<DiffLine>,
+}
+
+///
+/// [`ReconsileFix`] is a single repair performed by [`reconsile_nodes`].
+///
+/// [`reconsile_nodes`]: crate::prelude::ChangeNote::reconsile_nodes
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReconsileFix {
+    /// A forward link pointed to a note that does not exist.
+    DroppedLink { note: NoteId, target: NoteId },
+    /// A branch option pointed to a note that does not exist.
+    DroppedBranchOption {
+        note: NoteId,
+        branch: BranchId,
+        target: NoteId,
+    },
+    /// A branch without any options was removed.
+    RemovedEmptyBranch { note: NoteId, branch: BranchId },
+    /// The other half of a bidirectional link was missing, and was added back to `note`.
+    PairedLink { note: NoteId, target: NoteId },
+    /// The backlinks of a note did not match the forward links pointing to it.
+    RebuiltBacklinks {
+        note: NoteId,
+        added: Vec<NoteId>,
+        removed: Vec<NoteId>,
+    },
+    /// A note lost all of its backlinks and was removed.
+    RemovedOrphan { note: NoteId },
+    /// All the notes a note links to were in a terminal state, so the note was moved to done.
+    CompletedNote { note: NoteId },
+}
+
+///
+/// [`ReconsileReport`] lists every fix made by a reconsile pass, in the order they were applied.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReconsileReport {
+    pub fixes: Vec<ReconsileFix>,
+}
+
+impl ReconsileReport {
+    pub fn is_empty(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+///
+/// [`SearchHit`] is a note matching a search query. A higher score means a better match, scores
+/// are only meaningful within the results of a single query.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    pub note: NoteId,
+    pub score: f64,
 }
 
 impl NoteId {
@@ -91,6 +553,10 @@ impl NoteId {
     pub fn new_test(id: String) -> Self {
         NoteId(id)
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl BranchId {
@@ -103,11 +569,194 @@ impl BranchId {
     pub fn new_test(id: String) -> Self {
         BranchId(id)
     }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+///
+/// [`validate_id`] checks that an id is non-empty and only uses the characters of the nanoid
+/// alphabet.
+///
+fn validate_id(id: &str) -> Result<(), ParseIdError> {
+    if id.is_empty() {
+        return Err(ParseIdError::Empty);
+    }
+
+    match id
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        Some(c) => Err(ParseIdError::InvalidCharacter(c)),
+        None => Ok(()),
+    }
+}
+
+impl FromStr for NoteId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_id(s)?;
+        Ok(NoteId(s.to_string()))
+    }
+}
+
+impl FromStr for BranchId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_id(s)?;
+        Ok(BranchId(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for NoteId {
+    type Error = ParseIdError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate_id(&value)?;
+        Ok(NoteId(value))
+    }
+}
+
+impl TryFrom<String> for BranchId {
+    type Error = ParseIdError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate_id(&value)?;
+        Ok(BranchId(value))
+    }
+}
+
+impl From<NoteId> for String {
+    fn from(value: NoteId) -> Self {
+        value.0
+    }
+}
+
+impl From<BranchId> for String {
+    fn from(value: BranchId) -> Self {
+        value.0
+    }
+}
+
+impl Tag {
+    pub const SEPARATOR: char = '/';
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split(Self::SEPARATOR)
+    }
+
+    pub fn parent(&self) -> Option<Tag> {
+        self.0
+            .rsplit_once(Self::SEPARATOR)
+            .map(|(parent, _)| Tag(parent.to_string()))
+    }
+
+    ///
+    /// [`is_under`] checks whether the tag is `ancestor` itself or one of its descendants.
+    ///
+    pub fn is_under(&self, ancestor: &Tag) -> bool {
+        match self.0.strip_prefix(ancestor.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with(Self::SEPARATOR),
+            None => false,
+        }
+    }
+
+    ///
+    /// [`rebase`] moves a tag under `from` to the same place under `to`, returning `None` if the
+    /// tag is not under `from`.
+    ///
+    pub fn rebase(&self, from: &Tag, to: &Tag) -> Option<Tag> {
+        self.is_under(from)
+            .then(|| Tag(format!("{}{}", to.0, &self.0[from.0.len()..])))
+    }
+}
+
+fn validate_tag(tag: &str) -> Result<(), ParseTagError> {
+    if tag.is_empty() {
+        return Err(ParseTagError::Empty);
+    }
+
+    for segment in tag.split(Tag::SEPARATOR) {
+        if segment.is_empty() {
+            return Err(ParseTagError::EmptySegment);
+        }
+
+        if let Some(c) = segment
+            .chars()
+            .find(|c| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+        {
+            return Err(ParseTagError::InvalidCharacter(c));
+        }
+    }
+
+    Ok(())
+}
+
+impl FromStr for Tag {
+    type Err = ParseTagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_tag(s)?;
+        Ok(Tag(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for Tag {
+    type Error = ParseTagError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        validate_tag(&value)?;
+        Ok(Tag(value))
+    }
+}
+
+impl From<Tag> for String {
+    fn from(value: Tag) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for NoteId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for BranchId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for TrashId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 impl Note {
     pub fn new(title: String, subtitle: Option<String>, body: String) -> Self {
-        let now_odt = time::OffsetDateTime::now_utc();
+        let timestamp = now();
+
         Note {
             id: NoteId::new(),
             title,
@@ -115,14 +764,26 @@ impl Note {
             body,
             backlinks: Vec::new(),
             forwardlinks: Vec::new(),
-            timestamp: time::PrimitiveDateTime::new(now_odt.date(), now_odt.time()),
-            marked: false,
+            created: timestamp,
+            modified: timestamp,
+            state: TaskState::default(),
+            state_history: Vec::new(),
+            tags: BTreeSet::new(),
+            revisions: Vec::new(),
         }
     }
     pub fn get_id(&self) -> NoteId {
         self.id.clone()
     }
 
+    ///
+    /// [`with_id`] replaces the generated id of the note, this is used when a note is loaded back
+    /// from a storage.
+    ///
+    pub fn with_id(self, id: NoteId) -> Self {
+        Note { id, ..self }
+    }
+
     pub fn add_backlink(&mut self, id: NoteId) {
         self.backlinks.push(id);
     }
@@ -132,6 +793,191 @@ impl Note {
         self.backlinks.len()
     }
 
+    ///
+    /// [`delete_backlink_once`] removes a single backlink from `id`, for when `id` still links to
+    /// the note in some other way.
+    ///
+    pub fn delete_backlink_once(&mut self, id: &NoteId) -> usize {
+        if let Some(position) = self.backlinks.iter().position(|x| x == id) {
+            self.backlinks.remove(position);
+        }
+        self.backlinks.len()
+    }
+
+    ///
+    /// [`link`] finds the direct link of the note to another note, branch options are not
+    /// included.
+    ///
+    pub fn link(&self, to: &NoteId) -> Option<&Link> {
+        self.forwardlinks.iter().find_map(|flink| match flink {
+            FLink::Link(link) if &link.id == to => Some(link),
+            _ => None,
+        })
+    }
+
+    pub fn link_mut(&mut self, to: &NoteId) -> Option<&mut Link> {
+        self.forwardlinks.iter_mut().find_map(|flink| match flink {
+            FLink::Link(link) if &link.id == to => Some(link),
+            _ => None,
+        })
+    }
+
+    ///
+    /// [`remove_link`] takes the direct link of the note to another note out of its forward
+    /// links. The backlink of the other note is left as it is.
+    ///
+    pub fn remove_link(&mut self, to: &NoteId) -> Option<Link> {
+        let position = self
+            .forwardlinks
+            .iter()
+            .position(|flink| matches!(flink, FLink::Link(link) if &link.id == to))?;
+
+        match self.forwardlinks.remove(position) {
+            FLink::Link(link) => Some(link),
+            FLink::Branch(_) => None,
+        }
+    }
+
+    ///
+    /// [`branch`] finds a branch of the note by id, at any depth.
+    ///
+    pub fn branch(&self, id: &BranchId) -> Option<&Branch> {
+        self.branches().find_map(|branch| branch.find(id))
+    }
+
+    pub fn branch_mut(&mut self, id: &BranchId) -> Option<&mut Branch> {
+        self.forwardlinks.iter_mut().find_map(|flink| match flink {
+            FLink::Branch(branch) => branch.find_mut(id),
+            FLink::Link(_) => None,
+        })
+    }
+
+    ///
+    /// [`branch_path`] lists the ids of the branches leading to a branch, starting with the branch
+    /// at the top of the note and ending with the branch itself.
+    ///
+    pub fn branch_path(&self, id: &BranchId) -> Option<Vec<BranchId>> {
+        self.branches().find_map(|branch| branch.path_to(id))
+    }
+
+    ///
+    /// [`branch_siblings_mut`] returns the list holding a branch, the forward links of the note
+    /// for a branch at the top, the options of its parent for a nested branch.
+    ///
+    pub fn branch_siblings_mut(&mut self, id: &BranchId) -> Option<&mut Vec<FLink>> {
+        let path = self.branch_path(id)?;
+
+        match path.len() {
+            1 => Some(&mut self.forwardlinks),
+            length => self
+                .branch_mut(&path[length - 2])
+                .map(|parent| &mut parent.branches),
+        }
+    }
+
+    fn branches(&self) -> impl Iterator<Item = &Branch> {
+        self.forwardlinks.iter().filter_map(|flink| match flink {
+            FLink::Branch(branch) => Some(branch),
+            FLink::Link(_) => None,
+        })
+    }
+
+    pub fn date(&self, field: DateField) -> OffsetDateTime {
+        match field {
+            DateField::Created => self.created,
+            DateField::Modified => self.modified,
+        }
+    }
+
+    ///
+    /// [`stamp`] compares the note with an earlier state of it, and sets the time of the change as
+    /// `modified` on the note and on every link and branch that changed. Links and branches that
+    /// did not exist before are created at that time.
+    ///
+    pub(crate) fn stamp(&mut self, before: &Note, at: OffsetDateTime) {
+        stamp_options(&mut self.forwardlinks, &before.forwardlinks, at);
+
+        if self.title != before.title
+            || self.subtitle != before.subtitle
+            || self.body != before.body
+            || self.state != before.state
+            || self.state_history != before.state_history
+            || self.forwardlinks != before.forwardlinks
+            || self.tags != before.tags
+            || self.revisions != before.revisions
+        {
+            self.modified = at;
+        }
+    }
+
+    ///
+    /// [`snapshot`] captures the current title, subtitle and body as a revision, to be kept when
+    /// they are edited at the given time.
+    ///
+    pub fn snapshot(&self, at: OffsetDateTime) -> NoteRevision {
+        NoteRevision {
+            title: self.title.clone(),
+            subtitle: self.subtitle.clone(),
+            body: self.body.clone(),
+            timestamp: at,
+        }
+    }
+
+    ///
+    /// [`change_state`] moves the note to another state at the given time, and keeps the move in
+    /// its state history.
+    ///
+    pub fn change_state(&mut self, state: TaskState, at: OffsetDateTime) {
+        let from = std::mem::replace(&mut self.state, state.clone());
+
+        self.state_history.push(StateChange {
+            from,
+            to: state,
+            timestamp: at,
+        });
+    }
+
+    ///
+    /// [`open_forwardlinks`] lists the forward links that still lead to an open note. A link is
+    /// included if its target is open, a branch is included if any of its options is.
+    ///
+    pub fn open_forwardlinks(&self, is_open: impl Fn(&NoteId) -> bool) -> Vec<&FLink> {
+        self.forwardlinks
+            .iter()
+            .filter(|flink| match flink {
+                FLink::Link(link) => is_open(&link.id),
+                FLink::Branch(branch) => branch.targets().iter().any(|link| is_open(&link.id)),
+            })
+            .collect()
+    }
+
+    ///
+    /// [`trim_revisions`] drops the oldest revisions, keeping at most `retention` of them.
+    ///
+    pub fn trim_revisions(&mut self, retention: usize) {
+        let excess = self.revisions.len().saturating_sub(retention);
+        self.revisions.drain(..excess);
+    }
+
+    ///
+    /// [`revision_content`] returns the title, subtitle and body of a revision. Revisions are
+    /// numbered from the oldest one kept, the number after the last revision stands for the
+    /// current content.
+    ///
+    pub fn revision_content(&self, revision: usize) -> Option<(&str, Option<&str>, &str)> {
+        match self.revisions.get(revision) {
+            Some(revision) => Some((
+                &revision.title,
+                revision.subtitle.as_deref(),
+                &revision.body,
+            )),
+            None if revision == self.revisions.len() => {
+                Some((&self.title, self.subtitle.as_deref(), &self.body))
+            }
+            None => None,
+        }
+    }
+
     #[cfg(test)]
     pub fn set_id(self, id: String) -> Self {
         Note {
@@ -141,15 +987,587 @@ impl Note {
     }
 }
 
+pub(crate) fn now() -> OffsetDateTime {
+    OffsetDateTime::now_utc()
+}
+
+impl Link {
+    pub fn new(id: NoteId, reason: String) -> Self {
+        let timestamp = now();
+
+        Link {
+            id,
+            reason,
+            relation: None,
+            weight: None,
+            annotations: BTreeMap::new(),
+            bidirectional: false,
+            created: timestamp,
+            modified: timestamp,
+        }
+    }
+
+    pub fn with_relation(self, relation: Relation) -> Self {
+        Link {
+            relation: Some(relation),
+            ..self
+        }
+    }
+
+    pub fn has_relation(&self, relation: &Relation) -> bool {
+        self.relation.as_ref() == Some(relation)
+    }
+
+    ///
+    /// [`mirrored`] is the other half of a bidirectional link that starts from `from`: it points
+    /// back to `from` with the inverse relation, and shares everything else.
+    ///
+    pub fn mirrored(&self, from: NoteId) -> Self {
+        Link {
+            id: from,
+            relation: self.relation.as_ref().map(Relation::inverted),
+            bidirectional: true,
+            ..self.clone()
+        }
+    }
+
+    ///
+    /// [`is_valid_weight`] checks that a weight is a finite number.
+    ///
+    pub fn is_valid_weight(weight: Option<f64>) -> bool {
+        weight.is_none_or(f64::is_finite)
+    }
+
+    ///
+    /// [`is_valid_annotation`] checks that an annotation key is set, and that it does not start
+    /// or end with spaces.
+    ///
+    pub fn is_valid_annotation(key: &str) -> bool {
+        !key.is_empty() && key.trim() == key
+    }
+}
+
+impl Relation {
+    pub fn new(name: impl Into<String>, inverse: impl Into<String>) -> Self {
+        Relation {
+            name: name.into(),
+            inverse: inverse.into(),
+        }
+    }
+
+    pub fn symmetric(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Relation::new(name.clone(), name)
+    }
+
+    pub fn supports() -> Self {
+        Relation::new("supports", "supported-by")
+    }
+
+    pub fn depends_on() -> Self {
+        Relation::new("depends-on", "blocks")
+    }
+
+    pub fn refines() -> Self {
+        Relation::new("refines", "refined-by")
+    }
+
+    ///
+    /// [`inverted`] is the same relation seen from the other side of the link.
+    ///
+    pub fn inverted(&self) -> Self {
+        Relation::new(self.inverse.clone(), self.name.clone())
+    }
+
+    ///
+    /// [`is_valid`] checks that both names are set, and that they do not start or end with
+    /// spaces.
+    ///
+    pub fn is_valid(&self) -> bool {
+        [&self.name, &self.inverse]
+            .iter()
+            .all(|name| !name.is_empty() && name.trim() == name.as_str())
+    }
+}
+
+impl TaskState {
+    pub fn new(name: impl Into<String>) -> Self {
+        TaskState(name.into())
+    }
+
+    pub fn todo() -> Self {
+        TaskState::new("todo")
+    }
+
+    pub fn in_progress() -> Self {
+        TaskState::new("in-progress")
+    }
+
+    pub fn blocked() -> Self {
+        TaskState::new("blocked")
+    }
+
+    pub fn done() -> Self {
+        TaskState::new("done")
+    }
+
+    pub fn cancelled() -> Self {
+        TaskState::new("cancelled")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for TaskState {
+    fn default() -> Self {
+        TaskState::todo()
+    }
+}
+
+impl TaskStates {
+    ///
+    /// [`new`] creates a state machine with only its `initial` and `done` states, and no moves
+    /// between them. `done` is terminal.
+    ///
+    pub fn new(initial: TaskState, done: TaskState) -> Self {
+        TaskStates {
+            transitions: BTreeMap::from([
+                (initial.clone(), BTreeSet::new()),
+                (done.clone(), BTreeSet::new()),
+            ]),
+            terminal: BTreeSet::from([done.clone()]),
+            initial,
+            done,
+            enforce_done: false,
+        }
+    }
+
+    pub fn with_transition(mut self, from: TaskState, to: TaskState) -> Self {
+        self.transitions.entry(to.clone()).or_default();
+        self.transitions.entry(from).or_default().insert(to);
+        self
+    }
+
+    pub fn with_terminal(mut self, state: TaskState) -> Self {
+        self.transitions.entry(state.clone()).or_default();
+        self.terminal.insert(state);
+        self
+    }
+
+    pub fn with_done_enforced(self, enforce_done: bool) -> Self {
+        TaskStates {
+            enforce_done,
+            ..self
+        }
+    }
+
+    pub fn initial(&self) -> &TaskState {
+        &self.initial
+    }
+
+    pub fn done(&self) -> &TaskState {
+        &self.done
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = &TaskState> {
+        self.transitions.keys()
+    }
+
+    pub fn contains(&self, state: &TaskState) -> bool {
+        self.transitions.contains_key(state)
+    }
+
+    pub fn is_terminal(&self, state: &TaskState) -> bool {
+        self.terminal.contains(state)
+    }
+
+    pub fn is_done_enforced(&self) -> bool {
+        self.enforce_done
+    }
+
+    ///
+    /// [`allows`] checks whether a note can move from one state to another. A note left in a
+    /// state that is no longer configured can only go back to the initial state.
+    ///
+    pub fn allows(&self, from: &TaskState, to: &TaskState) -> bool {
+        match self.transitions.get(from) {
+            Some(next) => next.contains(to),
+            None => to == &self.initial,
+        }
+    }
+
+    ///
+    /// [`check_transition`] is [`allows`] as an error, telling an unknown state apart from a move
+    /// that is not allowed.
+    ///
+    pub(crate) fn check_transition(
+        &self,
+        from: &TaskState,
+        to: &TaskState,
+    ) -> Result<(), ChangeError> {
+        if !self.contains(to) {
+            return Err(ChangeError::UnknownState(to.clone()));
+        }
+
+        if !self.allows(from, to) {
+            return Err(ChangeError::InvalidTransition {
+                from: from.clone(),
+                to: to.clone(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for TaskStates {
+    fn default() -> Self {
+        let mut states = TaskStates::new(TaskState::todo(), TaskState::done())
+            .with_terminal(TaskState::cancelled());
+
+        // A note can be picked up, blocked or closed while it is open, and reopened once closed.
+        let moves = [
+            (
+                TaskState::todo(),
+                vec![
+                    TaskState::in_progress(),
+                    TaskState::blocked(),
+                    TaskState::done(),
+                    TaskState::cancelled(),
+                ],
+            ),
+            (
+                TaskState::in_progress(),
+                vec![
+                    TaskState::todo(),
+                    TaskState::blocked(),
+                    TaskState::done(),
+                    TaskState::cancelled(),
+                ],
+            ),
+            (
+                TaskState::blocked(),
+                vec![
+                    TaskState::todo(),
+                    TaskState::in_progress(),
+                    TaskState::cancelled(),
+                ],
+            ),
+            (TaskState::done(), vec![TaskState::todo()]),
+            (TaskState::cancelled(), vec![TaskState::todo()]),
+        ];
+
+        for (from, targets) in moves {
+            for to in targets {
+                states = states.with_transition(from.clone(), to);
+            }
+        }
+
+        states
+    }
+}
+
+impl DateQuery {
+    pub fn created() -> Self {
+        DateQuery {
+            field: DateField::Created,
+            sort: DateField::Created,
+            ..DateQuery::default()
+        }
+    }
+
+    pub fn modified() -> Self {
+        DateQuery::default()
+    }
+
+    pub fn from(self, from: OffsetDateTime) -> Self {
+        DateQuery {
+            from: Some(from),
+            ..self
+        }
+    }
+
+    pub fn to(self, to: OffsetDateTime) -> Self {
+        DateQuery {
+            to: Some(to),
+            ..self
+        }
+    }
+
+    pub fn sorted_by(self, sort: DateField) -> Self {
+        DateQuery { sort, ..self }
+    }
+
+    pub fn matches(&self, note: &Note) -> bool {
+        let date = note.date(self.field);
+
+        self.from.is_none_or(|from| from <= date) && self.to.is_none_or(|to| date < to)
+    }
+}
+
 impl Branch {
     pub fn new(condition: String) -> Self {
+        let timestamp = now();
+
         Branch {
             id: BranchId::new(),
             condition,
             branches: Vec::new(),
+            created: timestamp,
+            modified: timestamp,
         }
     }
     pub fn get_id(&self) -> BranchId {
         self.id.clone()
     }
+
+    pub fn with_id(self, id: BranchId) -> Self {
+        Branch { id, ..self }
+    }
+
+    pub fn id(&self) -> &BranchId {
+        &self.id
+    }
+
+    ///
+    /// [`links`] lists the options of the branch that lead to a note, nested branches are skipped.
+    ///
+    pub fn links(&self) -> impl Iterator<Item = &Link> {
+        self.branches.iter().filter_map(|option| match option {
+            FLink::Link(link) => Some(link),
+            FLink::Branch(_) => None,
+        })
+    }
+
+    pub fn sub_branches(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.iter().filter_map(|option| match option {
+            FLink::Branch(branch) => Some(branch),
+            FLink::Link(_) => None,
+        })
+    }
+
+    ///
+    /// [`walk`] lists the branch followed by every branch nested in it, depth first.
+    ///
+    pub fn walk(&self) -> Vec<&Branch> {
+        let mut branches = vec![self];
+        for branch in self.sub_branches() {
+            branches.extend(branch.walk());
+        }
+        branches
+    }
+
+    ///
+    /// [`targets`] lists the links of the branch and of every branch nested in it, depth first.
+    ///
+    pub fn targets(&self) -> Vec<&Link> {
+        self.branches
+            .iter()
+            .flat_map(|option| match option {
+                FLink::Link(link) => vec![link],
+                FLink::Branch(branch) => branch.targets(),
+            })
+            .collect()
+    }
+
+    ///
+    /// [`find`] finds the branch itself or one of the branches nested in it.
+    ///
+    pub fn find(&self, id: &BranchId) -> Option<&Branch> {
+        if &self.id == id {
+            return Some(self);
+        }
+
+        self.sub_branches().find_map(|branch| branch.find(id))
+    }
+
+    pub fn find_mut(&mut self, id: &BranchId) -> Option<&mut Branch> {
+        if &self.id == id {
+            return Some(self);
+        }
+
+        self.branches.iter_mut().find_map(|option| match option {
+            FLink::Branch(branch) => branch.find_mut(id),
+            FLink::Link(_) => None,
+        })
+    }
+
+    ///
+    /// [`path_to`] lists the ids of the branches from this branch down to the nested branch, both
+    /// included.
+    ///
+    pub fn path_to(&self, id: &BranchId) -> Option<Vec<BranchId>> {
+        if &self.id == id {
+            return Some(vec![self.get_id()]);
+        }
+
+        self.sub_branches().find_map(|branch| {
+            let mut path = branch.path_to(id)?;
+            path.insert(0, self.get_id());
+            Some(path)
+        })
+    }
+}
+
+///
+/// [`stamp_options`] stamps the links and branches of a note, or the options of a branch, against
+/// their earlier state. Links are matched by their target and branches by their id.
+///
+fn stamp_options(options: &mut [FLink], before: &[FLink], at: OffsetDateTime) {
+    for option in options {
+        match option {
+            FLink::Link(link) => {
+                let previous = before.iter().find_map(|previous| match previous {
+                    FLink::Link(previous) if previous.id == link.id => Some(previous),
+                    _ => None,
+                });
+
+                match previous {
+                    Some(previous)
+                        if previous.reason != link.reason
+                            || previous.relation != link.relation
+                            || previous.weight != link.weight
+                            || previous.annotations != link.annotations
+                            || previous.bidirectional != link.bidirectional =>
+                    {
+                        link.modified = at
+                    }
+                    Some(_) => {}
+                    None => {
+                        link.created = at;
+                        link.modified = at;
+                    }
+                }
+            }
+            FLink::Branch(branch) => {
+                let previous = before.iter().find_map(|previous| match previous {
+                    FLink::Branch(previous) if previous.id == branch.id => Some(previous),
+                    _ => None,
+                });
+
+                match previous {
+                    Some(previous) => {
+                        stamp_options(&mut branch.branches, &previous.branches, at);
+
+                        if previous.condition != branch.condition
+                            || previous.branches != branch.branches
+                        {
+                            branch.modified = at;
+                        }
+                    }
+                    None => {
+                        stamp_options(&mut branch.branches, &[], at);
+                        branch.created = at;
+                        branch.modified = at;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FLink {
+    ///
+    /// [`is_branch`] is true if the forward link is the branch with the given id.
+    ///
+    pub fn is_branch(&self, id: &BranchId) -> bool {
+        matches!(self, FLink::Branch(branch) if branch.id() == id)
+    }
+
+    ///
+    /// [`targets`] lists the links the forward link leads to, the options of a branch included.
+    ///
+    pub fn targets(&self) -> Vec<&Link> {
+        match self {
+            FLink::Link(link) => vec![link],
+            FLink::Branch(branch) => branch.targets(),
+        }
+    }
+
+    pub fn flink_id(&self) -> FLinkId {
+        match self {
+            FLink::Link(link) => FLinkId::Link(link.id.clone()),
+            FLink::Branch(branch) => FLinkId::Branch(branch.get_id()),
+        }
+    }
+}
+
+///
+/// [`move_option`] moves a forward link or branch option to `position`, counted once it was
+/// taken out of the list.
+///
+pub(crate) fn move_option(
+    options: &mut Vec<FLink>,
+    item: &FLinkId,
+    position: usize,
+) -> Result<(), ChangeError> {
+    let from = find_option(options, item)?;
+
+    if position >= options.len() {
+        return Err(ChangeError::InvalidPosition);
+    }
+
+    let option = options.remove(from);
+    options.insert(position, option);
+
+    Ok(())
+}
+
+///
+/// [`swap_options`] swaps two forward links or branch options.
+///
+pub(crate) fn swap_options(
+    options: &mut [FLink],
+    first: &FLinkId,
+    second: &FLinkId,
+) -> Result<(), ChangeError> {
+    let first = find_option(options, first)?;
+    let second = find_option(options, second)?;
+
+    options.swap(first, second);
+
+    Ok(())
+}
+
+///
+/// [`sort_options`] sorts forward links or branch options. `note` gives the title of a note and
+/// whether it is in a terminal state.
+///
+pub(crate) fn sort_options(
+    options: &mut [FLink],
+    key: SortKey,
+    note: impl Fn(&NoteId) -> Option<(String, bool)>,
+) {
+    let text = |option: &FLink| match option {
+        FLink::Link(link) if key == SortKey::Title => {
+            note(&link.id).map(|(title, _)| title).unwrap_or_default()
+        }
+        FLink::Link(link) => link.reason.clone(),
+        FLink::Branch(branch) => branch.condition.clone(),
+    };
+
+    let closed = |option: &FLink| {
+        let targets = option.targets();
+        !targets.is_empty()
+            && targets
+                .iter()
+                .all(|link| note(&link.id).is_some_and(|(_, closed)| closed))
+    };
+
+    options.sort_by_cached_key(|option| match key {
+        SortKey::Title | SortKey::Reason => (false, text(option).to_lowercase()),
+        SortKey::State => (closed(option), String::new()),
+    });
+}
+
+fn find_option(options: &[FLink], item: &FLinkId) -> Result<usize, ChangeError> {
+    options
+        .iter()
+        .position(|option| &option.flink_id() == item)
+        .ok_or(match item {
+            FLinkId::Link(_) => ChangeError::LinkDoesNotExist,
+            FLinkId::Branch(_) => ChangeError::BranchDoesNotExist,
+        })
 }
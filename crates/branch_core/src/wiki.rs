@@ -0,0 +1,237 @@
+//!
+//! The wiki module finds references to other notes in the body of a note. A reference names a
+//! note by its id or by its title, and can carry a label that is shown in its place.
+//!
+//! ```text
+//! The API is described in [[Architecture]], see [[V1StGXR8_Z5jdHi6B-myT|the endpoints]].
+//! ```
+//!
+//! Changing the body of a note through the manager, or restoring one of its revisions, links the
+//! note to every note its body refers to, and removes the links whose reference was taken out. A
+//! link created this way carries the sentence the reference appears in as its reason, and the
+//! [`ANNOTATION`] marker. Links added by hand are left alone.
+//!
+//! ```rust
+//! use branch_core::prelude::*;
+//!
+//! let mut manager = NotesManager::default();
+//! let api = manager
+//!     .add_note(Note::new("api".to_string(), None, String::new()))
+//!     .unwrap();
+//! let design = manager
+//!     .add_note(Note::new("Architecture".to_string(), None, String::new()))
+//!     .unwrap();
+//!
+//! manager
+//!     .change_note_body(
+//!         api.clone(),
+//!         "The API follows [[Architecture]]. It is versioned in [[Roadmap]].".to_string(),
+//!     )
+//!     .unwrap();
+//!
+//! let links = manager.list_pure_links(api.clone()).unwrap();
+//! assert_eq!(links[0].id, design);
+//! assert_eq!(links[0].reason, "The API follows Architecture.");
+//!
+//! let unresolved = manager.list_unresolved_references(api).unwrap();
+//! assert_eq!(unresolved[0].reference.target, "Roadmap");
+//! ```
+//!
+
+use std::ops::Range;
+
+use crate::types::NoteId;
+
+///
+/// [`ANNOTATION`] marks a link created from a reference. Its value is the target of the reference,
+/// as written in the body.
+///
+pub const ANNOTATION: &str = "wiki";
+
+const OPEN: &str = "[[";
+const CLOSE: &str = "]]";
+const SEPARATOR: char = '|';
+
+///
+/// [`Reference`] is a `[[target]]` or `[[target|label]]` found in a body. `span` is where it is
+/// in the body, and `sentence` is the sentence around it, with every reference replaced by its
+/// text.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub target: String,
+    pub label: Option<String>,
+    pub sentence: String,
+    pub span: Range<usize>,
+}
+
+impl Reference {
+    ///
+    /// [`text`] is what the reference reads as, its label or else its target.
+    ///
+    pub fn text(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.target)
+    }
+}
+
+///
+/// [`Problem`] is why a reference is not linked.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// No note has the id or the title of the reference.
+    NoteDoesNotExist,
+    /// More than one note has the title of the reference.
+    AmbiguousTitle(Vec<NoteId>),
+    /// The link would close this cycle, and the manager is in strict mode.
+    CycleDetected(Vec<NoteId>),
+}
+
+///
+/// [`UnresolvedReference`] is a reference that could not be linked, along with the reason.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnresolvedReference {
+    pub reference: Reference,
+    pub problem: Problem,
+}
+
+///
+/// [`parse`] lists the references in a body, in the order they appear. A reference can not span
+/// several lines, and a reference without a target is ignored.
+///
+pub fn parse(body: &str) -> Vec<Reference> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = body[offset..].find(OPEN).map(|start| offset + start) {
+        let inner = start + OPEN.len();
+
+        let Some(end) = body[inner..].find(CLOSE).map(|end| inner + end) else {
+            break;
+        };
+
+        let content = &body[inner..end];
+
+        // An unclosed reference on the line is skipped, the next `[[` may start a real one.
+        if content.contains('\n') || content.contains(OPEN) {
+            offset = inner;
+            continue;
+        }
+
+        let (target, label) = match content.split_once(SEPARATOR) {
+            Some((target, label)) => (target.trim(), Some(label.trim())),
+            None => (content.trim(), None),
+        };
+
+        if !target.is_empty() {
+            found.push((
+                start..end + CLOSE.len(),
+                target.to_string(),
+                label.filter(|label| !label.is_empty()).map(str::to_string),
+            ));
+        }
+
+        offset = end + CLOSE.len();
+    }
+
+    let spans = found
+        .iter()
+        .map(|(span, _, _)| span.clone())
+        .collect::<Vec<_>>();
+
+    found
+        .iter()
+        .map(|(span, target, label)| Reference {
+            target: target.clone(),
+            label: label.clone(),
+            sentence: sentence(body, span, &spans, &found),
+            span: span.clone(),
+        })
+        .collect()
+}
+
+///
+/// [`sentence`] extracts the sentence around a reference. Sentences end with `.`, `!` or `?`
+/// followed by a space, or at the end of a line. The punctuation of the references themselves is
+/// not taken into account.
+///
+fn sentence(
+    body: &str,
+    span: &Range<usize>,
+    spans: &[Range<usize>],
+    references: &[(Range<usize>, String, Option<String>)],
+) -> String {
+    let outside = |index: usize| !spans.iter().any(|span| span.contains(&index));
+
+    let is_end = |index: usize, c: char| {
+        outside(index)
+            && (c == '\n'
+                || (matches!(c, '.' | '!' | '?')
+                    && body[index + c.len_utf8()..]
+                        .chars()
+                        .next()
+                        .is_none_or(char::is_whitespace)))
+    };
+
+    let start = body[..span.start]
+        .char_indices()
+        .rev()
+        .find(|&(index, c)| is_end(index, c))
+        .map(|(index, c)| index + c.len_utf8())
+        .unwrap_or(0);
+
+    let end = body[span.end..]
+        .char_indices()
+        .map(|(index, c)| (span.end + index, c))
+        .find(|&(index, c)| is_end(index, c))
+        .map(|(index, c)| if c == '\n' { index } else { index + 1 })
+        .unwrap_or(body.len());
+
+    let mut text = String::new();
+    let mut position = start;
+
+    for (span, target, label) in references {
+        if span.start < start || span.end > end {
+            continue;
+        }
+
+        text.push_str(&body[position..span.start]);
+        text.push_str(label.as_deref().unwrap_or(target));
+        position = span.end;
+    }
+
+    text.push_str(&body[position..end]);
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let body = "Intro line\nSee [[Design|the design]] and [[a1]]. Not [[ closed\nv1.2 is \
+                    out! [[]] Read [[Release v1.2]] first";
+
+        let references = parse(body);
+
+        assert_eq!(
+            references
+                .iter()
+                .map(|reference| (reference.target.as_str(), reference.label.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Design", Some("the design")),
+                ("a1", None),
+                ("Release v1.2", None)
+            ]
+        );
+        assert_eq!(references[0].sentence, "See the design and a1.");
+        assert_eq!(references[1].sentence, "See the design and a1.");
+        assert_eq!(references[2].sentence, "[[]] Read Release v1.2 first");
+        assert_eq!(&body[references[1].span.clone()], "[[a1]]");
+        assert_eq!(references[0].text(), "the design");
+    }
+}